@@ -0,0 +1,486 @@
+//! Keyframe animation of glTF nodes, skins and morph targets
+//!
+//! Only the first animation of a file is played, looping. Sampling happens on
+//! the CPU, producing joint matrices and morph weights that the vertex
+//! shaders apply.
+use cgmath::prelude::*;
+use cgmath::{Matrix4, Quaternion, Vector3};
+
+/// Must match `MAX_JOINTS` in deform.glsl
+pub const MAX_JOINTS: usize = 64;
+/// Must match `MAX_MORPH_TARGETS` in deform.glsl
+pub const MAX_MORPH_TARGETS: usize = 4;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+enum Interpolation {
+    Linear,
+    Step,
+    /// Hermite spline, with in and out tangents stored around each value
+    CubicSpline,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+enum Property {
+    Translation,
+    Rotation,
+    Scale,
+    MorphWeights,
+}
+
+/// Keyframes driving one property of one node
+struct Channel {
+    node: usize,
+    property: Property,
+    interpolation: Interpolation,
+    times: Vec<f32>,
+    /// Keyframe values, `components` floats each
+    values: Vec<f32>,
+    components: usize,
+}
+
+impl Channel {
+    /// The `components` floats of keyframe `key`
+    fn value(&self, key: usize) -> &[f32] {
+        let index = match self.interpolation {
+            Interpolation::CubicSpline => key * 3 + 1,
+            _ => key,
+        };
+        &self.values[index * self.components..(index + 1) * self.components]
+    }
+
+    /// In or out tangent of a cubic spline keyframe
+    fn tangent(&self, key: usize, out: bool) -> &[f32] {
+        let index = key * 3 + if out { 2 } else { 0 };
+        &self.values[index * self.components..(index + 1) * self.components]
+    }
+
+    /// The value at `time`, held constant before the first and after the
+    /// last keyframe
+    fn sample(&self, time: f32) -> Vec<f32> {
+        let last = self.times.len() - 1;
+        if time <= self.times[0] {
+            return self.value(0).to_vec();
+        }
+        if time >= self.times[last] {
+            return self.value(last).to_vec();
+        }
+
+        let next = match self
+            .times
+            .binary_search_by(|t| t.partial_cmp(&time).unwrap_or(std::cmp::Ordering::Less))
+        {
+            Ok(exact) => return self.value(exact).to_vec(),
+            Err(next) => next,
+        };
+        let key = next - 1;
+        let dt = self.times[next] - self.times[key];
+        let t = (time - self.times[key]) / dt;
+        let (a, b) = (self.value(key), self.value(next));
+
+        match self.interpolation {
+            Interpolation::Step => a.to_vec(),
+            Interpolation::Linear if self.property == Property::Rotation => {
+                let a = Quaternion::new(a[3], a[0], a[1], a[2]);
+                let b = Quaternion::new(b[3], b[0], b[1], b[2]);
+                // Take the short way around
+                let b = if a.dot(b) < 0.0 { -b } else { b };
+                let q = a.slerp(b, t);
+                vec![q.v.x, q.v.y, q.v.z, q.s]
+            }
+            Interpolation::Linear => a.iter().zip(b).map(|(a, b)| a + (b - a) * t).collect(),
+            Interpolation::CubicSpline => {
+                let (t2, t3) = (t * t, t * t * t);
+                let out_a = self.tangent(key, true);
+                let in_b = self.tangent(next, false);
+                (0..self.components)
+                    .map(|i| {
+                        (2.0 * t3 - 3.0 * t2 + 1.0) * a[i]
+                            + (t3 - 2.0 * t2 + t) * dt * out_a[i]
+                            + (-2.0 * t3 + 3.0 * t2) * b[i]
+                            + (t3 - t2) * dt * in_b[i]
+                    })
+                    .collect()
+            }
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+struct Transform {
+    translation: Vector3<f32>,
+    rotation: Quaternion<f32>,
+    scale: Vector3<f32>,
+}
+
+impl Transform {
+    fn matrix(&self) -> Matrix4<f32> {
+        Matrix4::from_translation(self.translation)
+            * Matrix4::from(self.rotation)
+            * Matrix4::from_nonuniform_scale(self.scale.x, self.scale.y, self.scale.z)
+    }
+}
+
+struct Skin {
+    /// Node index of each joint
+    joints: Vec<usize>,
+    /// Mesh space to the space of each joint in the bind pose
+    inverse_bind: Vec<Matrix4<f32>>,
+}
+
+/// The node hierarchy and animation of a glTF mesh
+pub struct Rig {
+    parents: Vec<Option<usize>>,
+    rest: Vec<Transform>,
+    rest_weights: [f32; MAX_MORPH_TARGETS],
+    /// The node the mesh is attached to
+    mesh_node: usize,
+    skin: Option<Skin>,
+    channels: Vec<Channel>,
+    /// Time of the last keyframe, after which the animation loops
+    duration: f32,
+}
+
+/// A rig sampled at one point in time, laid out the way the vertex shaders
+/// expect it
+#[derive(Clone, Debug)]
+pub struct Pose {
+    pub skinned: bool,
+    /// Bind pose mesh space to animated mesh space, for each joint
+    pub joint_matrices: Vec<[[f32; 4]; 4]>,
+    pub morph_weights: [f32; MAX_MORPH_TARGETS],
+    /// Movement of the mesh's node away from its rest pose, in the mesh's own
+    /// space, to apply on top of the scene node's transform
+    pub transform: Matrix4<f32>,
+}
+
+impl Default for Pose {
+    /// The pose of a mesh that doesn't move
+    fn default() -> Self {
+        Self {
+            skinned: false,
+            joint_matrices: Vec::new(),
+            morph_weights: [0.0; MAX_MORPH_TARGETS],
+            transform: Matrix4::identity(),
+        }
+    }
+}
+
+impl Rig {
+    /// Gather what is needed to animate `mesh`, or `None` if nothing about it
+    /// ever moves
+    pub fn from_gltf(
+        document: &gltf::Document,
+        buffers: &[gltf::buffer::Data],
+        mesh: &gltf::Mesh,
+    ) -> Option<Self> {
+        let mesh_node = document
+            .nodes()
+            .find(|node| node.mesh().map_or(false, |m| m.index() == mesh.index()))?;
+
+        let mut parents = vec![None; document.nodes().len()];
+        for node in document.nodes() {
+            for child in node.children() {
+                parents[child.index()] = Some(node.index());
+            }
+        }
+        let rest = document
+            .nodes()
+            .map(|node| {
+                let (t, r, s) = node.transform().decomposed();
+                Transform {
+                    translation: t.into(),
+                    rotation: Quaternion::new(r[3], r[0], r[1], r[2]),
+                    scale: s.into(),
+                }
+            })
+            .collect();
+
+        let mut rest_weights = [0.0; MAX_MORPH_TARGETS];
+        if let Some(weights) = mesh_node.weights().or_else(|| mesh.weights()) {
+            for (rest, &weight) in rest_weights.iter_mut().zip(weights) {
+                *rest = weight;
+            }
+        }
+
+        let skin = mesh_node.skin().map(|skin| {
+            let mut joints: Vec<_> = skin.joints().map(|joint| joint.index()).collect();
+            if joints.len() > MAX_JOINTS {
+                eprintln!(
+                    "Skin with {} joints, only the first {} are animated",
+                    joints.len(),
+                    MAX_JOINTS
+                );
+                joints.truncate(MAX_JOINTS);
+            }
+            let reader = skin.reader(|buffer| Some(&buffers[buffer.index()]));
+            let inverse_bind = match reader.read_inverse_bind_matrices() {
+                Some(matrices) => matrices.map(Matrix4::from).collect(),
+                None => vec![Matrix4::identity(); joints.len()],
+            };
+            Skin {
+                joints,
+                inverse_bind,
+            }
+        });
+
+        let channels: Vec<_> = document
+            .animations()
+            .next()
+            .into_iter()
+            .flat_map(|animation| animation.channels())
+            .filter_map(|channel| load_channel(&channel, buffers))
+            .collect();
+        let duration = channels
+            .iter()
+            .map(|channel| channel.times[channel.times.len() - 1])
+            .fold(0.0, f32::max);
+
+        if skin.is_none() && channels.is_empty() && rest_weights.iter().all(|&w| w == 0.0) {
+            return None;
+        }
+
+        Some(Self {
+            parents,
+            rest,
+            rest_weights,
+            mesh_node: mesh_node.index(),
+            skin,
+            channels,
+            duration,
+        })
+    }
+
+    /// The pose `time` seconds into the animation, looping at its end
+    pub fn pose(&self, time: f32) -> Pose {
+        let time = if self.duration > 0.0 {
+            time % self.duration
+        } else {
+            0.0
+        };
+
+        let mut local = self.rest.clone();
+        let mut weights = self.rest_weights;
+        for channel in &self.channels {
+            let value = channel.sample(time);
+            let node = &mut local[channel.node];
+            match channel.property {
+                Property::Translation => {
+                    node.translation = Vector3::new(value[0], value[1], value[2])
+                }
+                Property::Rotation => {
+                    node.rotation =
+                        Quaternion::new(value[3], value[0], value[1], value[2]).normalize()
+                }
+                Property::Scale => node.scale = Vector3::new(value[0], value[1], value[2]),
+                // The weights only reach the mesh through its own node
+                Property::MorphWeights if channel.node == self.mesh_node => {
+                    for (weight, &v) in weights.iter_mut().zip(&value) {
+                        *weight = v;
+                    }
+                }
+                Property::MorphWeights => {}
+            }
+        }
+
+        self.evaluate(&local, weights)
+    }
+
+    /// The pose without any animation applied
+    pub fn rest_pose(&self) -> Pose {
+        self.evaluate(&self.rest, self.rest_weights)
+    }
+
+    fn evaluate(&self, local: &[Transform], weights: [f32; MAX_MORPH_TARGETS]) -> Pose {
+        let globals = global_transforms(&self.parents, local);
+
+        match &self.skin {
+            // Skinned vertices are placed by their joints alone, the mesh's
+            // node transform doesn't apply
+            Some(skin) => Pose {
+                skinned: true,
+                joint_matrices: skin
+                    .joints
+                    .iter()
+                    .zip(&skin.inverse_bind)
+                    .map(|(&joint, inverse_bind)| (globals[joint] * inverse_bind).into())
+                    .collect(),
+                morph_weights: weights,
+                transform: Matrix4::identity(),
+            },
+            None => {
+                let rest = global_transforms(&self.parents, &self.rest)[self.mesh_node];
+                Pose {
+                    skinned: false,
+                    joint_matrices: Vec::new(),
+                    morph_weights: weights,
+                    // The vertices are drawn in mesh space, without the
+                    // node's rest transform, so the movement is expressed
+                    // relative to it
+                    transform: rest.invert().unwrap_or(Matrix4::identity())
+                        * globals[self.mesh_node],
+                }
+            }
+        }
+    }
+}
+
+/// Node space to the space of the glTF scene, for every node
+fn global_transforms(parents: &[Option<usize>], local: &[Transform]) -> Vec<Matrix4<f32>> {
+    fn resolve(
+        node: usize,
+        parents: &[Option<usize>],
+        local: &[Transform],
+        globals: &mut Vec<Option<Matrix4<f32>>>,
+    ) -> Matrix4<f32> {
+        if let Some(global) = globals[node] {
+            return global;
+        }
+        let parent = match parents[node] {
+            Some(parent) => resolve(parent, parents, local, globals),
+            None => Matrix4::identity(),
+        };
+        let global = parent * local[node].matrix();
+        globals[node] = Some(global);
+        global
+    }
+
+    let mut globals = vec![None; local.len()];
+    (0..local.len())
+        .map(|node| resolve(node, parents, local, &mut globals))
+        .collect()
+}
+
+fn load_channel(
+    channel: &gltf::animation::Channel,
+    buffers: &[gltf::buffer::Data],
+) -> Option<Channel> {
+    use gltf::animation::util::ReadOutputs;
+
+    let reader = channel.reader(|buffer| Some(&buffers[buffer.index()]));
+    let times: Vec<f32> = reader.read_inputs()?.collect();
+    if times.is_empty() {
+        return None;
+    }
+
+    let (property, values): (_, Vec<f32>) = match reader.read_outputs()? {
+        ReadOutputs::Translations(values) => (
+            Property::Translation,
+            values.flat_map(|v| v.to_vec()).collect(),
+        ),
+        ReadOutputs::Rotations(values) => (
+            Property::Rotation,
+            values.into_f32().flat_map(|v| v.to_vec()).collect(),
+        ),
+        ReadOutputs::Scales(values) => (Property::Scale, values.flat_map(|v| v.to_vec()).collect()),
+        ReadOutputs::MorphTargetWeights(values) => {
+            (Property::MorphWeights, values.into_f32().collect())
+        }
+    };
+
+    let interpolation = match channel.sampler().interpolation() {
+        gltf::animation::Interpolation::Step => Interpolation::Step,
+        gltf::animation::Interpolation::CubicSpline => Interpolation::CubicSpline,
+        _ => Interpolation::Linear,
+    };
+    let values_per_key = match interpolation {
+        Interpolation::CubicSpline => 3,
+        _ => 1,
+    };
+    let components = values.len() / (times.len() * values_per_key);
+    if components == 0 {
+        return None;
+    }
+
+    Some(Channel {
+        node: channel.target().node().index(),
+        property,
+        interpolation,
+        times,
+        values,
+        components,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use cgmath::{Deg, Rotation3};
+
+    /// Where `transform` moves the point `p`
+    fn apply(transform: Matrix4<f32>, p: [f32; 3]) -> Vector3<f32> {
+        (transform * Vector3::from(p).extend(1.0)).truncate()
+    }
+
+    fn assert_close(a: Vector3<f32>, b: [f32; 3]) {
+        assert!(
+            (a - Vector3::from(b)).magnitude() < 1e-5,
+            "{:?} != {:?}",
+            a,
+            b
+        );
+    }
+
+    #[test]
+    fn translated_node_turns_about_its_own_origin() {
+        let end = Quaternion::from_angle_z(Deg(90.0));
+        let rig = Rig {
+            parents: vec![None],
+            rest: vec![Transform {
+                translation: Vector3::new(5.0, 0.0, 0.0),
+                rotation: Quaternion::new(1.0, 0.0, 0.0, 0.0),
+                scale: Vector3::new(1.0, 1.0, 1.0),
+            }],
+            rest_weights: [0.0; MAX_MORPH_TARGETS],
+            mesh_node: 0,
+            skin: None,
+            channels: vec![Channel {
+                node: 0,
+                property: Property::Rotation,
+                interpolation: Interpolation::Linear,
+                times: vec![0.0, 2.0],
+                values: vec![0.0, 0.0, 0.0, 1.0, end.v.x, end.v.y, end.v.z, end.s],
+                components: 4,
+            }],
+            duration: 2.0,
+        };
+
+        let pose = rig.pose(1.0);
+        assert!(!pose.skinned);
+        assert_close(apply(pose.transform, [0.0; 3]), [0.0; 3]);
+        let turned = Deg(45.0f32);
+        assert_close(
+            apply(pose.transform, [1.0, 0.0, 0.0]),
+            [turned.cos(), turned.sin(), 0.0],
+        );
+    }
+
+    #[test]
+    fn rest_pose_does_not_move() {
+        let rig = Rig {
+            parents: vec![None, Some(0)],
+            rest: vec![
+                Transform {
+                    translation: Vector3::new(0.0, 3.0, 0.0),
+                    rotation: Quaternion::from_angle_x(Deg(30.0)),
+                    scale: Vector3::new(2.0, 2.0, 2.0),
+                },
+                Transform {
+                    translation: Vector3::new(1.0, 0.0, 0.0),
+                    rotation: Quaternion::new(1.0, 0.0, 0.0, 0.0),
+                    scale: Vector3::new(1.0, 1.0, 1.0),
+                },
+            ],
+            rest_weights: [0.5, 0.0, 0.0, 0.0],
+            mesh_node: 1,
+            skin: None,
+            channels: Vec::new(),
+            duration: 0.0,
+        };
+
+        let pose = rig.rest_pose();
+        let p = [0.25, -1.0, 4.0];
+        assert_close(apply(pose.transform, p), p);
+        assert_eq!(pose.morph_weights, [0.5, 0.0, 0.0, 0.0]);
+    }
+}
@@ -0,0 +1,88 @@
+//! Radix-2 fast Fourier transform
+use std::f32::consts::PI;
+
+/// Magnitudes of the first half of the spectrum of `samples`, one per
+/// frequency bin from 0 up to the Nyquist frequency. The length of `samples`
+/// must be a power of two.
+pub fn magnitudes(samples: &[f32]) -> Vec<f32> {
+    let n = samples.len();
+    assert!(n.is_power_of_two());
+    let bits = n.trailing_zeros();
+
+    // Bit reversed order, so the butterflies can run in place
+    let mut re = vec![0.0; n];
+    let mut im = vec![0.0; n];
+    for (i, &sample) in samples.iter().enumerate() {
+        let j = if bits == 0 {
+            0
+        } else {
+            i.reverse_bits() >> (usize::max_value().count_ones() - bits)
+        };
+        re[j] = sample;
+    }
+
+    let mut size = 2;
+    while size <= n {
+        let half = size / 2;
+        let step = -2.0 * PI / size as f32;
+        for start in (0..n).step_by(size) {
+            for k in 0..half {
+                let (sin, cos) = (step * k as f32).sin_cos();
+                let (a, b) = (start + k, start + k + half);
+                let t_re = re[b] * cos - im[b] * sin;
+                let t_im = re[b] * sin + im[b] * cos;
+                re[b] = re[a] - t_re;
+                im[b] = im[a] - t_im;
+                re[a] += t_re;
+                im[a] += t_im;
+            }
+        }
+        size *= 2;
+    }
+
+    (0..n / 2 + 1)
+        .map(|k| (re[k] * re[k] + im[k] * im[k]).sqrt())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sine_lands_in_its_bin() {
+        let n = 64;
+        let samples: Vec<f32> = (0..n)
+            .map(|i| (2.0 * PI * 5.0 * i as f32 / n as f32).sin())
+            .collect();
+        let spectrum = magnitudes(&samples);
+        assert_eq!(spectrum.len(), n / 2 + 1);
+        for (k, &magnitude) in spectrum.iter().enumerate() {
+            let expected = if k == 5 { n as f32 / 2.0 } else { 0.0 };
+            assert!(
+                (magnitude - expected).abs() < 1e-3,
+                "bin {}: {} instead of {}",
+                k,
+                magnitude,
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn constant_and_nyquist() {
+        let spectrum = magnitudes(&[1.0; 8]);
+        assert!((spectrum[0] - 8.0).abs() < 1e-5);
+        assert!(spectrum[1..].iter().all(|m| m.abs() < 1e-5));
+
+        let alternating = [1.0, -1.0, 1.0, -1.0, 1.0, -1.0, 1.0, -1.0];
+        let spectrum = magnitudes(&alternating);
+        assert!((spectrum[4] - 8.0).abs() < 1e-5);
+        assert!(spectrum[..4].iter().all(|m| m.abs() < 1e-5));
+    }
+
+    #[test]
+    fn single_sample() {
+        assert_eq!(magnitudes(&[0.5]), vec![0.5]);
+    }
+}
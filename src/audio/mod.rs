@@ -0,0 +1,111 @@
+//! Offline analysis of a WAV file into per frame frequency band energies,
+//! used to drive the scene in time with the music
+//!
+//! Everything is computed up front and looked up by animation time rather
+//! than the wall clock, so exported videos line up with the audio track.
+use std::io;
+use std::path::Path;
+
+mod fft;
+mod wav;
+
+/// Number of frequency bands, from bass to treble
+pub const BAND_COUNT: usize = 4;
+pub const BAND_NAMES: [&'static str; BAND_COUNT] = ["bass", "low mid", "high mid", "treble"];
+/// Band edges, in Hz
+const BAND_EDGES: [f32; BAND_COUNT + 1] = [20.0, 150.0, 600.0, 2500.0, 10000.0];
+
+/// Samples analyzed around each frame
+const WINDOW_SIZE: usize = 2048;
+/// Fraction of a band's energy kept from one frame to the next when it gets
+/// quieter, so beats fade out instead of cutting off
+const RELEASE: f32 = 0.85;
+
+pub struct AudioAnalysis {
+    fps: u32,
+    /// Energy of each band at every frame, in [0, 1] relative to the loudest
+    /// frame of that band
+    frames: Vec<[f32; BAND_COUNT]>,
+}
+
+impl AudioAnalysis {
+    /// Read and analyze a WAV file for playback at `fps` frames per second
+    pub fn load(path: &Path, fps: u32) -> io::Result<Self> {
+        let wav = wav::read(path)?;
+        Ok(Self::new(&wav, fps))
+    }
+
+    fn new(wav: &wav::Wav, fps: u32) -> Self {
+        let rate = wav.sample_rate as usize;
+        let frame_count = (wav.samples.len() * fps as usize + rate - 1) / rate;
+
+        // Hann window, against leakage between the bands
+        let window: Vec<f32> = (0..WINDOW_SIZE)
+            .map(|i| {
+                let x = i as f32 / (WINDOW_SIZE - 1) as f32;
+                0.5 - 0.5 * (2.0 * std::f32::consts::PI * x).cos()
+            })
+            .collect();
+        let bin_width = wav.sample_rate as f32 / WINDOW_SIZE as f32;
+
+        let mut frames: Vec<[f32; BAND_COUNT]> = (0..frame_count)
+            .map(|frame| {
+                let center = frame * rate / fps as usize;
+                let windowed: Vec<f32> = (0..WINDOW_SIZE)
+                    .map(|i| {
+                        (center + i)
+                            .checked_sub(WINDOW_SIZE / 2)
+                            .and_then(|s| wav.samples.get(s))
+                            .map_or(0.0, |&sample| sample * window[i])
+                    })
+                    .collect();
+                let spectrum = fft::magnitudes(&windowed);
+
+                let mut bands = [0.0; BAND_COUNT];
+                for (band, energy) in bands.iter_mut().enumerate() {
+                    let first = (BAND_EDGES[band] / bin_width).ceil() as usize;
+                    let last =
+                        ((BAND_EDGES[band + 1] / bin_width) as usize).min(spectrum.len() - 1);
+                    if first <= last {
+                        let sum: f32 = spectrum[first..=last].iter().map(|m| m * m).sum();
+                        *energy = (sum / (last - first + 1) as f32).sqrt();
+                    }
+                }
+                bands
+            })
+            .collect();
+
+        for band in 0..BAND_COUNT {
+            let loudest = frames.iter().map(|f| f[band]).fold(0.0, f32::max);
+            let mut previous = 0.0;
+            for frame in frames.iter_mut() {
+                let energy = if loudest > 0.0 {
+                    frame[band] / loudest
+                } else {
+                    0.0
+                };
+                previous = energy.max(previous * RELEASE);
+                frame[band] = previous;
+            }
+        }
+
+        Self { fps, frames }
+    }
+
+    /// Band energies at `time` seconds into the track, silent past its end
+    pub fn bands(&self, time: f32) -> [f32; BAND_COUNT] {
+        let frame = (time * self.fps as f32).round();
+        if frame < 0.0 {
+            return [0.0; BAND_COUNT];
+        }
+        self.frames
+            .get(frame as usize)
+            .copied()
+            .unwrap_or([0.0; BAND_COUNT])
+    }
+
+    /// Length of the track, in seconds
+    pub fn duration(&self) -> f32 {
+        self.frames.len() as f32 / self.fps as f32
+    }
+}
@@ -0,0 +1,280 @@
+//! A minimal WAV reader for uncompressed PCM and IEEE float files
+use std::fs::File;
+use std::io::{self, BufReader, Read};
+use std::path::Path;
+
+const FORMAT_PCM: u16 = 1;
+const FORMAT_FLOAT: u16 = 3;
+/// The actual format is stored in the first two bytes of the subformat GUID
+const FORMAT_EXTENSIBLE: u16 = 0xfffe;
+
+pub struct Wav {
+    pub sample_rate: u32,
+    /// Samples in [-1, 1], with all the channels averaged together
+    pub samples: Vec<f32>,
+}
+
+struct Format {
+    tag: u16,
+    channels: u16,
+    sample_rate: u32,
+    bits_per_sample: u16,
+}
+
+pub fn read(path: &Path) -> io::Result<Wav> {
+    let file = File::open(path)?;
+    let len = file.metadata()?.len();
+    parse(BufReader::new(file), len)
+}
+
+/// Parse a WAV file that is `len` bytes long
+fn parse<R: Read>(mut r: R, len: u64) -> io::Result<Wav> {
+    let mut header = [0u8; 12];
+    r.read_exact(&mut header)?;
+    if &header[0..4] != b"RIFF" || &header[8..12] != b"WAVE" {
+        return Err(invalid("not a RIFF WAVE file"));
+    }
+    let mut remaining = len.saturating_sub(12);
+
+    let mut format = None;
+    loop {
+        let mut chunk_header = [0u8; 8];
+        r.read_exact(&mut chunk_header)?;
+        remaining = remaining.saturating_sub(8);
+        let size = u32_le(&chunk_header[4..8]);
+
+        if &chunk_header[0..4] == b"data" {
+            let format = format.ok_or_else(|| invalid("data chunk before fmt chunk"))?;
+            // Streamed files leave the size at 0 or 0xFFFFFFFF, and truncated
+            // ones overstate it, so never read past the end of the file
+            let size = match size {
+                0 | 0xffff_ffff => remaining,
+                size => u64::from(size).min(remaining),
+            };
+            let mut data = Vec::new();
+            r.take(size).read_to_end(&mut data)?;
+            return decode(&format, &data);
+        }
+
+        // Any other size comes straight from the file, so check it before
+        // allocating anything
+        let size = u64::from(size);
+        if size > remaining {
+            return Err(invalid("chunk runs past the end of the file"));
+        }
+        // Chunks are padded to an even size
+        let padded = size + size % 2;
+        remaining = remaining.saturating_sub(padded);
+
+        match &chunk_header[0..4] {
+            b"fmt " => {
+                let mut chunk = vec![0u8; size as usize];
+                r.read_exact(&mut chunk)?;
+                if size % 2 == 1 {
+                    r.read_exact(&mut [0u8; 1])?;
+                }
+                if chunk.len() < 16 {
+                    return Err(invalid("truncated fmt chunk"));
+                }
+                let mut tag = u16_le(&chunk[0..2]);
+                if tag == FORMAT_EXTENSIBLE && chunk.len() >= 26 {
+                    tag = u16_le(&chunk[24..26]);
+                }
+                format = Some(Format {
+                    tag,
+                    channels: u16_le(&chunk[2..4]),
+                    sample_rate: u32_le(&chunk[4..8]),
+                    bits_per_sample: u16_le(&chunk[14..16]),
+                });
+            }
+            _ => {
+                io::copy(&mut (&mut r).take(padded), &mut io::sink())?;
+            }
+        }
+    }
+}
+
+fn decode(format: &Format, data: &[u8]) -> io::Result<Wav> {
+    let channels = format.channels as usize;
+    let bytes = format.bits_per_sample as usize / 8;
+    if channels == 0 || format.sample_rate == 0 {
+        return Err(invalid("no channels or zero sample rate"));
+    }
+
+    let sample = |b: &[u8]| -> Option<f32> {
+        Some(match (format.tag, format.bits_per_sample) {
+            (FORMAT_PCM, 8) => (b[0] as f32 - 128.0) / 128.0,
+            (FORMAT_PCM, 16) => i16::from_le_bytes([b[0], b[1]]) as f32 / 32_768.0,
+            // Shift into the top of an i32 to sign extend
+            (FORMAT_PCM, 24) => i32::from_le_bytes([0, b[0], b[1], b[2]]) as f32 / 2_147_483_648.0,
+            (FORMAT_PCM, 32) => {
+                i32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f32 / 2_147_483_648.0
+            }
+            (FORMAT_FLOAT, 32) => f32::from_le_bytes([b[0], b[1], b[2], b[3]]),
+            _ => return None,
+        })
+    };
+    if bytes == 0 || sample(&[0; 4]).is_none() {
+        return Err(invalid(&format!(
+            "unsupported sample format {} with {} bits per sample",
+            format.tag, format.bits_per_sample
+        )));
+    }
+
+    let samples = data
+        .chunks_exact(bytes * channels)
+        .map(|frame| frame.chunks_exact(bytes).filter_map(sample).sum::<f32>() / channels as f32)
+        .collect();
+
+    Ok(Wav {
+        sample_rate: format.sample_rate,
+        samples,
+    })
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+fn u16_le(b: &[u8]) -> u16 {
+    u16::from_le_bytes([b[0], b[1]])
+}
+
+fn u32_le(b: &[u8]) -> u32 {
+    u32::from_le_bytes([b[0], b[1], b[2], b[3]])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn format(tag: u16, channels: u16, bits_per_sample: u16) -> Format {
+        Format {
+            tag,
+            channels,
+            sample_rate: 44_100,
+            bits_per_sample,
+        }
+    }
+
+    /// A mono 16-bit file whose data chunk claims to be `data_size` bytes
+    fn wav_file(data_size: u32, data: &[u8]) -> Vec<u8> {
+        let mut file = Vec::new();
+        file.extend_from_slice(b"RIFF");
+        file.extend_from_slice(&0u32.to_le_bytes());
+        file.extend_from_slice(b"WAVE");
+        file.extend_from_slice(b"fmt ");
+        file.extend_from_slice(&16u32.to_le_bytes());
+        file.extend_from_slice(&FORMAT_PCM.to_le_bytes());
+        file.extend_from_slice(&1u16.to_le_bytes());
+        file.extend_from_slice(&44_100u32.to_le_bytes());
+        file.extend_from_slice(&88_200u32.to_le_bytes());
+        file.extend_from_slice(&2u16.to_le_bytes());
+        file.extend_from_slice(&16u16.to_le_bytes());
+        file.extend_from_slice(b"data");
+        file.extend_from_slice(&data_size.to_le_bytes());
+        file.extend_from_slice(data);
+        file
+    }
+
+    fn parse_bytes(file: &[u8]) -> io::Result<Wav> {
+        parse(file, file.len() as u64)
+    }
+
+    #[test]
+    fn decodes_8_bit_pcm() {
+        let wav = decode(&format(FORMAT_PCM, 1, 8), &[0, 128, 255]).unwrap();
+        assert_eq!(wav.samples, vec![-1.0, 0.0, 127.0 / 128.0]);
+    }
+
+    #[test]
+    fn decodes_16_bit_pcm() {
+        let data: Vec<u8> = [-32_768i16, 0, 16_384]
+            .iter()
+            .flat_map(|s| s.to_le_bytes().to_vec())
+            .collect();
+        let wav = decode(&format(FORMAT_PCM, 1, 16), &data).unwrap();
+        assert_eq!(wav.samples, vec![-1.0, 0.0, 0.5]);
+    }
+
+    #[test]
+    fn decodes_24_bit_pcm() {
+        let data = [0x00, 0x00, 0x80, 0x00, 0x00, 0x00, 0x00, 0x00, 0x40];
+        let wav = decode(&format(FORMAT_PCM, 1, 24), &data).unwrap();
+        assert_eq!(wav.samples, vec![-1.0, 0.0, 0.5]);
+    }
+
+    #[test]
+    fn decodes_float() {
+        let data: Vec<u8> = [0.25f32, -0.75]
+            .iter()
+            .flat_map(|s| s.to_le_bytes().to_vec())
+            .collect();
+        let wav = decode(&format(FORMAT_FLOAT, 1, 32), &data).unwrap();
+        assert_eq!(wav.samples, vec![0.25, -0.75]);
+    }
+
+    #[test]
+    fn averages_channels() {
+        // Two stereo frames, then half a frame that is dropped
+        let data: Vec<u8> = [16_384i16, -16_384, 16_384, 0, 16_384]
+            .iter()
+            .flat_map(|s| s.to_le_bytes().to_vec())
+            .collect();
+        let wav = decode(&format(FORMAT_PCM, 2, 16), &data).unwrap();
+        assert_eq!(wav.samples, vec![0.0, 0.25]);
+    }
+
+    #[test]
+    fn rejects_unsupported_formats() {
+        assert!(decode(&format(FORMAT_PCM, 1, 12), &[0; 4]).is_err());
+        assert!(decode(&format(FORMAT_FLOAT, 1, 64), &[0; 8]).is_err());
+        assert!(decode(&format(FORMAT_PCM, 0, 16), &[0; 4]).is_err());
+    }
+
+    #[test]
+    fn parses_a_file() {
+        let wav = parse_bytes(&wav_file(4, &[0x00, 0x40, 0x00, 0xc0])).unwrap();
+        assert_eq!(wav.sample_rate, 44_100);
+        assert_eq!(wav.samples, vec![0.5, -0.5]);
+    }
+
+    #[test]
+    fn streamed_data_reads_to_the_end() {
+        for &size in &[0, 0xffff_ffff] {
+            let wav = parse_bytes(&wav_file(size, &[0x00, 0x40, 0x00, 0xc0])).unwrap();
+            assert_eq!(wav.samples, vec![0.5, -0.5]);
+        }
+    }
+
+    #[test]
+    fn overstated_data_stops_at_the_end() {
+        let wav = parse_bytes(&wav_file(0x7fff_0000, &[0x00, 0x40])).unwrap();
+        assert_eq!(wav.samples, vec![0.5]);
+    }
+
+    #[test]
+    fn oversized_chunks_are_rejected() {
+        let mut file = wav_file(2, &[0x00, 0x40]);
+        // Blow up the size of the fmt chunk
+        file[16..20].copy_from_slice(&0xffff_fff0u32.to_le_bytes());
+        let error = parse_bytes(&file).err().unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn skips_unknown_chunks() {
+        let mut file = wav_file(2, &[0x00, 0x40]);
+        // An odd sized chunk, plus its padding byte, before the data
+        let extra = [b'L', b'I', b'S', b'T', 3, 0, 0, 0, 1, 2, 3, 0];
+        let data_start = file.len() - 10;
+        file.splice(data_start..data_start, extra.iter().cloned());
+        let wav = parse_bytes(&file).unwrap();
+        assert_eq!(wav.samples, vec![0.5]);
+    }
+
+    #[test]
+    fn rejects_other_files() {
+        assert!(parse_bytes(b"RIFF\0\0\0\0AVI LIST").is_err());
+    }
+}
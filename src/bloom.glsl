@@ -2,11 +2,34 @@ in vec2 v_pos;
 
 uniform sampler2D main_tex;
 uniform sampler2D bright_tex;
+uniform float bloom_strength;
+uniform float exposure;
+// Matches the ToneMapper enum
+uniform int tone_mapper;
 
 out vec4 color;
 
+const int REINHARD = 1;
+const int ACES = 2;
+
+vec3 aces(vec3 x) {
+  // Narkowicz's fit of the ACES filmic curve
+  return clamp((x * (2.51 * x + 0.03)) / (x * (2.43 * x + 0.59) + 0.14), 0.0, 1.0);
+}
+
 void main() {
-  color = texture(bright_tex, v_pos) + texture(main_tex, v_pos);
+  // Depth of field has already applied the occlusion when it is enabled, so
+  // that the occlusion is blurred along with the rest of the scene
+  vec3 scene = occluded_scene(main_tex, v_pos);
+  color = texture(bright_tex, v_pos) * bloom_strength
+    + vec4(scene, texture(main_tex, v_pos).a);
+  color.rgb *= exposure;
+
+  if (tone_mapper == REINHARD) {
+    color.rgb = color.rgb / (color.rgb + vec3(1.0));
+  } else if (tone_mapper == ACES) {
+    color.rgb = aces(color.rgb);
+  }
 
   color = pow(color, vec4(1.0 / 2.2));
 }
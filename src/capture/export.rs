@@ -0,0 +1,107 @@
+//! Frame by frame export of the rendered output
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::PathBuf;
+
+use crate::options::ExportTarget;
+
+enum Sink {
+    Y4m(BufWriter<File>),
+    ImageSequence(PathBuf),
+}
+
+/// Writes a sequence of RGBA8 frames, as returned by
+/// [`read_back_buffer`](super::read_back_buffer), to a YUV4MPEG2 stream or a
+/// directory of numbered PNG files.
+pub struct FrameExporter {
+    sink: Sink,
+    size: [u32; 2],
+    frames_written: u32,
+}
+
+impl FrameExporter {
+    pub fn new(target: &ExportTarget, size: [u32; 2], fps: u32) -> io::Result<Self> {
+        let sink = match target {
+            ExportTarget::Y4m(path) => {
+                let mut out = BufWriter::new(File::create(path)?);
+                write!(
+                    out,
+                    "YUV4MPEG2 W{} H{} F{}:1 Ip A1:1 C444 XCOLORRANGE=FULL\n",
+                    size[0], size[1], fps
+                )?;
+                Sink::Y4m(out)
+            }
+            ExportTarget::ImageSequence(directory) => {
+                fs::create_dir_all(directory)?;
+                Sink::ImageSequence(directory.clone())
+            }
+        };
+
+        Ok(Self {
+            sink,
+            size,
+            frames_written: 0,
+        })
+    }
+
+    pub fn frames_written(&self) -> u32 {
+        self.frames_written
+    }
+
+    /// Append a frame. The frame must have the size the exporter was created
+    /// with.
+    pub fn write_frame(&mut self, size: [u32; 2], rgba: &[u8]) -> io::Result<()> {
+        if size != self.size {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "frame size changed from {:?} to {:?} during export",
+                    self.size, size
+                ),
+            ));
+        }
+
+        match &mut self.sink {
+            Sink::Y4m(out) => {
+                out.write_all(b"FRAME\n")?;
+                out.write_all(&rgba_to_yuv444(rgba))?;
+            }
+            Sink::ImageSequence(directory) => {
+                let path = directory.join(format!("frame-{:06}.png", self.frames_written));
+                image::save_buffer(path, rgba, size[0], size[1], image::ColorType::RGBA(8))?;
+            }
+        }
+
+        self.frames_written += 1;
+        Ok(())
+    }
+
+    /// Flush any buffered output
+    pub fn finish(self) -> io::Result<()> {
+        match self.sink {
+            Sink::Y4m(mut out) => out.flush(),
+            Sink::ImageSequence(_) => Ok(()),
+        }
+    }
+}
+
+/// Convert RGBA8 pixels into full range BT.601 Y, Cb and Cr planes
+fn rgba_to_yuv444(rgba: &[u8]) -> Vec<u8> {
+    let pixel_count = rgba.len() / 4;
+    let mut planes = vec![0u8; pixel_count * 3];
+    {
+        let (y_plane, rest) = planes.split_at_mut(pixel_count);
+        let (u_plane, v_plane) = rest.split_at_mut(pixel_count);
+
+        for (i, pixel) in rgba.chunks(4).enumerate() {
+            let (r, g, b) = (pixel[0] as f32, pixel[1] as f32, pixel[2] as f32);
+            let y = 0.299 * r + 0.587 * g + 0.114 * b;
+            let u = 128.0 - 0.168736 * r - 0.331264 * g + 0.5 * b;
+            let v = 128.0 + 0.5 * r - 0.418688 * g - 0.081312 * b;
+            y_plane[i] = y.round().max(0.0).min(255.0) as u8;
+            u_plane[i] = u.round().max(0.0).min(255.0) as u8;
+            v_plane[i] = v.round().max(0.0).min(255.0) as u8;
+        }
+    }
+    planes
+}
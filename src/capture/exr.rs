@@ -0,0 +1,95 @@
+//! A minimal OpenEXR writer.
+//!
+//! Only produces single part, uncompressed, scanline images with 32 bit float
+//! RGB channels, which is all we need for dumping HDR render targets.
+use std::io::{self, Write};
+
+const MAGIC: i32 = 20000630;
+const VERSION: i32 = 2;
+const PIXEL_TYPE_FLOAT: i32 = 2;
+
+/// Write `pixels`, which contains interleaved RGB triples with the top row
+/// first, as an OpenEXR image.
+pub fn write<W: Write>(w: &mut W, width: u32, height: u32, pixels: &[f32]) -> io::Result<()> {
+    assert!(pixels.len() == (width * height * 3) as usize);
+
+    let mut header = Vec::new();
+    header.extend_from_slice(&MAGIC.to_le_bytes());
+    header.extend_from_slice(&VERSION.to_le_bytes());
+
+    // Channels must be stored in alphabetical order
+    let mut channels = Vec::new();
+    for name in &["B", "G", "R"] {
+        channels.extend_from_slice(name.as_bytes());
+        channels.push(0);
+        channels.extend_from_slice(&PIXEL_TYPE_FLOAT.to_le_bytes());
+        // pLinear + reserved
+        channels.extend_from_slice(&[0, 0, 0, 0]);
+        // x and y sampling
+        channels.extend_from_slice(&1i32.to_le_bytes());
+        channels.extend_from_slice(&1i32.to_le_bytes());
+    }
+    channels.push(0);
+    attribute(&mut header, "channels", "chlist", &channels);
+
+    // No compression
+    attribute(&mut header, "compression", "compression", &[0]);
+
+    let mut window = Vec::new();
+    for v in &[0, 0, width as i32 - 1, height as i32 - 1] {
+        window.extend_from_slice(&v.to_le_bytes());
+    }
+    attribute(&mut header, "dataWindow", "box2i", &window);
+    attribute(&mut header, "displayWindow", "box2i", &window);
+
+    // Increasing Y
+    attribute(&mut header, "lineOrder", "lineOrder", &[0]);
+    attribute(
+        &mut header,
+        "pixelAspectRatio",
+        "float",
+        &1.0f32.to_le_bytes(),
+    );
+    attribute(&mut header, "screenWindowCenter", "v2f", &[0; 8]);
+    attribute(
+        &mut header,
+        "screenWindowWidth",
+        "float",
+        &1.0f32.to_le_bytes(),
+    );
+    header.push(0);
+
+    let line_bytes = width as usize * 3 * 4;
+    let block_size = 4 + 4 + line_bytes;
+    let table_end = header.len() + height as usize * 8;
+
+    w.write_all(&header)?;
+    for y in 0..height as usize {
+        w.write_all(&((table_end + y * block_size) as u64).to_le_bytes())?;
+    }
+
+    let mut line = Vec::with_capacity(line_bytes);
+    for (y, row) in pixels.chunks(width as usize * 3).enumerate() {
+        line.clear();
+        // Each scanline holds all of one channel, then all of the next
+        for channel in &[2, 1, 0] {
+            for texel in row.chunks(3) {
+                line.extend_from_slice(&texel[*channel].to_le_bytes());
+            }
+        }
+        w.write_all(&(y as i32).to_le_bytes())?;
+        w.write_all(&(line_bytes as i32).to_le_bytes())?;
+        w.write_all(&line)?;
+    }
+
+    Ok(())
+}
+
+fn attribute(header: &mut Vec<u8>, name: &str, ty: &str, value: &[u8]) {
+    header.extend_from_slice(name.as_bytes());
+    header.push(0);
+    header.extend_from_slice(ty.as_bytes());
+    header.push(0);
+    header.extend_from_slice(&(value.len() as i32).to_le_bytes());
+    header.extend_from_slice(value);
+}
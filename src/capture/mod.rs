@@ -0,0 +1,53 @@
+//! Frame capture: reading rendered images back from the GPU and writing them
+//! to disk
+
+mod export;
+mod exr;
+mod rgbe;
+mod screenshot;
+
+pub use export::FrameExporter;
+pub use screenshot::{HdrFormat, ScreenshotRequest, Screenshotter};
+
+/// Read the contents of the default framebuffer's back buffer as tightly
+/// packed RGBA8 rows, top row first.
+///
+/// This must be called after all rendering for the frame has finished and
+/// before the buffers are swapped.
+pub fn read_back_buffer(size: [u32; 2]) -> Vec<u8> {
+    let (width, height) = (size[0] as usize, size[1] as usize);
+    let mut pixels = vec![0u8; width * height * 4];
+
+    unsafe {
+        let mut previous_read_fb = 0;
+        gl::GetIntegerv(gl::READ_FRAMEBUFFER_BINDING, &mut previous_read_fb);
+
+        gl::BindFramebuffer(gl::READ_FRAMEBUFFER, 0);
+        gl::ReadBuffer(gl::BACK);
+        gl::PixelStorei(gl::PACK_ALIGNMENT, 1);
+        gl::ReadPixels(
+            0,
+            0,
+            size[0] as gl::types::GLsizei,
+            size[1] as gl::types::GLsizei,
+            gl::RGBA,
+            gl::UNSIGNED_BYTE,
+            pixels.as_mut_ptr() as *mut _,
+        );
+
+        gl::BindFramebuffer(gl::READ_FRAMEBUFFER, previous_read_fb as gl::types::GLuint);
+    }
+
+    flip_rows(&mut pixels, width * 4);
+    pixels
+}
+
+/// OpenGL hands back images bottom row first, everything we write wants the
+/// top row first.
+pub fn flip_rows<T: Copy>(data: &mut [T], row_len: usize) {
+    let rows = data.len() / row_len;
+    for row in 0..rows / 2 {
+        let (top, bottom) = data.split_at_mut((rows - row - 1) * row_len);
+        top[row * row_len..(row + 1) * row_len].swap_with_slice(&mut bottom[..row_len]);
+    }
+}
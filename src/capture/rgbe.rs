@@ -0,0 +1,43 @@
+//! A minimal Radiance RGBE (.hdr) writer, storing flat (not run length
+//! encoded) scanlines
+use std::io::{self, Write};
+
+/// Write `pixels`, which contains interleaved RGB triples with the top row
+/// first, as a Radiance .hdr image.
+pub fn write<W: Write>(w: &mut W, width: u32, height: u32, pixels: &[f32]) -> io::Result<()> {
+    assert!(pixels.len() == (width * height * 3) as usize);
+
+    write!(
+        w,
+        "#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y {} +X {}\n",
+        height, width
+    )?;
+
+    let mut line = Vec::with_capacity(width as usize * 4);
+    for row in pixels.chunks(width as usize * 3) {
+        line.clear();
+        for texel in row.chunks(3) {
+            line.extend_from_slice(&to_rgbe([texel[0], texel[1], texel[2]]));
+        }
+        w.write_all(&line)?;
+    }
+
+    Ok(())
+}
+
+fn to_rgbe(color: [f32; 3]) -> [u8; 4] {
+    let max = color[0].max(color[1]).max(color[2]);
+    if max < 1e-32 {
+        return [0, 0, 0, 0];
+    }
+
+    // Find e such that max = m * 2^e with m in [0.5, 1)
+    let exponent = max.log2().floor() as i32 + 1;
+    let scale = 256.0 / 2.0f32.powi(exponent);
+    [
+        (color[0] * scale).min(255.0) as u8,
+        (color[1] * scale).min(255.0) as u8,
+        (color[2] * scale).min(255.0) as u8,
+        (exponent + 128) as u8,
+    ]
+}
@@ -0,0 +1,105 @@
+//! Screenshots of the final frame and the HDR buffers that went into it
+use std::fs::{self, File};
+use std::io::{self, BufWriter};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use luminance::pixel::R11G11B10F;
+use luminance::texture::{Dim2, Flat, Texture};
+
+/// File format used when dumping the raw HDR buffers
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum HdrFormat {
+    OpenExr,
+    Radiance,
+}
+
+impl HdrFormat {
+    fn extension(self) -> &'static str {
+        match self {
+            HdrFormat::OpenExr => "exr",
+            HdrFormat::Radiance => "hdr",
+        }
+    }
+}
+
+/// What to capture at the end of the frame
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum ScreenshotRequest {
+    /// Only the tonemapped back buffer
+    BackBuffer,
+    /// The tonemapped back buffer, plus the main and bright HDR buffers
+    WithHdr(HdrFormat),
+}
+
+pub struct Screenshotter {
+    directory: PathBuf,
+}
+
+impl Screenshotter {
+    /// Create a screenshotter that writes into `directory`, which is created
+    /// on the first capture if it doesn't exist.
+    pub fn new<P: Into<PathBuf>>(directory: P) -> Self {
+        Self {
+            directory: directory.into(),
+        }
+    }
+
+    /// Capture the current frame. Must be called after the final composite
+    /// and before swapping buffers. Returns the path of the PNG that was
+    /// written.
+    pub fn capture(
+        &self,
+        request: ScreenshotRequest,
+        back_buffer_size: [u32; 2],
+        main: &Texture<Flat, Dim2, R11G11B10F>,
+        bright: &Texture<Flat, Dim2, R11G11B10F>,
+    ) -> io::Result<PathBuf> {
+        fs::create_dir_all(&self.directory)?;
+
+        let stem = timestamp_stem();
+        let png_path = self.directory.join(format!("{}.png", stem));
+        let pixels = super::read_back_buffer(back_buffer_size);
+        image::save_buffer(
+            &png_path,
+            &pixels,
+            back_buffer_size[0],
+            back_buffer_size[1],
+            image::ColorType::RGBA(8),
+        )?;
+
+        if let ScreenshotRequest::WithHdr(format) = request {
+            for (name, texture) in &[("main", main), ("bright", bright)] {
+                let path = self
+                    .directory
+                    .join(format!("{}-{}.{}", stem, name, format.extension()));
+                write_hdr(&path, format, texture)?;
+            }
+        }
+
+        Ok(png_path)
+    }
+}
+
+fn write_hdr(
+    path: &Path,
+    format: HdrFormat,
+    texture: &Texture<Flat, Dim2, R11G11B10F>,
+) -> io::Result<()> {
+    let [width, height] = texture.size();
+    let mut texels = texture.get_raw_texels();
+    super::flip_rows(&mut texels, width as usize * 3);
+
+    let mut out = BufWriter::new(File::create(path)?);
+    match format {
+        HdrFormat::OpenExr => super::exr::write(&mut out, width, height, &texels),
+        HdrFormat::Radiance => super::rgbe::write(&mut out, width, height, &texels),
+    }
+}
+
+fn timestamp_stem() -> String {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    format!("screenshot-{}-{:03}", now.as_secs(), now.subsec_millis())
+}
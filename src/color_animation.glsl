@@ -0,0 +1,86 @@
+// Vertex colors are animated from a per-vertex seed, stored in the color
+// attribute
+uniform int color_animation;
+// Seconds of animation, advancing faster or slower with the speed setting
+uniform float color_time;
+// Amount added to each channel per second when cycling
+uniform float color_speed;
+// Scale applied to every animated color, following the audio when there is
+// some
+uniform float color_intensity;
+
+// Must match settings::ColorAnimation
+const int HUE = 1;
+const int WAVE = 2;
+const int FLICKER = 3;
+const int PALETTE = 4;
+
+// Channels wrap back to 0 past this value when cycling
+const float COLOR_WRAP = 1.1;
+
+// Must match PALETTE_SIZE in palette.rs
+#define PALETTE_SIZE 32
+uniform vec4 palette[PALETTE_SIZE];
+
+vec3 rgb_to_hsv(vec3 c) {
+  vec4 k = vec4(0.0, -1.0 / 3.0, 2.0 / 3.0, -1.0);
+  vec4 p = c.g < c.b ? vec4(c.bg, k.wz) : vec4(c.gb, k.xy);
+  vec4 q = c.r < p.x ? vec4(p.xyw, c.r) : vec4(c.r, p.yzx);
+  float d = q.x - min(q.w, q.y);
+  float e = 1.0e-10;
+  return vec3(abs(q.z + (q.w - q.y) / (6.0 * d + e)), d / (q.x + e), q.x);
+}
+
+vec3 hsv_to_rgb(vec3 c) {
+  vec3 p = abs(fract(c.xxx + vec3(1.0, 2.0 / 3.0, 1.0 / 3.0)) * 6.0 - 3.0);
+  return c.z * mix(vec3(1.0), clamp(p - 1.0, 0.0, 1.0), c.y);
+}
+
+// Pseudo-random value in [0, 1) from a seed
+float hash(vec2 p) {
+  return fract(sin(dot(p, vec2(12.9898, 78.233))) * 43758.5453);
+}
+
+// Smoothly varying noise in [0, 1) along t, different for each seed
+float value_noise(float seed, float t) {
+  float i = floor(t);
+  float f = fract(t);
+  float a = hash(vec2(seed, i));
+  float b = hash(vec2(seed, i + 1.0));
+  return mix(a, b, f * f * (3.0 - 2.0 * f));
+}
+
+// Animated color of a vertex with color `seed` at world position `p`
+vec3 animate_rgb(vec3 seed, vec3 p) {
+  float t = color_time;
+  if (color_animation == HUE) {
+    // A full turn of the color wheel every 6 seconds
+    vec3 hsv = rgb_to_hsv(seed);
+    hsv.x = fract(hsv.x + t / 6.0);
+    return hsv_to_rgb(hsv);
+  } else if (color_animation == WAVE) {
+    // Bright fronts sweeping diagonally through the scene, pushing the
+    // vertices they pass above the bloom threshold
+    float phase = dot(p, normalize(vec3(1.0, 0.5, 0.25))) * 3.0 - t * 4.0;
+    float front = pow(0.5 + 0.5 * sin(phase), 8.0);
+    return seed * (0.3 + 2.7 * front);
+  } else if (color_animation == FLICKER) {
+    float seed_value = dot(seed, vec3(1.0, 7.0, 31.0));
+    float flicker = value_noise(seed_value, t * 12.0);
+    return seed * (0.2 + 2.0 * flicker * flicker);
+  } else if (color_animation == PALETTE) {
+    // Each vertex starts at a different point along the gradient and loops
+    // through it every 4 seconds
+    float x = fract(dot(seed, vec3(0.3, 0.5, 0.2)) + t / 4.0) * float(PALETTE_SIZE);
+    int i = int(x) % PALETTE_SIZE;
+    return mix(palette[i], palette[(i + 1) % PALETTE_SIZE], fract(x)).rgb;
+  }
+
+  return mod(seed + t * color_speed, COLOR_WRAP);
+}
+
+// Color of a vertex with color attribute `seed` at world position `p`
+vec4 animate_color(vec4 seed, vec3 p) {
+  return vec4(animate_rgb(seed.rgb, p) * color_intensity, seed.a);
+}
+
@@ -0,0 +1,204 @@
+//! Bounding volumes and view frustum culling
+use cgmath::prelude::*;
+use cgmath::{Matrix4, Point3, Vector3, Vector4};
+
+/// Axis-aligned bounding box
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Aabb {
+    pub min: Point3<f32>,
+    pub max: Point3<f32>,
+}
+
+impl Aabb {
+    /// The smallest box containing every point, or an empty box at the origin
+    /// if there are none
+    pub fn from_points<I: IntoIterator<Item = [f32; 3]>>(points: I) -> Self {
+        let mut points = points.into_iter().map(Point3::from);
+        let first = match points.next() {
+            Some(first) => first,
+            None => {
+                return Self {
+                    min: Point3::origin(),
+                    max: Point3::origin(),
+                }
+            }
+        };
+
+        points.fold(
+            Self {
+                min: first,
+                max: first,
+            },
+            |aabb, p| Self {
+                min: Point3::new(
+                    aabb.min.x.min(p.x),
+                    aabb.min.y.min(p.y),
+                    aabb.min.z.min(p.z),
+                ),
+                max: Point3::new(
+                    aabb.max.x.max(p.x),
+                    aabb.max.y.max(p.y),
+                    aabb.max.z.max(p.z),
+                ),
+            },
+        )
+    }
+
+    /// The box covering this one moved by any offset within `offsets`
+    pub fn sweep(&self, offsets: &Aabb) -> Self {
+        Self {
+            min: self.min + offsets.min.to_vec(),
+            max: self.max + offsets.max.to_vec(),
+        }
+    }
+
+    /// Sphere around the center of the box, touching its corners
+    pub fn bounding_sphere(&self) -> BoundingSphere {
+        BoundingSphere {
+            center: self.min.midpoint(self.max),
+            radius: (self.max - self.min).magnitude() / 2.0,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct BoundingSphere {
+    pub center: Point3<f32>,
+    pub radius: f32,
+}
+
+impl BoundingSphere {
+    /// A sphere containing this one after it is transformed by `m`
+    pub fn transform(&self, m: &Matrix4<f32>) -> Self {
+        Self {
+            center: m.transform_point(self.center),
+            radius: self.radius * max_scale(m),
+        }
+    }
+}
+
+/// The largest factor by which `m` stretches any axis
+pub fn max_scale(m: &Matrix4<f32>) -> f32 {
+    [m.x, m.y, m.z]
+        .iter()
+        .map(|axis| axis.truncate().magnitude())
+        .fold(0.0, f32::max)
+}
+
+/// The six planes bounding the volume visible through a projection
+pub struct Frustum {
+    /// xyz: inward facing unit normal, w: distance from the origin
+    planes: [Vector4<f32>; 6],
+}
+
+impl Frustum {
+    /// Extract the planes from a projection times view matrix, following
+    /// Gribb and Hartmann
+    pub fn from_matrix(m: &Matrix4<f32>) -> Self {
+        let row = |i: usize| Vector4::new(m.x[i], m.y[i], m.z[i], m.w[i]);
+        let normalize = |plane: Vector4<f32>| plane / plane.truncate().magnitude();
+        let (r0, r1, r2, r3) = (row(0), row(1), row(2), row(3));
+
+        Self {
+            planes: [
+                normalize(r3 + r0),
+                normalize(r3 - r0),
+                normalize(r3 + r1),
+                normalize(r3 - r1),
+                normalize(r3 + r2),
+                normalize(r3 - r2),
+            ],
+        }
+    }
+
+    /// Whether any part of `sphere` might be visible
+    pub fn intersects(&self, sphere: &BoundingSphere) -> bool {
+        let center = sphere.center.to_vec();
+        self.planes
+            .iter()
+            .all(|plane| Vector3::dot(plane.truncate(), center) + plane.w >= -sphere.radius)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sweep_covers_every_offset() {
+        let aabb = Aabb::from_points(vec![[-1.0, -1.0, -1.0], [1.0, 1.0, 1.0]]);
+        let offsets = Aabb::from_points(vec![[0.0, -0.5, 0.0], [2.0, 0.0, 0.0]]);
+        let swept = aabb.sweep(&offsets);
+        assert_eq!(swept.min, Point3::new(-1.0, -1.5, -1.0));
+        assert_eq!(swept.max, Point3::new(3.0, 1.0, 1.0));
+    }
+
+    /// 90° square frustum looking down -z, with the near plane at 1 and the
+    /// far plane at 100, so the side planes are x = ±z and y = ±z
+    fn frustum(view: Matrix4<f32>) -> Frustum {
+        let projection = cgmath::perspective(cgmath::Deg(90.0), 1.0, 1.0, 100.0);
+        Frustum::from_matrix(&(projection * view))
+    }
+
+    fn sphere(x: f32, y: f32, z: f32, radius: f32) -> BoundingSphere {
+        BoundingSphere {
+            center: Point3::new(x, y, z),
+            radius,
+        }
+    }
+
+    #[test]
+    fn frustum_planes() {
+        let frustum = frustum(Matrix4::identity());
+        let diagonal = 1.0 / 2f32.sqrt();
+        let expected = [
+            Vector4::new(diagonal, 0.0, -diagonal, 0.0),
+            Vector4::new(-diagonal, 0.0, -diagonal, 0.0),
+            Vector4::new(0.0, diagonal, -diagonal, 0.0),
+            Vector4::new(0.0, -diagonal, -diagonal, 0.0),
+            Vector4::new(0.0, 0.0, -1.0, -1.0),
+            Vector4::new(0.0, 0.0, 1.0, 100.0),
+        ];
+        for (plane, expected) in frustum.planes.iter().zip(&expected) {
+            assert!(
+                (*plane - *expected).magnitude() < 1e-4,
+                "{:?} != {:?}",
+                plane,
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn spheres_inside_and_outside() {
+        let frustum = frustum(Matrix4::identity());
+        assert!(frustum.intersects(&sphere(0.0, 0.0, -10.0, 1.0)));
+        // Past each side plane
+        assert!(!frustum.intersects(&sphere(20.0, 0.0, -10.0, 1.0)));
+        assert!(!frustum.intersects(&sphere(-20.0, 0.0, -10.0, 1.0)));
+        assert!(!frustum.intersects(&sphere(0.0, 20.0, -10.0, 1.0)));
+        assert!(!frustum.intersects(&sphere(0.0, -20.0, -10.0, 1.0)));
+        // Behind the camera and beyond the far plane
+        assert!(!frustum.intersects(&sphere(0.0, 0.0, 5.0, 1.0)));
+        assert!(!frustum.intersects(&sphere(0.0, 0.0, -102.0, 1.0)));
+    }
+
+    #[test]
+    fn spheres_straddling_a_plane() {
+        let frustum = frustum(Matrix4::identity());
+        // Center half a unit past the right plane: 0.35 away from it
+        assert!(frustum.intersects(&sphere(10.5, 0.0, -10.0, 1.0)));
+        assert!(!frustum.intersects(&sphere(10.5, 0.0, -10.0, 0.1)));
+        assert!(frustum.intersects(&sphere(0.0, 0.0, -0.5, 1.0)));
+        assert!(frustum.intersects(&sphere(0.0, 0.0, -100.5, 1.0)));
+    }
+
+    #[test]
+    fn view_moves_the_frustum() {
+        // Camera at z = 5, still looking down -z
+        let frustum = frustum(Matrix4::from_translation(Vector3::new(0.0, 0.0, -5.0)));
+        assert!(frustum.intersects(&sphere(0.0, 0.0, 0.0, 1.0)));
+        assert!(!frustum.intersects(&sphere(0.0, 0.0, 7.0, 1.0)));
+        assert!(!frustum.intersects(&sphere(8.0, 0.0, 0.0, 1.0)));
+    }
+}
@@ -0,0 +1,32 @@
+in vec4 v_color;
+in vec3 v_normal;
+in vec3 v_face_normal;
+in vec3 v_barycentric;
+
+// Matches the RenderMode enum
+uniform int mode;
+
+layout(location = 0) out vec4 main_color;
+layout(location = 1) out vec4 bright_color;
+layout(location = 2) out vec4 ambient_light;
+
+const int WIREFRAME = 1;
+const int FACE_NORMALS = 2;
+const int VERTEX_NORMALS = 3;
+
+void main() {
+  if (mode == WIREFRAME) {
+    // Distance to the closest edge in pixels
+    vec3 d = v_barycentric / fwidth(v_barycentric);
+    if (min(d.x, min(d.y, d.z)) > 1.0) {
+      discard;
+    }
+    main_color = v_color;
+  } else if (mode == FACE_NORMALS) {
+    main_color = vec4(normalize(v_face_normal) * 0.5 + 0.5, 1.0);
+  } else {
+    main_color = vec4(normalize(v_normal) * 0.5 + 0.5, 1.0);
+  }
+  bright_color = vec4(0.0);
+  ambient_light = vec4(0.0);
+}
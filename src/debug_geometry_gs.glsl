@@ -0,0 +1,31 @@
+layout (triangles) in;
+layout (triangle_strip, max_vertices = 3) out;
+
+in vec3 g_pos[];
+in vec4 g_color[];
+in vec3 g_normal[];
+
+out vec4 v_color;
+out vec3 v_normal;
+out vec3 v_face_normal;
+out vec3 v_barycentric;
+
+const vec3[3] CORNERS = vec3[](
+  vec3(1.0, 0.0, 0.0),
+  vec3(0.0, 1.0, 0.0),
+  vec3(0.0, 0.0, 1.0)
+);
+
+void main() {
+  vec3 face_normal = normalize(cross(g_pos[1] - g_pos[0], g_pos[2] - g_pos[0]));
+
+  for (int i = 0; i < 3; ++i) {
+    gl_Position = gl_in[i].gl_Position;
+    v_color = g_color[i];
+    v_normal = g_normal[i];
+    v_face_normal = face_normal;
+    v_barycentric = CORNERS[i];
+    EmitVertex();
+  }
+  EndPrimitive();
+}
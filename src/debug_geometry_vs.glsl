@@ -0,0 +1,26 @@
+layout (location = 0) in vec3 pos;
+layout (location = 1) in vec4 color;
+layout (location = 2) in vec3 normal;
+layout (location = 3) in vec2 uv;
+
+uniform mat4 transform;
+uniform mat4 model;
+
+out vec3 g_pos;
+out vec4 g_color;
+out vec3 g_normal;
+
+void main() {
+  vec3 p = pos;
+  vec3 n = normal;
+  deform(p, n);
+  if (instanced) {
+    p = place_instance(p);
+    n = rotate(instance_rotation, n);
+  }
+
+  gl_Position = transform * vec4(p, 1.);
+  g_pos = p;
+  g_color = animate_color(color, (model * vec4(p, 1.)).xyz);
+  g_normal = n;
+}
@@ -0,0 +1,43 @@
+// Skinning and morph targets, applied in the mesh's own space before any
+// other transform
+layout (location = 7) in vec4 joints;
+layout (location = 8) in vec4 weights;
+layout (location = 9) in vec3 morph_position_0;
+layout (location = 10) in vec3 morph_position_1;
+layout (location = 11) in vec3 morph_position_2;
+layout (location = 12) in vec3 morph_position_3;
+
+// Must match MAX_JOINTS and MAX_MORPH_TARGETS in animation.rs
+#define MAX_JOINTS 64
+#define MAX_MORPH_TARGETS 4
+
+uniform bool skinned;
+uniform mat4 joint_matrices[MAX_JOINTS];
+uniform vec4 morph_weights;
+
+void deform(inout vec3 p, inout vec3 n, inout vec3 t) {
+  p += morph_weights.x * morph_position_0
+     + morph_weights.y * morph_position_1
+     + morph_weights.z * morph_position_2
+     + morph_weights.w * morph_position_3;
+
+  if (skinned) {
+    float total = dot(weights, vec4(1.0));
+    // Vertices the skin doesn't reach stay where they are
+    mat4 skin = total > 0.0
+      ? (weights.x * joint_matrices[int(joints.x)]
+         + weights.y * joint_matrices[int(joints.y)]
+         + weights.z * joint_matrices[int(joints.z)]
+         + weights.w * joint_matrices[int(joints.w)]) / total
+      : mat4(1.0);
+    p = (skin * vec4(p, 1.0)).xyz;
+    n = mat3(skin) * n;
+    t = mat3(skin) * t;
+  }
+}
+
+void deform(inout vec3 p, inout vec3 n) {
+  vec3 t = vec3(0.0);
+  deform(p, n, t);
+}
+
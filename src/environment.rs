@@ -0,0 +1,326 @@
+//! Image-based lighting from an equirectangular HDR environment map
+//!
+//! The environment is converted to cubemaps on the CPU at startup: one for
+//! the skybox, a cosine convolved irradiance map for diffuse ambient light,
+//! and a set of GGX prefiltered maps of increasing roughness for specular
+//! reflections.
+use std::f32::consts::PI;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+
+use cgmath::prelude::*;
+use cgmath::Vector3;
+
+use luminance::context::GraphicsContext;
+use luminance::pixel::RGB32F;
+use luminance::texture::{
+    CubeFace, Cubemap, Flat, GenMipmaps, MagFilter, MinFilter, Sampler, Texture, Wrap,
+};
+
+use crate::error::LuminanceError;
+
+pub type EnvironmentTexture = Texture<Flat, Cubemap, RGB32F>;
+
+/// Number of prefiltered specular maps, from roughness 0 to 1. Must match
+/// `SPECULAR_LEVELS` in fs.glsl.
+pub const SPECULAR_LEVELS: usize = 5;
+
+const SKYBOX_SIZE: u32 = 512;
+const IRRADIANCE_SIZE: u32 = 32;
+/// Face size of the sharpest specular map, halved for each rougher level
+const SPECULAR_SIZE: u32 = 128;
+const SPECULAR_SAMPLES: u32 = 64;
+/// Widest equirectangular image used as a source for the convolutions
+const SOURCE_WIDTH: u32 = 1024;
+/// Widest equirectangular image integrated over for the irradiance map
+const IRRADIANCE_SOURCE_WIDTH: u32 = 64;
+
+/// Faces in the order used by [`face_direction`]
+const FACES: [CubeFace; 6] = [
+    CubeFace::PositiveX,
+    CubeFace::NegativeX,
+    CubeFace::PositiveY,
+    CubeFace::NegativeY,
+    CubeFace::PositiveZ,
+    CubeFace::NegativeZ,
+];
+
+/// An HDR image mapping longitude to x and latitude to y, with +Z up
+#[derive(Clone)]
+pub struct Equirect {
+    width: u32,
+    height: u32,
+    texels: Vec<[f32; 3]>,
+}
+
+impl Equirect {
+    /// Load a Radiance .hdr file
+    pub fn load(path: &Path) -> image::ImageResult<Self> {
+        let decoder = image::hdr::HDRDecoder::new(BufReader::new(File::open(path)?))?;
+        let metadata = decoder.metadata();
+        let texels = decoder
+            .read_image_hdr()?
+            .into_iter()
+            .map(|pixel| pixel.data)
+            .collect();
+
+        Ok(Self {
+            width: metadata.width,
+            height: metadata.height,
+            texels,
+        })
+    }
+
+    /// Half the resolution, averaging each 2x2 block
+    fn downsample(&self) -> Self {
+        let (width, height) = ((self.width / 2).max(1), (self.height / 2).max(1));
+        let mut texels = Vec::with_capacity((width * height) as usize);
+        for y in 0..height {
+            for x in 0..width {
+                let mut sum = [0.0; 3];
+                for (dx, dy) in &[(0, 0), (1, 0), (0, 1), (1, 1)] {
+                    let texel = self.texel((x * 2 + dx) as i64, (y * 2 + dy) as i64);
+                    for c in 0..3 {
+                        sum[c] += texel[c] / 4.0;
+                    }
+                }
+                texels.push(sum);
+            }
+        }
+
+        Self {
+            width,
+            height,
+            texels,
+        }
+    }
+
+    /// Wraps around horizontally, clamps vertically
+    fn texel(&self, x: i64, y: i64) -> [f32; 3] {
+        let x = x.rem_euclid(self.width as i64) as u32;
+        let y = y.max(0).min(self.height as i64 - 1) as u32;
+        self.texels[(y * self.width + x) as usize]
+    }
+
+    /// Bilinearly filtered radiance arriving from `direction`
+    fn sample(&self, direction: Vector3<f32>) -> [f32; 3] {
+        let d = direction.normalize();
+        let u = 0.5 + d.y.atan2(d.x) / (2.0 * PI);
+        let v = 0.5 - d.z.max(-1.0).min(1.0).asin() / PI;
+
+        let x = u * self.width as f32 - 0.5;
+        let y = v * self.height as f32 - 0.5;
+        let (x0, y0) = (x.floor(), y.floor());
+        let (fx, fy) = (x - x0, y - y0);
+        let (x0, y0) = (x0 as i64, y0 as i64);
+
+        let mut result = [0.0; 3];
+        for &(dx, dy, weight) in &[
+            (0, 0, (1.0 - fx) * (1.0 - fy)),
+            (1, 0, fx * (1.0 - fy)),
+            (0, 1, (1.0 - fx) * fy),
+            (1, 1, fx * fy),
+        ] {
+            let texel = self.texel(x0 + dx, y0 + dy);
+            for c in 0..3 {
+                result[c] += texel[c] * weight;
+            }
+        }
+        result
+    }
+
+    /// Direction and solid angle covered by every texel
+    fn texel_directions(&self) -> impl Iterator<Item = (Vector3<f32>, f32, [f32; 3])> + '_ {
+        let texel_angle = (2.0 * PI / self.width as f32) * (PI / self.height as f32);
+        self.texels.iter().enumerate().map(move |(i, &texel)| {
+            let (x, y) = (i as u32 % self.width, i as u32 / self.width);
+            let phi = ((x as f32 + 0.5) / self.width as f32 - 0.5) * 2.0 * PI;
+            let theta = (0.5 - (y as f32 + 0.5) / self.height as f32) * PI;
+            let direction = Vector3::new(
+                theta.cos() * phi.cos(),
+                theta.cos() * phi.sin(),
+                theta.sin(),
+            );
+            (direction, texel_angle * theta.cos(), texel)
+        })
+    }
+}
+
+/// The cubemaps used for image-based lighting
+pub struct Environment {
+    pub skybox: EnvironmentTexture,
+    /// Cosine weighted average of the incoming radiance around each normal
+    pub irradiance: EnvironmentTexture,
+    /// Incoming radiance filtered by the GGX lobe, from roughness 0 to 1
+    pub specular: Vec<EnvironmentTexture>,
+    loaded: bool,
+}
+
+impl Environment {
+    /// Convert an equirectangular map into the cubemaps needed for lighting.
+    /// This takes a moment, as all the filtering is done on the CPU.
+    pub fn new<C: GraphicsContext>(c: &mut C, equirect: &Equirect) -> Result<Self, LuminanceError> {
+        let mut source = equirect.clone();
+        while source.width > SOURCE_WIDTH {
+            source = source.downsample();
+        }
+        let mut irradiance_source = source.downsample();
+        while irradiance_source.width > IRRADIANCE_SOURCE_WIDTH {
+            irradiance_source = irradiance_source.downsample();
+        }
+
+        let skybox = upload_cube(c, SKYBOX_SIZE, |d| equirect.sample(d))?;
+
+        let samples: Vec<_> = irradiance_source.texel_directions().collect();
+        let irradiance = upload_cube(c, IRRADIANCE_SIZE, |n| {
+            let mut sum = [0.0; 3];
+            for (direction, solid_angle, radiance) in &samples {
+                let weight = n.dot(*direction).max(0.0) * solid_angle / PI;
+                for c in 0..3 {
+                    sum[c] += radiance[c] * weight;
+                }
+            }
+            sum
+        })?;
+
+        // Rougher levels are blurrier, so they can read from blurrier sources
+        // without losing detail, which keeps the sample count low
+        let mut specular = Vec::with_capacity(SPECULAR_LEVELS);
+        let mut level_source = source;
+        for level in 0..SPECULAR_LEVELS {
+            let roughness = level as f32 / (SPECULAR_LEVELS - 1) as f32;
+            let size = (SPECULAR_SIZE >> level).max(8);
+            specular.push(upload_cube(c, size, |n| {
+                prefilter(&level_source, n, roughness)
+            })?);
+            level_source = level_source.downsample();
+        }
+
+        Ok(Self {
+            skybox,
+            irradiance,
+            specular,
+            loaded: true,
+        })
+    }
+
+    /// A black environment, bound in place of a real one so the shaders
+    /// always have valid cubemaps to sample
+    pub fn black<C: GraphicsContext>(c: &mut C) -> Result<Self, LuminanceError> {
+        let black = |c: &mut C| upload_cube(c, 1, |_| [0.0; 3]);
+        Ok(Self {
+            skybox: black(c)?,
+            irradiance: black(c)?,
+            specular: (0..SPECULAR_LEVELS)
+                .map(|_| black(c))
+                .collect::<Result<_, _>>()?,
+            loaded: false,
+        })
+    }
+
+    /// Whether this holds an actual environment rather than the black
+    /// placeholder
+    pub fn is_loaded(&self) -> bool {
+        self.loaded
+    }
+}
+
+/// Radiance reflected towards `n` by a GGX lobe of the given roughness,
+/// assuming the view direction equals the normal
+fn prefilter(source: &Equirect, n: Vector3<f32>, roughness: f32) -> [f32; 3] {
+    if roughness == 0.0 {
+        return source.sample(n);
+    }
+
+    let up = if n.z.abs() < 0.999 {
+        Vector3::unit_z()
+    } else {
+        Vector3::unit_x()
+    };
+    let tangent = up.cross(n).normalize();
+    let bitangent = n.cross(tangent);
+
+    let a = roughness * roughness;
+    let mut sum = [0.0; 3];
+    let mut total_weight = 0.0;
+    for i in 0..SPECULAR_SAMPLES {
+        // Hammersley point set, importance sampled over the GGX distribution
+        let xi = (
+            i as f32 / SPECULAR_SAMPLES as f32,
+            i.reverse_bits() as f32 / 4_294_967_296.0,
+        );
+        let phi = 2.0 * PI * xi.0;
+        let cos_theta = ((1.0 - xi.1) / (1.0 + (a * a - 1.0) * xi.1)).sqrt();
+        let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
+        let h =
+            tangent * (sin_theta * phi.cos()) + bitangent * (sin_theta * phi.sin()) + n * cos_theta;
+
+        let l = h * (2.0 * n.dot(h)) - n;
+        let n_dot_l = n.dot(l);
+        if n_dot_l > 0.0 {
+            let radiance = source.sample(l);
+            for c in 0..3 {
+                sum[c] += radiance[c] * n_dot_l;
+            }
+            total_weight += n_dot_l;
+        }
+    }
+
+    let total_weight = total_weight.max(1e-4);
+    [
+        sum[0] / total_weight,
+        sum[1] / total_weight,
+        sum[2] / total_weight,
+    ]
+}
+
+/// Direction through the center of a texel of a cubemap face, following the
+/// OpenGL cubemap layout. `s` and `t` are in [-1, 1].
+fn face_direction(face: usize, s: f32, t: f32) -> Vector3<f32> {
+    match face {
+        0 => Vector3::new(1.0, -t, -s),
+        1 => Vector3::new(-1.0, -t, s),
+        2 => Vector3::new(s, 1.0, t),
+        3 => Vector3::new(s, -1.0, -t),
+        4 => Vector3::new(s, -t, 1.0),
+        _ => Vector3::new(-s, -t, -1.0),
+    }
+    .normalize()
+}
+
+/// Fill a cubemap by evaluating `radiance` in the direction of every texel
+fn upload_cube<C, F>(
+    c: &mut C,
+    size: u32,
+    radiance: F,
+) -> Result<EnvironmentTexture, LuminanceError>
+where
+    C: GraphicsContext,
+    F: Fn(Vector3<f32>) -> [f32; 3],
+{
+    let sampler = Sampler {
+        wrap_s: Wrap::ClampToEdge,
+        wrap_t: Wrap::ClampToEdge,
+        wrap_r: Wrap::ClampToEdge,
+        mag_filter: MagFilter::Linear,
+        min_filter: MinFilter::Linear,
+        ..Sampler::default()
+    };
+    let texture = Texture::new(c, size, 0, &sampler)?;
+
+    for (face_index, face) in FACES.iter().enumerate() {
+        let mut texels = Vec::with_capacity((size * size) as usize);
+        for y in 0..size {
+            for x in 0..size {
+                let s = (x as f32 + 0.5) / size as f32 * 2.0 - 1.0;
+                let t = (y as f32 + 0.5) / size as f32 * 2.0 - 1.0;
+                let value = radiance(face_direction(face_index, s, t));
+                texels.push((value[0], value[1], value[2]));
+            }
+        }
+        texture.upload_part(GenMipmaps::No, ([0, 0], *face), size, &texels);
+    }
+
+    Ok(texture)
+}
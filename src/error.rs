@@ -1,11 +1,15 @@
 //! Various error wrappers
 use luminance::framebuffer::FramebufferError;
 use luminance::shader::program::ProgramError;
+use luminance::tess::TessError;
+use luminance::texture::TextureError;
 
 #[derive(Debug)]
 pub enum LuminanceError {
     FramebufferError(FramebufferError),
     ProgramError(ProgramError),
+    TessError(TessError),
+    TextureError(TextureError),
 }
 
 impl From<FramebufferError> for LuminanceError {
@@ -19,3 +23,15 @@ impl From<ProgramError> for LuminanceError {
         LuminanceError::ProgramError(o)
     }
 }
+
+impl From<TessError> for LuminanceError {
+    fn from(o: TessError) -> Self {
+        LuminanceError::TessError(o)
+    }
+}
+
+impl From<TextureError> for LuminanceError {
+    fn from(o: TextureError) -> Self {
+        LuminanceError::TextureError(o)
+    }
+}
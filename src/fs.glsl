@@ -1,13 +1,242 @@
 in vec4 v_color;
+in vec3 v_world_pos;
+in vec3 v_normal;
+in vec2 v_uv;
+in vec4 v_tangent;
+
+uniform float bloom_threshold;
+uniform vec3 camera_position;
+// Direction the light travels in
+uniform vec3 light_direction;
+uniform vec3 light_color;
+uniform vec3 ambient_color;
+
+// Image-based lighting replaces the flat ambient color when an environment
+// map is loaded
+uniform bool has_environment;
+uniform float environment_strength;
+uniform samplerCube irradiance_map;
+// Must match SPECULAR_LEVELS in environment.rs
+#define SPECULAR_LEVELS 5
+// Prefiltered for roughness 0, 0.25, 0.5, 0.75 and 1
+uniform samplerCube specular_map_0;
+uniform samplerCube specular_map_1;
+uniform samplerCube specular_map_2;
+uniform samplerCube specular_map_3;
+uniform samplerCube specular_map_4;
+
+uniform bool shadows;
+uniform sampler2D shadow_map;
+// World space to the directional light's clip space
+uniform mat4 light_space_transform;
+// Depth offset against shadow acne, scaled up on surfaces facing away
+uniform float shadow_bias;
+// Radius of the PCF kernel, in shadow map texels
+uniform int shadow_pcf_radius;
+
+// Must match MAX_LIGHTS in lights.rs
+#define MAX_LIGHTS 16
+uniform int light_count;
+// xyz: position, w: range
+uniform vec4 light_position_range[MAX_LIGHTS];
+// rgb: color times intensity
+uniform vec4 light_color_intensity[MAX_LIGHTS];
+// xyz: spot direction, w: 1 for spot lights, 0 for point lights
+uniform vec4 light_direction_kind[MAX_LIGHTS];
+// x: cosine of the inner cone angle, y: cosine of the outer cone angle
+uniform vec4 light_cone[MAX_LIGHTS];
+
+uniform vec4 base_color_factor;
+uniform float metallic_factor;
+uniform float roughness_factor;
+uniform vec3 emissive_factor;
+uniform float emissive_strength;
+uniform float normal_scale;
+uniform float occlusion_strength;
+uniform bool has_normal_tex;
+
+uniform sampler2D base_color_tex;
+// Roughness in green, metalness in blue
+uniform sampler2D metallic_roughness_tex;
+uniform sampler2D normal_tex;
+uniform sampler2D occlusion_tex;
+uniform sampler2D emissive_tex;
 
 layout(location = 0) out vec4 main_color;
 layout(location = 1) out vec4 bright_color;
+// The part of main_color that SSAO darkens
+layout(location = 2) out vec4 ambient_light;
+
+const float PI = 3.14159265359;
+
+// Trowbridge-Reitz GGX normal distribution
+float distribution_ggx(float n_dot_h, float roughness) {
+  float a = roughness * roughness;
+  float a2 = a * a;
+  float d = n_dot_h * n_dot_h * (a2 - 1.0) + 1.0;
+  return a2 / (PI * d * d);
+}
+
+// Smith's method with Schlick-GGX for each direction
+float geometry_smith(float n_dot_v, float n_dot_l, float roughness) {
+  float k = (roughness + 1.0) * (roughness + 1.0) / 8.0;
+  float g_v = n_dot_v / (n_dot_v * (1.0 - k) + k);
+  float g_l = n_dot_l / (n_dot_l * (1.0 - k) + k);
+  return g_v * g_l;
+}
+
+vec3 fresnel_schlick(float cos_theta, vec3 f0) {
+  return f0 + (1.0 - f0) * pow(1.0 - cos_theta, 5.0);
+}
+
+vec3 fresnel_schlick_roughness(float cos_theta, vec3 f0, float roughness) {
+  return f0 + (max(vec3(1.0 - roughness), f0) - f0) * pow(1.0 - cos_theta, 5.0);
+}
+
+// Karis' analytic fit of the split sum environment BRDF, returning the scale
+// and bias applied to f0
+vec2 environment_brdf(float n_dot_v, float roughness) {
+  const vec4 c0 = vec4(-1.0, -0.0275, -0.572, 0.022);
+  const vec4 c1 = vec4(1.0, 0.0425, 1.04, -0.04);
+  vec4 r = roughness * c0 + c1;
+  float a004 = min(r.x * r.x, exp2(-9.28 * n_dot_v)) * r.x + r.y;
+  return vec2(-1.04, 1.04) * a004 + r.zw;
+}
+
+vec3 specular_level(int level, vec3 direction) {
+  if (level == 0) return texture(specular_map_0, direction).rgb;
+  if (level == 1) return texture(specular_map_1, direction).rgb;
+  if (level == 2) return texture(specular_map_2, direction).rgb;
+  if (level == 3) return texture(specular_map_3, direction).rgb;
+  return texture(specular_map_4, direction).rgb;
+}
+
+// Prefiltered radiance, blended between the two nearest roughness levels
+vec3 prefiltered_radiance(vec3 direction, float roughness) {
+  float level = roughness * float(SPECULAR_LEVELS - 1);
+  int lower = int(floor(level));
+  int upper = min(lower + 1, SPECULAR_LEVELS - 1);
+  return mix(specular_level(lower, direction), specular_level(upper, direction),
+             level - float(lower));
+}
+
+// Smooth falloff to zero at the light's range, as in KHR_lights_punctual
+float range_attenuation(float distance, float range) {
+  float ratio = distance / range;
+  float window = clamp(1.0 - ratio * ratio * ratio * ratio, 0.0, 1.0);
+  return window * window / max(distance * distance, 1e-4);
+}
+
+// Outgoing radiance towards v for light arriving from l with the given
+// radiance, with the 1/PI of the BRDF folded into the light intensity
+vec3 shade_light(vec3 n, vec3 v, vec3 l, vec3 radiance, vec3 base_color, float metallic,
+                 float roughness) {
+  vec3 h = normalize(l + v);
+  float n_dot_l = max(dot(n, l), 0.0);
+  float n_dot_v = max(dot(n, v), 1e-4);
+  float n_dot_h = max(dot(n, h), 0.0);
+
+  vec3 f0 = mix(vec3(0.04), base_color, metallic);
+  vec3 f = fresnel_schlick(max(dot(h, v), 0.0), f0);
+  float d = distribution_ggx(n_dot_h, roughness);
+  float g = geometry_smith(n_dot_v, n_dot_l, roughness);
+
+  vec3 specular = d * g * f / (4.0 * n_dot_v * max(n_dot_l, 1e-4));
+  vec3 k_d = (vec3(1.0) - f) * (1.0 - metallic);
+  vec3 diffuse = k_d * base_color / PI;
+
+  return (diffuse + specular) * radiance * n_dot_l * PI;
+}
+
+// Fraction of the directional light reaching the fragment, averaged over a
+// square of shadow map texels for soft edges
+float shadow_visibility(vec3 n, vec3 l) {
+  vec4 light_clip = light_space_transform * vec4(v_world_pos, 1.0);
+  vec3 coords = light_clip.xyz / light_clip.w * 0.5 + 0.5;
+  if (!shadows || any(lessThan(coords, vec3(0.0))) || any(greaterThan(coords, vec3(1.0)))) {
+    return 1.0;
+  }
+
+  float bias = max(shadow_bias * (1.0 - dot(n, l)), shadow_bias * 0.1);
+  vec2 texel = 1.0 / vec2(textureSize(shadow_map, 0));
+  float visible = 0.0;
+  for (int x = -shadow_pcf_radius; x <= shadow_pcf_radius; ++x) {
+    for (int y = -shadow_pcf_radius; y <= shadow_pcf_radius; ++y) {
+      float depth = texture(shadow_map, coords.xy + vec2(x, y) * texel).r;
+      visible += coords.z - bias > depth ? 0.0 : 1.0;
+    }
+  }
+  float side = float(shadow_pcf_radius * 2 + 1);
+  return visible / (side * side);
+}
 
 void main() {
-  if (dot(v_color, vec4(0.2126, 0.7152, 0.0722, 0.0)) > 0.8) {
-    bright_color = v_color;
+  vec4 base_color = v_color * base_color_factor * texture(base_color_tex, v_uv);
+  vec4 metallic_roughness = texture(metallic_roughness_tex, v_uv);
+  float metallic = clamp(metallic_factor * metallic_roughness.b, 0.0, 1.0);
+  float roughness = clamp(roughness_factor * metallic_roughness.g, 0.04, 1.0);
+  float occlusion = mix(1.0, texture(occlusion_tex, v_uv).r, occlusion_strength);
+
+  vec3 n = normalize(v_normal);
+  if (has_normal_tex) {
+    vec3 tangent_normal = texture(normal_tex, v_uv).xyz * 2.0 - 1.0;
+    tangent_normal.xy *= normal_scale;
+    // Bitangent from the tangent's handedness, as glTF defines it
+    vec3 t = normalize(v_tangent.xyz - n * dot(n, v_tangent.xyz));
+    vec3 b = cross(n, t) * v_tangent.w;
+    n = normalize(mat3(t, b, n) * tangent_normal);
+  }
+  vec3 v = normalize(camera_position - v_world_pos);
+
+  // The 1/PI in the BRDF is cancelled by treating light_color as irradiance
+  // from a light facing the surface head on
+  vec3 sun = normalize(-light_direction);
+  vec3 lit = shade_light(n, v, sun, light_color, base_color.rgb, metallic, roughness)
+    * shadow_visibility(normalize(v_normal), sun);
+
+  for (int i = 0; i < min(light_count, MAX_LIGHTS); ++i) {
+    vec3 to_light = light_position_range[i].xyz - v_world_pos;
+    float distance = length(to_light);
+    vec3 l = to_light / max(distance, 1e-4);
+    float attenuation = range_attenuation(distance, light_position_range[i].w);
+
+    if (light_direction_kind[i].w > 0.5) {
+      float cos_angle = dot(normalize(light_direction_kind[i].xyz), -l);
+      attenuation *= smoothstep(light_cone[i].y, light_cone[i].x, cos_angle);
+    }
+
+    if (attenuation > 0.0) {
+      lit += shade_light(n, v, l, light_color_intensity[i].rgb * attenuation, base_color.rgb,
+                         metallic, roughness);
+    }
+  }
+
+  vec3 ambient;
+  if (has_environment) {
+    float n_dot_v = max(dot(n, v), 1e-4);
+    vec3 f0 = mix(vec3(0.04), base_color.rgb, metallic);
+    vec3 f = fresnel_schlick_roughness(n_dot_v, f0, roughness);
+    vec3 k_d = (vec3(1.0) - f) * (1.0 - metallic);
+    vec3 diffuse = k_d * base_color.rgb * texture(irradiance_map, n).rgb;
+
+    vec2 brdf = environment_brdf(n_dot_v, roughness);
+    vec3 specular = prefiltered_radiance(reflect(-v, n), roughness) * (f0 * brdf.x + brdf.y);
+    ambient = (diffuse + specular) * occlusion * environment_strength;
+  } else {
+    ambient = ambient_color * base_color.rgb * occlusion;
+  }
+  lit += ambient;
+
+  vec3 emissive = emissive_factor * texture(emissive_tex, v_uv).rgb * emissive_strength;
+  lit += emissive;
+  vec4 shaded = vec4(lit, base_color.a);
+
+  if (dot(shaded, vec4(0.2126, 0.7152, 0.0722, 0.0)) > bloom_threshold) {
+    bright_color = shaded;
   } else {
-    bright_color = vec4(0.0);
+    // Emissive surfaces always glow, regardless of the threshold
+    bright_color = vec4(emissive, 0.0);
   }
-  main_color = v_color;
+  main_color = shaded;
+  ambient_light = vec4(ambient, 0.0);
 }
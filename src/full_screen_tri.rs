@@ -16,3 +16,26 @@ void main() {
   v_pos = pos;
 }
 "#;
+
+/// Like [`VS`], but only covers `rect`, given as the bottom left corner and
+/// the size in the unit square covering the render target.
+pub const RECT_VS: &'static str = r#"
+out vec2 v_pos;
+
+uniform vec4 rect;
+
+const vec2[6] POS = vec2[](
+  vec2(0.0, 1.0),
+  vec2(0.0, 0.0),
+  vec2(1.0, 1.0),
+  vec2(0.0, 0.0),
+  vec2(1.0, 1.0),
+  vec2(1.0, 0.0)
+);
+
+void main() {
+  vec2 pos = POS[gl_VertexID];
+  gl_Position = vec4(((rect.xy + pos * rect.zw) * 2.0) - vec2(1.0), 0.0, 1.0);
+  v_pos = pos;
+}
+"#;
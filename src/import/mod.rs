@@ -0,0 +1,56 @@
+//! Importers for mesh formats other than glTF
+//!
+//! Importers only read the triangles and materials of a file. Welding
+//! vertices and generating missing normals is left to the caller, so every
+//! format ends up with the same vertex layout as glTF assets.
+use std::io;
+use std::path::Path;
+
+use crate::material::Material;
+use crate::texture::TextureSource;
+
+pub mod obj;
+pub mod ply;
+
+/// One corner of a triangle
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Corner {
+    pub position: [f32; 3],
+    pub uv: [f32; 2],
+    /// Normal stored in the file, if it has any
+    pub normal: Option<[f32; 3]>,
+    /// Linear color stored in the file, if it has any
+    pub color: Option<[f32; 4]>,
+}
+
+/// Triangles sharing a material, three corners each
+#[derive(Clone, Debug, Default)]
+pub struct Group {
+    pub material: usize,
+    pub corners: Vec<Corner>,
+}
+
+/// Everything read from a mesh file. Materials and textures are laid out the
+/// same way as those loaded from glTF.
+#[derive(Default)]
+pub struct ImportedMesh {
+    pub groups: Vec<Group>,
+    pub materials: Vec<Material>,
+    pub textures: Vec<TextureSource>,
+    pub images: Vec<gltf::image::Data>,
+}
+
+/// Read an OBJ or PLY file, picking the format from the extension. Returns
+/// `None` for any other extension.
+pub fn read(path: &Path) -> Option<io::Result<ImportedMesh>> {
+    let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+    match extension.as_str() {
+        "obj" => Some(obj::read(path)),
+        "ply" => Some(ply::read(path)),
+        _ => None,
+    }
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
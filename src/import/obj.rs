@@ -0,0 +1,555 @@
+//! Wavefront OBJ meshes, with materials from MTL libraries
+//!
+//! Polygons are triangulated as fans. Vertex colors written after the
+//! position (`v x y z r g b`), a common extension, are read as sRGB. MTL bump
+//! maps are height maps, which aren't supported, so only `norm` and
+//! `map_Bump -bm` (the way Blender exports tangent space normal maps) are
+//! used as normal maps.
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::path::Path;
+
+use luminance::texture::{MagFilter, MinFilter, Sampler, Wrap};
+
+use super::{invalid, Corner, Group, ImportedMesh};
+use crate::material::{Material, TextureSlot};
+use crate::texture::{srgb_to_linear, TextureSource};
+
+pub fn read(path: &Path) -> io::Result<ImportedMesh> {
+    parse(BufReader::new(File::open(path)?), path, |library| {
+        File::open(library).map(BufReader::new)
+    })
+}
+
+/// Parse the OBJ file at `path`, read from `reader`. Material libraries are
+/// read from what `open` returns for their path.
+fn parse<R, L, F>(reader: R, path: &Path, mut open: F) -> io::Result<ImportedMesh>
+where
+    R: BufRead,
+    L: BufRead,
+    F: FnMut(&Path) -> io::Result<L>,
+{
+    let directory = path.parent().unwrap_or_else(|| Path::new(""));
+    let mut mesh = ImportedMesh::default();
+    // Faces before any `usemtl`, or naming a missing material, use the
+    // default material at index 0
+    mesh.materials.push(Material::default());
+    let mut material_names = HashMap::new();
+    let mut texture_names = HashMap::new();
+
+    let mut positions: Vec<[f32; 3]> = Vec::new();
+    let mut colors: Vec<Option<[f32; 4]>> = Vec::new();
+    let mut uvs: Vec<[f32; 2]> = Vec::new();
+    let mut normals: Vec<[f32; 3]> = Vec::new();
+    let mut group = Group::default();
+
+    for (number, line) in reader.lines().enumerate() {
+        let line = line?;
+        let error = |message: &str| invalid(format!("line {}: {}", number + 1, message));
+        let mut words = line.split_whitespace();
+        let floats = |words: std::str::SplitWhitespace| -> io::Result<Vec<f32>> {
+            words
+                .map(|w| {
+                    w.parse()
+                        .map_err(|_| error(&format!("invalid number {:?}", w)))
+                })
+                .collect()
+        };
+
+        match words.next() {
+            Some("v") => {
+                let v = floats(words)?;
+                if v.len() < 3 {
+                    return Err(error("vertex with fewer than 3 coordinates"));
+                }
+                positions.push([v[0], v[1], v[2]]);
+                colors.push(if v.len() >= 6 {
+                    Some([
+                        srgb_to_linear(v[3]),
+                        srgb_to_linear(v[4]),
+                        srgb_to_linear(v[5]),
+                        1.0,
+                    ])
+                } else {
+                    None
+                });
+            }
+            Some("vt") => {
+                let vt = floats(words)?;
+                // OBJ puts the texture origin at the bottom left, glTF at the
+                // top left
+                uvs.push([
+                    vt.get(0).copied().unwrap_or(0.0),
+                    1.0 - vt.get(1).copied().unwrap_or(0.0),
+                ]);
+            }
+            Some("vn") => {
+                let vn = floats(words)?;
+                if vn.len() < 3 {
+                    return Err(error("normal with fewer than 3 coordinates"));
+                }
+                normals.push([vn[0], vn[1], vn[2]]);
+            }
+            Some("f") => {
+                let corners = words
+                    .map(|word| {
+                        corner(word, &positions, &colors, &uvs, &normals)
+                            .ok_or_else(|| error(&format!("invalid face vertex {:?}", word)))
+                    })
+                    .collect::<io::Result<Vec<_>>>()?;
+                for i in 2..corners.len() {
+                    group
+                        .corners
+                        .extend_from_slice(&[corners[0], corners[i - 1], corners[i]]);
+                }
+            }
+            Some("usemtl") => {
+                let name = material_name(words);
+                let material = material_names.get(&name).copied().unwrap_or_else(|| {
+                    eprintln!("{}: unknown material {:?}", path.display(), name);
+                    0
+                });
+                if !group.corners.is_empty() {
+                    mesh.groups.push(group);
+                }
+                group = Group {
+                    material,
+                    corners: Vec::new(),
+                };
+            }
+            Some("mtllib") => {
+                let library_error = |library: &Path, e: io::Error| {
+                    io::Error::new(
+                        e.kind(),
+                        format!("material library {}: {}", library.display(), e),
+                    )
+                };
+                // A line can list several libraries, but a single name can
+                // also contain spaces, so try the whole line as one first
+                let name = material_name(words.clone());
+                let whole = directory.join(&name);
+                let libraries = match open(&whole) {
+                    Ok(reader) => vec![(whole, reader)],
+                    Err(_) if name.contains(' ') => words
+                        .map(|library| {
+                            let library = directory.join(library);
+                            let reader = open(&library).map_err(|e| library_error(&library, e))?;
+                            Ok((library, reader))
+                        })
+                        .collect::<io::Result<_>>()?,
+                    Err(e) => return Err(library_error(&whole, e)),
+                };
+                for (library, reader) in libraries {
+                    read_mtl(
+                        reader,
+                        &library,
+                        &mut mesh,
+                        &mut material_names,
+                        &mut texture_names,
+                    )?;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    if !group.corners.is_empty() {
+        mesh.groups.push(group);
+    }
+    Ok(mesh)
+}
+
+/// Material names may contain spaces, so the name is the rest of the line
+fn material_name(words: std::str::SplitWhitespace) -> String {
+    words.collect::<Vec<_>>().join(" ")
+}
+
+/// A face vertex such as `3`, `3/1`, `3//2` or `3/1/2`, with negative
+/// indices counting back from the last element read
+fn corner(
+    word: &str,
+    positions: &[[f32; 3]],
+    colors: &[Option<[f32; 4]>],
+    uvs: &[[f32; 2]],
+    normals: &[[f32; 3]],
+) -> Option<Corner> {
+    let resolve = |index: &str, len: usize| -> Option<usize> {
+        let index: i64 = index.parse().ok()?;
+        let resolved = if index < 0 {
+            len as i64 + index
+        } else {
+            index - 1
+        };
+        if resolved >= 0 && (resolved as usize) < len {
+            Some(resolved as usize)
+        } else {
+            None
+        }
+    };
+
+    let mut indices = word.split('/');
+    let position = resolve(indices.next()?, positions.len())?;
+    let uv = match indices.next() {
+        Some(index) if !index.is_empty() => uvs[resolve(index, uvs.len())?],
+        _ => [0.0, 0.0],
+    };
+    let normal = match indices.next() {
+        Some(index) if !index.is_empty() => Some(normals[resolve(index, normals.len())?]),
+        _ => None,
+    };
+
+    Some(Corner {
+        position: positions[position],
+        uv,
+        normal,
+        color: colors[position],
+    })
+}
+
+fn read_mtl<R: BufRead>(
+    reader: R,
+    path: &Path,
+    mesh: &mut ImportedMesh,
+    material_names: &mut HashMap<String, usize>,
+    texture_names: &mut HashMap<String, usize>,
+) -> io::Result<()> {
+    let directory = path.parent().unwrap_or_else(|| Path::new(""));
+    let mut current: Option<usize> = None;
+    for (number, line) in reader.lines().enumerate() {
+        let line = line?;
+        let error = |message: &str| {
+            invalid(format!(
+                "{} line {}: {}",
+                path.display(),
+                number + 1,
+                message
+            ))
+        };
+        let mut words = line.split_whitespace();
+        let keyword = match words.next() {
+            Some(keyword) => keyword,
+            None => continue,
+        };
+
+        if keyword == "newmtl" {
+            let name = material_name(words);
+            mesh.materials.push(Material::default());
+            material_names.insert(name, mesh.materials.len() - 1);
+            current = Some(mesh.materials.len() - 1);
+            continue;
+        }
+        let material = match current {
+            Some(index) => &mut mesh.materials[index],
+            None => continue,
+        };
+
+        let rest: Vec<_> = words.collect();
+        let floats = || -> io::Result<Vec<f32>> {
+            rest.iter()
+                .map(|w| {
+                    w.parse()
+                        .map_err(|_| error(&format!("invalid number {:?}", w)))
+                })
+                .collect()
+        };
+        // Texture options such as `-bm 1.0` come before the file name, which
+        // is always last
+        let texture = |mesh_textures: &mut Vec<TextureSource>,
+                       images: &mut Vec<gltf::image::Data>,
+                       texture_names: &mut HashMap<String, usize>|
+         -> Option<TextureSlot> {
+            let name = *rest.last()?;
+            if let Some(&texture) = texture_names.get(name) {
+                return Some(TextureSlot {
+                    texture,
+                    tex_coord: 0,
+                });
+            }
+            let image_path = directory.join(name);
+            match load_image(&image_path) {
+                Ok(image) => {
+                    images.push(image);
+                    mesh_textures.push(TextureSource {
+                        image: images.len() - 1,
+                        sampler: Sampler {
+                            wrap_s: Wrap::Repeat,
+                            wrap_t: Wrap::Repeat,
+                            mag_filter: MagFilter::Linear,
+                            min_filter: MinFilter::LinearMipmapLinear,
+                            ..Sampler::default()
+                        },
+                    });
+                    texture_names.insert(name.to_string(), mesh_textures.len() - 1);
+                    Some(TextureSlot {
+                        texture: mesh_textures.len() - 1,
+                        tex_coord: 0,
+                    })
+                }
+                Err(e) => {
+                    eprintln!("Failed to load texture {}: {}", image_path.display(), e);
+                    None
+                }
+            }
+        };
+
+        match keyword {
+            "Kd" => {
+                let kd = floats()?;
+                if kd.len() >= 3 {
+                    material.base_color_factor[..3].copy_from_slice(&kd[..3]);
+                }
+            }
+            "d" => {
+                if let Some(&d) = floats()?.first() {
+                    material.base_color_factor[3] = d;
+                }
+            }
+            "Tr" => {
+                if let Some(&tr) = floats()?.first() {
+                    material.base_color_factor[3] = 1.0 - tr;
+                }
+            }
+            "Ke" => {
+                let ke = floats()?;
+                if ke.len() >= 3 {
+                    material.emissive_factor.copy_from_slice(&ke[..3]);
+                }
+            }
+            // Blinn-Phong exponent to the roughness with a similar highlight
+            "Ns" => {
+                if let Some(&ns) = floats()?.first() {
+                    material.roughness_factor = (2.0 / (ns.max(0.0) + 2.0)).sqrt();
+                }
+            }
+            // The PBR extension to MTL
+            "Pr" => {
+                if let Some(&pr) = floats()?.first() {
+                    material.roughness_factor = pr;
+                }
+            }
+            "Pm" => {
+                if let Some(&pm) = floats()?.first() {
+                    material.metallic_factor = pm;
+                }
+            }
+            "map_Kd" => {
+                material.base_color_texture =
+                    texture(&mut mesh.textures, &mut mesh.images, texture_names)
+            }
+            "map_Ke" => {
+                material.emissive_texture =
+                    texture(&mut mesh.textures, &mut mesh.images, texture_names)
+            }
+            "norm" => {
+                material.normal_texture =
+                    texture(&mut mesh.textures, &mut mesh.images, texture_names)
+            }
+            "map_Bump" | "map_bump" | "bump" => {
+                if let Some(option) = rest.iter().position(|&w| w == "-bm") {
+                    material.normal_texture =
+                        texture(&mut mesh.textures, &mut mesh.images, texture_names);
+                    if let Some(scale) = rest.get(option + 1).and_then(|s| s.parse().ok()) {
+                        material.normal_scale = scale;
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    Ok(())
+}
+
+fn load_image(path: &Path) -> image::ImageResult<gltf::image::Data> {
+    let image = image::open(path)?.to_rgba();
+    let (width, height) = image.dimensions();
+    Ok(gltf::image::Data {
+        pixels: image.into_raw(),
+        format: gltf::image::Format::R8G8B8A8,
+        width,
+        height,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::Cursor;
+
+    const MTL: &str = "\
+newmtl red paint
+Kd 1 0 0
+Ns 0
+newmtl blue
+Kd 0 0 1
+d 0.5
+map_Bump height.png
+";
+
+    const GREEN_MTL: &str = "\
+newmtl green
+Kd 0 1 0
+";
+
+    /// Parse `obj`, with `materials.mtl` next to it holding `MTL`, and both
+    /// `my materials.mtl` and `green.mtl` holding `GREEN_MTL`
+    fn parse_str(obj: &str) -> io::Result<ImportedMesh> {
+        parse(obj.as_bytes(), Path::new("mesh.obj"), |library| {
+            if library == Path::new("materials.mtl") {
+                Ok(Cursor::new(MTL.as_bytes()))
+            } else if library == Path::new("my materials.mtl") || library == Path::new("green.mtl")
+            {
+                Ok(Cursor::new(GREEN_MTL.as_bytes()))
+            } else {
+                Err(io::Error::new(io::ErrorKind::NotFound, "no such file"))
+            }
+        })
+    }
+
+    const VERTICES: &str = "\
+v 0 0 0
+v 1 0 0
+v 1 1 0
+v 0 1 0
+vt 0 0
+vt 1 0.25
+vn 0 0 1
+";
+
+    fn positions(corners: &[Corner]) -> Vec<[f32; 3]> {
+        corners.iter().map(|c| c.position).collect()
+    }
+
+    #[test]
+    fn face_vertex_forms() {
+        let mesh = parse_str(&format!(
+            "{}f 1 2 3\nf 1/1 2/2 3/1\nf 1//1 2//1 3//1\nf 1/1/1 2/2/1 3/1/1\n",
+            VERTICES
+        ))
+        .unwrap();
+        assert_eq!(mesh.groups.len(), 1);
+        let corners = &mesh.groups[0].corners;
+        assert_eq!(corners.len(), 12);
+        for triangle in corners.chunks(3) {
+            assert_eq!(
+                positions(triangle),
+                vec![[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [1.0, 1.0, 0.0]]
+            );
+        }
+
+        // v
+        assert_eq!(corners[1].uv, [0.0, 0.0]);
+        assert_eq!(corners[1].normal, None);
+        // v/vt, flipped to a top left origin
+        assert_eq!(corners[4].uv, [1.0, 0.75]);
+        assert_eq!(corners[4].normal, None);
+        // v//vn
+        assert_eq!(corners[7].uv, [0.0, 0.0]);
+        assert_eq!(corners[7].normal, Some([0.0, 0.0, 1.0]));
+        // v/vt/vn
+        assert_eq!(corners[10].uv, [1.0, 0.75]);
+        assert_eq!(corners[10].normal, Some([0.0, 0.0, 1.0]));
+        assert_eq!(corners[10].color, None);
+    }
+
+    #[test]
+    fn negative_indices() {
+        let mesh = parse_str(&format!("{}f -4/-2/-1 -3/-1/-1 -1/-2/-1\n", VERTICES)).unwrap();
+        let corners = &mesh.groups[0].corners;
+        assert_eq!(
+            positions(corners),
+            vec![[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]]
+        );
+        assert_eq!(corners[1].uv, [1.0, 0.75]);
+        assert_eq!(corners[2].normal, Some([0.0, 0.0, 1.0]));
+    }
+
+    #[test]
+    fn quads_are_fans() {
+        let mesh = parse_str(&format!("{}f 1 2 3 4\n", VERTICES)).unwrap();
+        let p = |i: usize| {
+            [
+                [0.0, 0.0, 0.0],
+                [1.0, 0.0, 0.0],
+                [1.0, 1.0, 0.0],
+                [0.0, 1.0, 0.0],
+            ][i]
+        };
+        assert_eq!(
+            positions(&mesh.groups[0].corners),
+            vec![p(0), p(1), p(2), p(0), p(2), p(3)]
+        );
+    }
+
+    #[test]
+    fn vertex_colors() {
+        let mesh = parse_str("v 0 0 0 1 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 3\n").unwrap();
+        let corners = &mesh.groups[0].corners;
+        let red = corners[0].color.unwrap();
+        assert!((red[0] - 1.0).abs() < 1e-5);
+        assert_eq!(red[1..], [0.0, 0.0, 1.0]);
+        assert_eq!(corners[1].color, None);
+    }
+
+    #[test]
+    fn materials() {
+        let mesh = parse_str(&format!(
+            "mtllib materials.mtl\n{v}f 1 2 3\n\
+             usemtl red paint\nf 1 2 3\nf 1 3 4\n\
+             usemtl blue\nf 1 2 3\n\
+             usemtl missing\nf 1 2 3\n",
+            v = VERTICES
+        ))
+        .unwrap();
+
+        let groups: Vec<_> = mesh
+            .groups
+            .iter()
+            .map(|g| (g.material, g.corners.len()))
+            .collect();
+        assert_eq!(groups, vec![(0, 3), (1, 6), (2, 3), (0, 3)]);
+
+        assert_eq!(mesh.materials.len(), 3);
+        assert_eq!(mesh.materials[0], Material::default());
+        assert_eq!(mesh.materials[1].base_color_factor, [1.0, 0.0, 0.0, 1.0]);
+        assert_eq!(mesh.materials[1].roughness_factor, 1.0);
+        assert_eq!(mesh.materials[2].base_color_factor, [0.0, 0.0, 1.0, 0.5]);
+        // A height map, not a normal map
+        assert_eq!(mesh.materials[2].normal_texture, None);
+        assert!(mesh.textures.is_empty());
+    }
+
+    #[test]
+    fn material_library_names() {
+        let obj = |mtllib: &str| format!("mtllib {}\n{}usemtl green\nf 1 2 3\n", mtllib, VERTICES);
+
+        let mesh = parse_str(&obj("my materials.mtl")).unwrap();
+        assert_eq!(mesh.materials.len(), 2);
+        assert_eq!(mesh.materials[1].base_color_factor, [0.0, 1.0, 0.0, 1.0]);
+        assert_eq!(mesh.groups[0].material, 1);
+
+        // Several libraries on one line
+        let mesh = parse_str(&obj("materials.mtl green.mtl")).unwrap();
+        assert_eq!(mesh.materials.len(), 4);
+        assert_eq!(mesh.groups[0].material, 3);
+
+        let error = parse_str(&obj("materials.mtl other.mtl")).err().unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::NotFound);
+    }
+
+    #[test]
+    fn bad_index() {
+        for face in &["f 1 2 5", "f 0 1 2", "f 1 2 -5", "f 1/3 2 3", "f 1 2 x"] {
+            let error = parse_str(&format!("{}{}\n", VERTICES, face)).err().unwrap();
+            assert_eq!(error.kind(), io::ErrorKind::InvalidData, "{}", face);
+        }
+    }
+
+    #[test]
+    fn missing_material_library() {
+        let error = parse_str("mtllib other.mtl\n").err().unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::NotFound);
+    }
+}
@@ -0,0 +1,496 @@
+//! Stanford PLY meshes, in ASCII or binary encoding
+//!
+//! Reads vertex positions, normals, texture coordinates and colors, and
+//! polygonal faces, which are triangulated as fans. Elements other than
+//! vertices and faces are skipped. Integer colors are read as sRGB, floating
+//! point ones as linear.
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read};
+use std::path::Path;
+
+use super::{invalid, Corner, Group, ImportedMesh};
+use crate::material::Material;
+use crate::texture::srgb_to_linear;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+enum Encoding {
+    Ascii,
+    LittleEndian,
+    BigEndian,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+enum Scalar {
+    I8,
+    U8,
+    I16,
+    U16,
+    I32,
+    U32,
+    F32,
+    F64,
+}
+
+impl Scalar {
+    fn parse(name: &str) -> Option<Self> {
+        Some(match name {
+            "char" | "int8" => Scalar::I8,
+            "uchar" | "uint8" => Scalar::U8,
+            "short" | "int16" => Scalar::I16,
+            "ushort" | "uint16" => Scalar::U16,
+            "int" | "int32" => Scalar::I32,
+            "uint" | "uint32" => Scalar::U32,
+            "float" | "float32" => Scalar::F32,
+            "double" | "float64" => Scalar::F64,
+            _ => return None,
+        })
+    }
+
+    fn size(self) -> usize {
+        match self {
+            Scalar::I8 | Scalar::U8 => 1,
+            Scalar::I16 | Scalar::U16 => 2,
+            Scalar::I32 | Scalar::U32 | Scalar::F32 => 4,
+            Scalar::F64 => 8,
+        }
+    }
+
+    /// The largest value of integer types, used to normalize colors
+    fn max(self) -> Option<f64> {
+        match self {
+            Scalar::U8 => Some(255.0),
+            Scalar::U16 => Some(65535.0),
+            Scalar::I8 => Some(127.0),
+            Scalar::I16 => Some(32767.0),
+            Scalar::I32 => Some(2_147_483_647.0),
+            Scalar::U32 => Some(4_294_967_295.0),
+            Scalar::F32 | Scalar::F64 => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+enum Property {
+    Scalar(String, Scalar),
+    /// A count of type `.1` followed by that many values of type `.2`
+    List(String, Scalar, Scalar),
+}
+
+#[derive(Clone, Debug)]
+struct Element {
+    name: String,
+    count: usize,
+    properties: Vec<Property>,
+}
+
+/// Reads values in the file's encoding
+struct Values<R> {
+    reader: R,
+    encoding: Encoding,
+    /// Remaining words of the current line, for ASCII files
+    words: Vec<String>,
+}
+
+impl<R: BufRead> Values<R> {
+    fn next(&mut self, scalar: Scalar) -> io::Result<f64> {
+        let mut bytes = [0u8; 8];
+        let bytes = &mut bytes[..scalar.size()];
+        match self.encoding {
+            Encoding::Ascii => {
+                while self.words.is_empty() {
+                    let mut line = String::new();
+                    if self.reader.read_line(&mut line)? == 0 {
+                        return Err(invalid("unexpected end of file".to_string()));
+                    }
+                    self.words = line.split_whitespace().rev().map(String::from).collect();
+                }
+                let word = self.words.pop().unwrap_or_default();
+                return word
+                    .parse()
+                    .map_err(|_| invalid(format!("invalid number {:?}", word)));
+            }
+            Encoding::LittleEndian => self.reader.read_exact(bytes)?,
+            Encoding::BigEndian => {
+                self.reader.read_exact(bytes)?;
+                bytes.reverse();
+            }
+        }
+
+        // Little endian from here on
+        let b = bytes;
+        Ok(match scalar {
+            Scalar::I8 => b[0] as i8 as f64,
+            Scalar::U8 => b[0] as f64,
+            Scalar::I16 => i16::from_le_bytes([b[0], b[1]]) as f64,
+            Scalar::U16 => u16::from_le_bytes([b[0], b[1]]) as f64,
+            Scalar::I32 => i32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64,
+            Scalar::U32 => u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64,
+            Scalar::F32 => f32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64,
+            Scalar::F64 => f64::from_le_bytes([b[0], b[1], b[2], b[3], b[4], b[5], b[6], b[7]]),
+        })
+    }
+}
+
+pub fn read(path: &Path) -> io::Result<ImportedMesh> {
+    parse(BufReader::new(File::open(path)?))
+}
+
+fn parse<R: BufRead>(mut reader: R) -> io::Result<ImportedMesh> {
+    let (encoding, elements) = read_header(&mut reader)?;
+    let mut values = Values {
+        reader,
+        encoding,
+        words: Vec::new(),
+    };
+
+    let mut vertices: Vec<Corner> = Vec::new();
+    let mut corners = Vec::new();
+    for element in &elements {
+        for _ in 0..element.count {
+            match element.name.as_str() {
+                "vertex" => vertices.push(read_vertex(&mut values, &element.properties)?),
+                "face" => {
+                    let face = read_face(&mut values, &element.properties)?;
+                    let corner = |i: u32| {
+                        vertices
+                            .get(i as usize)
+                            .copied()
+                            .ok_or_else(|| invalid(format!("face refers to missing vertex {}", i)))
+                    };
+                    for i in 2..face.len() {
+                        corners.push(corner(face[0])?);
+                        corners.push(corner(face[i - 1])?);
+                        corners.push(corner(face[i])?);
+                    }
+                }
+                _ => {
+                    for property in &element.properties {
+                        skip_property(&mut values, property)?;
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(ImportedMesh {
+        groups: vec![Group {
+            material: 0,
+            corners,
+        }],
+        materials: vec![Material::default()],
+        textures: Vec::new(),
+        images: Vec::new(),
+    })
+}
+
+fn read_header<R: BufRead>(reader: &mut R) -> io::Result<(Encoding, Vec<Element>)> {
+    let mut line = String::new();
+    let mut next_line = |reader: &mut R| -> io::Result<Vec<String>> {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 {
+            return Err(invalid("unexpected end of header".to_string()));
+        }
+        Ok(line.split_whitespace().map(String::from).collect())
+    };
+
+    if next_line(reader)? != ["ply"] {
+        return Err(invalid("not a PLY file".to_string()));
+    }
+
+    let mut encoding = None;
+    let mut elements: Vec<Element> = Vec::new();
+    loop {
+        let words = next_line(reader)?;
+        let words: Vec<&str> = words.iter().map(String::as_str).collect();
+        let scalar = |name: &str| {
+            Scalar::parse(name).ok_or_else(|| invalid(format!("unknown type {:?}", name)))
+        };
+        match words.as_slice() {
+            ["format", format, _] => {
+                encoding = Some(match *format {
+                    "ascii" => Encoding::Ascii,
+                    "binary_little_endian" => Encoding::LittleEndian,
+                    "binary_big_endian" => Encoding::BigEndian,
+                    _ => return Err(invalid(format!("unknown format {:?}", format))),
+                })
+            }
+            ["element", name, count] => elements.push(Element {
+                name: name.to_string(),
+                count: count
+                    .parse()
+                    .map_err(|_| invalid(format!("invalid element count {:?}", count)))?,
+                properties: Vec::new(),
+            }),
+            ["property", "list", count, value, name] => elements
+                .last_mut()
+                .ok_or_else(|| invalid("property before any element".to_string()))?
+                .properties
+                .push(Property::List(
+                    name.to_string(),
+                    scalar(count)?,
+                    scalar(value)?,
+                )),
+            ["property", value, name] => elements
+                .last_mut()
+                .ok_or_else(|| invalid("property before any element".to_string()))?
+                .properties
+                .push(Property::Scalar(name.to_string(), scalar(value)?)),
+            ["end_header"] => break,
+            _ => {}
+        }
+    }
+
+    let encoding = encoding.ok_or_else(|| invalid("missing format line".to_string()))?;
+    Ok((encoding, elements))
+}
+
+fn read_vertex<R: BufRead>(values: &mut Values<R>, properties: &[Property]) -> io::Result<Corner> {
+    let mut position = [0.0; 3];
+    let mut uv = [0.0; 2];
+    let mut normal = [0.0; 3];
+    let mut has_normal = false;
+    let mut color = [1.0; 4];
+    let mut has_color = false;
+
+    for property in properties {
+        let (name, scalar) = match property {
+            Property::Scalar(name, scalar) => (name.as_str(), *scalar),
+            Property::List(..) => {
+                skip_property(values, property)?;
+                continue;
+            }
+        };
+        let value = values.next(scalar)?;
+        let channel = |value: f64| match scalar.max() {
+            Some(max) => srgb_to_linear((value / max) as f32),
+            None => value as f32,
+        };
+        match name {
+            "x" => position[0] = value as f32,
+            "y" => position[1] = value as f32,
+            "z" => position[2] = value as f32,
+            "nx" => {
+                normal[0] = value as f32;
+                has_normal = true;
+            }
+            "ny" => normal[1] = value as f32,
+            "nz" => normal[2] = value as f32,
+            "s" | "u" | "texture_u" => uv[0] = value as f32,
+            "t" | "v" | "texture_v" => uv[1] = 1.0 - value as f32,
+            "red" | "diffuse_red" => {
+                color[0] = channel(value);
+                has_color = true;
+            }
+            "green" | "diffuse_green" => color[1] = channel(value),
+            "blue" | "diffuse_blue" => color[2] = channel(value),
+            // Alpha is coverage, not a color, so it stays linear
+            "alpha" => color[3] = scalar.max().map_or(value, |max| value / max) as f32,
+            _ => {}
+        }
+    }
+
+    Ok(Corner {
+        position,
+        uv,
+        normal: if has_normal { Some(normal) } else { None },
+        color: if has_color { Some(color) } else { None },
+    })
+}
+
+fn read_face<R: BufRead>(values: &mut Values<R>, properties: &[Property]) -> io::Result<Vec<u32>> {
+    let mut face = Vec::new();
+    for property in properties {
+        match property {
+            Property::List(name, count, value)
+                if name == "vertex_indices" || name == "vertex_index" =>
+            {
+                let count = values.next(*count)? as usize;
+                for _ in 0..count {
+                    // Casting would quietly turn these into other vertices
+                    let index = values.next(*value)?;
+                    if index < 0.0 || index.fract() != 0.0 || index > f64::from(u32::max_value()) {
+                        return Err(invalid(format!("face refers to missing vertex {}", index)));
+                    }
+                    face.push(index as u32);
+                }
+            }
+            _ => skip_property(values, property)?,
+        }
+    }
+    Ok(face)
+}
+
+fn skip_property<R: BufRead>(values: &mut Values<R>, property: &Property) -> io::Result<()> {
+    match property {
+        Property::Scalar(_, scalar) => {
+            values.next(*scalar)?;
+        }
+        Property::List(_, count, value) => {
+            let count = values.next(*count)? as usize;
+            for _ in 0..count {
+                values.next(*value)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HEADER: &str = "element vertex 4
+property float x
+property float y
+property float z
+property uchar red
+property uchar green
+property uchar blue
+element face 1
+property list uchar int vertex_indices
+end_header
+";
+
+    const POSITIONS: [[f32; 3]; 4] = [
+        [0.0, 0.0, 0.0],
+        [1.0, 0.0, 0.0],
+        [1.0, 1.0, 0.0],
+        [0.0, 1.0, 0.0],
+    ];
+    const COLORS: [[u8; 3]; 4] = [[255, 0, 0], [0, 255, 0], [0, 0, 255], [255, 255, 255]];
+
+    fn assert_color(color: Option<[f32; 4]>, expected: [u8; 3]) {
+        let color = color.expect("vertex without a color");
+        for (&channel, &expected) in color.iter().zip(&expected) {
+            assert!((channel - expected as f32 / 255.0).abs() < 1e-5);
+        }
+        assert_eq!(color[3], 1.0);
+    }
+
+    /// The quad of the fixtures, as the corners of two triangles
+    fn assert_quad(mesh: &ImportedMesh) {
+        assert_eq!(mesh.groups.len(), 1);
+        let corners = &mesh.groups[0].corners;
+        assert_eq!(corners.len(), 6);
+        for (corner, &index) in corners.iter().zip(&[0, 1, 2, 0, 2, 3]) {
+            assert_eq!(corner.position, POSITIONS[index]);
+            assert_color(corner.color, COLORS[index]);
+            assert_eq!(corner.normal, None);
+        }
+    }
+
+    /// A binary file with the fixture's header, each value converted with
+    /// `f32_bytes` and `i32_bytes`
+    fn binary(
+        format: &str,
+        f32_bytes: fn(f32) -> [u8; 4],
+        i32_bytes: fn(i32) -> [u8; 4],
+    ) -> Vec<u8> {
+        let mut file = format!("ply\nformat {} 1.0\n{}", format, HEADER).into_bytes();
+        for (position, color) in POSITIONS.iter().zip(&COLORS) {
+            for &p in position {
+                file.extend_from_slice(&f32_bytes(p));
+            }
+            file.extend_from_slice(color);
+        }
+        file.push(4);
+        for i in 0..4 {
+            file.extend_from_slice(&i32_bytes(i));
+        }
+        file
+    }
+
+    #[test]
+    fn ascii() {
+        let file = format!(
+            "ply\nformat ascii 1.0\ncomment a quad\n{}\
+             0 0 0 255 0 0\n1 0 0 0 255 0\n1 1 0 0 0 255\n0 1 0 255 255 255\n\
+             4 0 1 2 3\n",
+            HEADER
+        );
+        assert_quad(&parse(file.as_bytes()).unwrap());
+    }
+
+    #[test]
+    fn binary_little_endian() {
+        let file = binary("binary_little_endian", f32::to_le_bytes, i32::to_le_bytes);
+        assert_quad(&parse(&file[..]).unwrap());
+    }
+
+    #[test]
+    fn binary_big_endian() {
+        let file = binary("binary_big_endian", f32::to_be_bytes, i32::to_be_bytes);
+        assert_quad(&parse(&file[..]).unwrap());
+    }
+
+    #[test]
+    fn normals_and_texture_coordinates() {
+        let file = "ply
+format ascii 1.0
+element vertex 3
+property float x
+property float y
+property float z
+property float nx
+property float ny
+property float nz
+property float s
+property float t
+element face 1
+property list uchar uint vertex_index
+end_header
+0 0 0 0 0 1 0 0
+1 0 0 0 0 1 1 0
+0 1 0 0 0 1 0 1
+3 0 1 2
+";
+        let mesh = parse(file.as_bytes()).unwrap();
+        let corners = &mesh.groups[0].corners;
+        assert_eq!(corners[1].normal, Some([0.0, 0.0, 1.0]));
+        assert_eq!(corners[1].uv, [1.0, 1.0]);
+        assert_eq!(corners[2].uv, [0.0, 0.0]);
+        assert_eq!(corners[0].color, None);
+    }
+
+    #[test]
+    fn bad_index() {
+        let file = format!(
+            "ply\nformat ascii 1.0\n{}\
+             0 0 0 255 0 0\n1 0 0 0 255 0\n1 1 0 0 0 255\n0 1 0 255 255 255\n\
+             3 0 1 4\n",
+            HEADER
+        );
+        let error = parse(file.as_bytes()).err().unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn negative_index() {
+        let file = format!(
+            "ply\nformat ascii 1.0\n{}\
+             0 0 0 255 0 0\n1 0 0 0 255 0\n1 1 0 0 0 255\n0 1 0 255 255 255\n\
+             3 0 1 -1\n",
+            HEADER
+        );
+        let error = parse(file.as_bytes()).err().unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn truncated_binary() {
+        let file = binary("binary_little_endian", f32::to_le_bytes, i32::to_le_bytes);
+        assert!(parse(&file[..file.len() - 2]).is_err());
+    }
+
+    #[test]
+    fn missing_format() {
+        let file = format!("ply\n{}", HEADER);
+        let error = parse(file.as_bytes()).err().unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn not_a_ply_file() {
+        assert!(parse(&b"solid cube\nendsolid\n"[..]).is_err());
+    }
+}
@@ -0,0 +1,16 @@
+// Per-instance attributes, only read when drawing instances
+layout (location = 4) in vec4 instance_offset_scale;
+layout (location = 5) in vec4 instance_rotation;
+layout (location = 6) in vec4 instance_tint;
+
+uniform bool instanced;
+
+// Rotate v by the unit quaternion q
+vec3 rotate(vec4 q, vec3 v) {
+  return v + 2.0 * cross(q.xyz, cross(q.xyz, v) + q.w * v);
+}
+
+// Move a vertex from the mesh's own space onto the current instance
+vec3 place_instance(vec3 p) {
+  return rotate(instance_rotation, p * instance_offset_scale.w) + instance_offset_scale.xyz;
+}
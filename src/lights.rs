@@ -0,0 +1,130 @@
+//! Point and spot lights, passed to the geometry pass as uniform arrays
+
+/// Must match `MAX_LIGHTS` in fs.glsl
+pub const MAX_LIGHTS: usize = 16;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum LightKind {
+    Point,
+    /// A cone around `direction`, at full intensity inside `inner_angle` and
+    /// fading out to nothing at `outer_angle` (both in degrees)
+    Spot {
+        direction: [f32; 3],
+        inner_angle: f32,
+        outer_angle: f32,
+    },
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Light {
+    pub position: [f32; 3],
+    pub color: [f32; 3],
+    pub intensity: f32,
+    /// Distance at which the light's contribution reaches zero
+    pub range: f32,
+    pub kind: LightKind,
+}
+
+/// Light data laid out the way the geometry shader expects it
+#[derive(Clone, Debug, Default)]
+pub struct PackedLights {
+    /// xyz: position, w: range
+    pub position_range: Vec<[f32; 4]>,
+    /// rgb: color times intensity
+    pub color_intensity: Vec<[f32; 4]>,
+    /// xyz: spot direction, w: 1 for spot lights, 0 for point lights
+    pub direction_kind: Vec<[f32; 4]>,
+    /// x: cosine of the inner cone angle, y: cosine of the outer cone angle
+    pub cone: Vec<[f32; 4]>,
+}
+
+impl PackedLights {
+    pub fn new(lights: &[Light]) -> Self {
+        let mut packed = PackedLights::default();
+        if lights.len() > MAX_LIGHTS {
+            eprintln!(
+                "{} lights in the scene, only the first {} are used",
+                lights.len(),
+                MAX_LIGHTS
+            );
+        }
+
+        for light in lights.iter().take(MAX_LIGHTS) {
+            let p = light.position;
+            let c = light.color;
+            packed.position_range.push([p[0], p[1], p[2], light.range]);
+            packed.color_intensity.push([
+                c[0] * light.intensity,
+                c[1] * light.intensity,
+                c[2] * light.intensity,
+                1.0,
+            ]);
+            match light.kind {
+                LightKind::Point => {
+                    packed.direction_kind.push([0.0, 0.0, -1.0, 0.0]);
+                    packed.cone.push([-1.0, -1.0, 0.0, 0.0]);
+                }
+                LightKind::Spot {
+                    direction: d,
+                    inner_angle,
+                    outer_angle,
+                } => {
+                    packed.direction_kind.push([d[0], d[1], d[2], 1.0]);
+                    packed.cone.push([
+                        inner_angle.to_radians().cos(),
+                        outer_angle.to_radians().cos(),
+                        0.0,
+                        0.0,
+                    ]);
+                }
+            }
+        }
+
+        packed
+    }
+
+    pub fn len(&self) -> usize {
+        self.position_range.len()
+    }
+}
+
+/// The demo lights: colored point lights orbiting the sphere cluster, plus a
+/// spot light sweeping across it. `time` is in seconds.
+pub fn orbiting_lights(count: usize, time: f32) -> Vec<Light> {
+    const COLORS: [[f32; 3]; 4] = [
+        [1.0, 0.3, 0.2],
+        [0.2, 0.5, 1.0],
+        [0.3, 1.0, 0.4],
+        [1.0, 0.8, 0.3],
+    ];
+
+    let mut lights: Vec<_> = (0..count)
+        .map(|i| {
+            let phase = i as f32 / count as f32 * std::f32::consts::PI * 2.0;
+            let angle = phase + time * 0.5;
+            let height = (time * 0.7 + phase).sin() * 0.8;
+            Light {
+                position: [angle.cos() * 2.0, angle.sin() * 2.0, height],
+                color: COLORS[i % COLORS.len()],
+                intensity: 2.0,
+                range: 5.0,
+                kind: LightKind::Point,
+            }
+        })
+        .collect();
+
+    let sweep = time * 0.3;
+    lights.push(Light {
+        position: [0.0, 0.0, 4.0],
+        color: [1.0, 1.0, 1.0],
+        intensity: 8.0,
+        range: 10.0,
+        kind: LightKind::Spot {
+            direction: [sweep.cos() * 0.3, sweep.sin() * 0.3, -1.0],
+            inner_angle: 10.0,
+            outer_angle: 20.0,
+        },
+    });
+
+    lights
+}
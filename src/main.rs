@@ -11,19 +11,40 @@ use luminance::pipeline::BoundTexture;
 use luminance::pixel::{Depth32F, Floating, R11G11B10F, RGB32F};
 use luminance::render_state::RenderState;
 use luminance::shader::program::Program;
-use luminance::tess::{Mode, TessBuilder};
-use luminance::texture::{Dim2, Dimensionable, Flat};
-use luminance_glfw::event::{Action, Key, WindowEvent};
+use luminance::tess::{Mode, Tess, TessBuilder, TessSlice};
+use luminance::texture::{Cubemap, Dim2, Dimensionable, Flat};
+use luminance_glfw::event::{Action, Key, Modifiers, WindowEvent};
 use luminance_glfw::surface::{GlfwSurface, Surface, WindowDim, WindowOpt};
 
 use rand::distributions::{Distribution, Uniform};
+use rand::rngs::StdRng;
+use rand::{FromEntropy, Rng, SeedableRng};
 
 use cgmath::prelude::*;
 use cgmath::Matrix4;
 
+use std::path::Path;
+
+mod animation;
+mod audio;
+mod capture;
+mod culling;
+mod environment;
 mod error;
 mod full_screen_tri;
+mod import;
+mod lights;
+mod material;
+mod options;
+mod palette;
 mod passes;
+mod procedural;
+mod processing;
+mod scene;
+mod settings;
+mod texture;
+mod timing;
+mod ui;
 
 #[derive(Copy, Clone, Debug, Eq, PartialEq, VertexAttribSem)]
 pub enum Vertex3DColoredSemantics {
@@ -31,28 +52,146 @@ pub enum Vertex3DColoredSemantics {
     Position,
     #[sem(name = "color", repr = "[f32; 4]", type_name = "VertexColor")]
     Color,
+    #[sem(name = "normal", repr = "[f32; 3]", type_name = "VertexNormal")]
+    Normal,
+    #[sem(name = "uv", repr = "[f32; 2]", type_name = "VertexUv")]
+    Uv,
+    #[sem(
+        name = "instance_offset_scale",
+        repr = "[f32; 4]",
+        type_name = "InstanceOffsetScale"
+    )]
+    InstanceOffsetScale,
+    #[sem(
+        name = "instance_rotation",
+        repr = "[f32; 4]",
+        type_name = "InstanceRotation"
+    )]
+    InstanceRotation,
+    #[sem(name = "instance_tint", repr = "[f32; 4]", type_name = "InstanceTint")]
+    InstanceTint,
+    #[sem(name = "joints", repr = "[f32; 4]", type_name = "VertexJoints")]
+    Joints,
+    #[sem(name = "weights", repr = "[f32; 4]", type_name = "VertexWeights")]
+    Weights,
+    #[sem(
+        name = "morph_position_0",
+        repr = "[f32; 3]",
+        type_name = "MorphPosition0"
+    )]
+    MorphPosition0,
+    #[sem(
+        name = "morph_position_1",
+        repr = "[f32; 3]",
+        type_name = "MorphPosition1"
+    )]
+    MorphPosition1,
+    #[sem(
+        name = "morph_position_2",
+        repr = "[f32; 3]",
+        type_name = "MorphPosition2"
+    )]
+    MorphPosition2,
+    #[sem(
+        name = "morph_position_3",
+        repr = "[f32; 3]",
+        type_name = "MorphPosition3"
+    )]
+    MorphPosition3,
+    #[sem(name = "tangent", repr = "[f32; 4]", type_name = "VertexTangent")]
+    Tangent,
 }
 
-#[derive(Vertex)]
+#[derive(Copy, Clone, Vertex)]
 #[vertex(sem = "Vertex3DColoredSemantics")]
 struct Vertex3DColored {
     position: Vertex3DPosition,
     color: VertexColor,
+    normal: VertexNormal,
+    uv: VertexUv,
+    /// Indices of the skin joints moving this vertex, as floats
+    joints: VertexJoints,
+    weights: VertexWeights,
+    /// Offsets from the position, blended in by the morph weights
+    morph_0: MorphPosition0,
+    morph_1: MorphPosition1,
+    morph_2: MorphPosition2,
+    morph_3: MorphPosition3,
+    /// Direction of increasing u, with the handedness of the bitangent in w.
+    /// Filled in by `processing`.
+    tangent: VertexTangent,
+}
+
+impl Vertex3DColored {
+    /// A vertex that isn't skinned or morphed
+    fn new(position: [f32; 3], color: [f32; 4], normal: [f32; 3], uv: [f32; 2]) -> Self {
+        Self {
+            position: Vertex3DPosition::new(position),
+            color: VertexColor::new(color),
+            normal: VertexNormal::new(normal),
+            uv: VertexUv::new(uv),
+            joints: VertexJoints::new([0.0; 4]),
+            weights: VertexWeights::new([0.0; 4]),
+            morph_0: MorphPosition0::new([0.0; 3]),
+            morph_1: MorphPosition1::new([0.0; 3]),
+            morph_2: MorphPosition2::new([0.0; 3]),
+            morph_3: MorphPosition3::new([0.0; 3]),
+            tangent: VertexTangent::new([0.0; 4]),
+        }
+    }
+}
+
+/// Per-instance attributes for drawing many copies of a mesh at once
+#[derive(Vertex)]
+#[vertex(sem = "Vertex3DColoredSemantics", instanced = "true")]
+struct MeshInstance {
+    /// xyz: translation, w: uniform scale
+    offset_scale: InstanceOffsetScale,
+    /// Unit quaternion, xyz imaginary part first
+    rotation: InstanceRotation,
+    /// Multiplied with the vertex color
+    tint: InstanceTint,
 }
 
 const SIMPLE_FS: &'static str = include_str!("fs.glsl");
-const SIMPLE_VS: &'static str = include_str!("vs.glsl");
+const SIMPLE_VS: &'static str = concat!(
+    include_str!("color_animation.glsl"),
+    include_str!("deform.glsl"),
+    include_str!("instance.glsl"),
+    include_str!("vs.glsl")
+);
+const DEBUG_GEOMETRY_VS: &'static str = concat!(
+    include_str!("color_animation.glsl"),
+    include_str!("deform.glsl"),
+    include_str!("instance.glsl"),
+    include_str!("debug_geometry_vs.glsl")
+);
+const DEBUG_GEOMETRY_GS: &'static str = include_str!("debug_geometry_gs.glsl");
+const DEBUG_GEOMETRY_FS: &'static str = include_str!("debug_geometry_fs.glsl");
 
 const BLUR_SIZE_FACTOR: u32 = 4;
 
+const NEAR_PLANE: f32 = 0.001;
+const FAR_PLANE: f32 = 1000.0;
+
+/// Scale applied to the icosphere drawn at each point and spot light
+const LIGHT_SPHERE_RADIUS: f32 = 0.05;
+
+/// Width and height of the directional light's shadow map, in texels
+const SHADOW_MAP_SIZE: u32 = 2048;
+
 struct RenderBuffers {
+    size: [u32; 2],
     back_buffer: Framebuffer<Flat, Dim2, (), ()>,
-    intermediate_buffer: Framebuffer<Flat, Dim2, (R11G11B10F, R11G11B10F), Depth32F>,
+    /// Scene color, bright parts for the bloom, and the ambient light SSAO
+    /// darkens
+    intermediate_buffer: Framebuffer<Flat, Dim2, (R11G11B10F, R11G11B10F, R11G11B10F), Depth32F>,
 }
 
 impl RenderBuffers {
     fn new<C: GraphicsContext>(c: &mut C, d: <Dim2 as Dimensionable>::Size) -> Self {
         Self {
+            size: d,
             back_buffer: Framebuffer::back_buffer(d),
             intermediate_buffer: Framebuffer::new(c, d, 0).expect("intermediate framebuffer"),
         }
@@ -61,89 +200,416 @@ impl RenderBuffers {
 
 luminance::uniform_interface! {
     struct GeometryShadeInterface {
-        transform: [[f32; 4]; 4]
+        transform: [[f32; 4]; 4],
+        model: [[f32; 4]; 4],
+        color_animation: i32,
+        color_time: f32,
+        color_speed: f32,
+        color_intensity: f32,
+        palette: &'static [[f32; 4]],
+        skinned: bool,
+        joint_matrices: &'static [[[f32; 4]; 4]],
+        morph_weights: [f32; 4],
+        bloom_threshold: f32,
+        camera_position: [f32; 3],
+        light_direction: [f32; 3],
+        light_color: [f32; 3],
+        ambient_color: [f32; 3],
+        light_count: i32,
+        light_position_range: &'static [[f32; 4]],
+        light_color_intensity: &'static [[f32; 4]],
+        light_direction_kind: &'static [[f32; 4]],
+        light_cone: &'static [[f32; 4]],
+        shadows: bool,
+        shadow_map: &'static BoundTexture<'static, Flat, Dim2, Floating>,
+        light_space_transform: [[f32; 4]; 4],
+        shadow_bias: f32,
+        shadow_pcf_radius: i32,
+        base_color_factor: [f32; 4],
+        metallic_factor: f32,
+        roughness_factor: f32,
+        emissive_factor: [f32; 3],
+        emissive_strength: f32,
+        normal_scale: f32,
+        occlusion_strength: f32,
+        has_normal_tex: bool,
+        instanced: bool,
+        base_color_tex: &'static BoundTexture<'static, Flat, Dim2, Floating>,
+        metallic_roughness_tex: &'static BoundTexture<'static, Flat, Dim2, Floating>,
+        normal_tex: &'static BoundTexture<'static, Flat, Dim2, Floating>,
+        occlusion_tex: &'static BoundTexture<'static, Flat, Dim2, Floating>,
+        emissive_tex: &'static BoundTexture<'static, Flat, Dim2, Floating>,
+        has_environment: bool,
+        environment_strength: f32,
+        irradiance_map: &'static BoundTexture<'static, Flat, Cubemap, Floating>,
+        specular_map_0: &'static BoundTexture<'static, Flat, Cubemap, Floating>,
+        specular_map_1: &'static BoundTexture<'static, Flat, Cubemap, Floating>,
+        specular_map_2: &'static BoundTexture<'static, Flat, Cubemap, Floating>,
+        specular_map_3: &'static BoundTexture<'static, Flat, Cubemap, Floating>,
+        specular_map_4: &'static BoundTexture<'static, Flat, Cubemap, Floating>
+    }
+}
+
+luminance::uniform_interface! {
+    struct DebugGeometryShadeInterface {
+        transform: [[f32; 4]; 4],
+        model: [[f32; 4]; 4],
+        color_animation: i32,
+        color_time: f32,
+        color_speed: f32,
+        color_intensity: f32,
+        palette: &'static [[f32; 4]],
+        skinned: bool,
+        joint_matrices: &'static [[[f32; 4]; 4]],
+        morph_weights: [f32; 4],
+        instanced: bool,
+        mode: i32
+    }
+}
+
+/// How the geometry pass draws the mesh
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+enum RenderMode {
+    Shaded = 0,
+    Wireframe = 1,
+    FaceNormals = 2,
+    VertexNormals = 3,
+}
+
+impl RenderMode {
+    fn next(self) -> Self {
+        match self {
+            RenderMode::Shaded => RenderMode::Wireframe,
+            RenderMode::Wireframe => RenderMode::FaceNormals,
+            RenderMode::FaceNormals => RenderMode::VertexNormals,
+            RenderMode::VertexNormals => RenderMode::Shaded,
+        }
     }
 }
 
 luminance::uniform_interface! {
     struct FinalShadeInterface {
         main_tex: &'static BoundTexture<'static, Flat, Dim2, Floating>,
-        bright_tex: &'static BoundTexture<'static, Flat, Dim2, Floating>
+        bright_tex: &'static BoundTexture<'static, Flat, Dim2, Floating>,
+        ambient_tex: &'static BoundTexture<'static, Flat, Dim2, Floating>,
+        ao_tex: &'static BoundTexture<'static, Flat, Dim2, Floating>,
+        ao_strength: f32,
+        bloom_strength: f32,
+        exposure: f32,
+        tone_mapper: i32
     }
 }
 
 #[inline]
-fn rand_color(max_rgb: f32, alpha: f32) -> [f32; 4] {
+fn rand_color<R: Rng>(rng: &mut R, max_rgb: f32, alpha: f32) -> [f32; 4] {
     let distribution = Uniform::new_inclusive(0.0, max_rgb);
-    let mut rng = rand::thread_rng();
 
     [
-        distribution.sample(&mut rng),
-        distribution.sample(&mut rng),
-        distribution.sample(&mut rng),
+        distribution.sample(rng),
+        distribution.sample(rng),
+        distribution.sample(rng),
         alpha,
     ]
 }
 
-fn gen_geometry() -> (Vec<Vertex3DColored>, Vec<u32>) {
-    struct DedupKey {
-        position: [f32; 3],
-    }
+/// A range of indices drawn with a single material
+struct SubMesh {
+    start: usize,
+    count: usize,
+    material: usize,
+}
 
-    impl DedupKey {
-        fn quantized_pos(&self) -> [i32; 3] {
-            [
-                (self.position[0] * 4096.0) as i32,
-                (self.position[1] * 4096.0) as i32,
-                (self.position[2] * 4096.0) as i32,
-            ]
+struct Geometry {
+    vertices: Vec<Vertex3DColored>,
+    indices: Vec<u32>,
+    submeshes: Vec<SubMesh>,
+    materials: Vec<material::Material>,
+    textures: Vec<texture::TextureSource>,
+    images: Vec<gltf::image::Data>,
+    bounds: culling::Aabb,
+    rig: Option<animation::Rig>,
+    /// Whether the vertex colors come from the file, rather than being random
+    vertex_colors: bool,
+    /// Whether every vertex has a normal, rather than needing them generated
+    normals: bool,
+    /// Whether every vertex has a tangent, rather than needing them generated
+    tangents: bool,
+    /// Vertices read before welding, for the processing statistics
+    source_vertices: usize,
+}
+
+impl Geometry {
+    /// Geometry drawn entirely with the default material
+    fn from_triangles(vertices: Vec<Vertex3DColored>, indices: Vec<u32>) -> Self {
+        Self {
+            bounds: culling::Aabb::from_points(vertices.iter().map(|v| v.position.repr)),
+            source_vertices: vertices.len(),
+            submeshes: vec![SubMesh {
+                start: 0,
+                count: indices.len(),
+                material: 0,
+            }],
+            vertices,
+            indices,
+            materials: vec![material::Material::default()],
+            textures: Vec::new(),
+            images: Vec::new(),
+            rig: None,
+            vertex_colors: false,
+            normals: true,
+            tangents: false,
         }
     }
+}
 
-    impl PartialEq for DedupKey {
-        fn eq(&self, o: &DedupKey) -> bool {
-            self.quantized_pos() == o.quantized_pos()
-        }
+// Vertices are welded when they share position, texture coordinates, normal
+// and tangent, so texture seams, hard edges and tangent space splits are kept
+// intact
+struct DedupKey {
+    position: [f32; 3],
+    uv: [f32; 2],
+    normal: [f32; 3],
+    tangent: [f32; 4],
+}
+
+impl DedupKey {
+    fn quantized_pos(&self) -> [i32; 3] {
+        [
+            (self.position[0] * 4096.0) as i32,
+            (self.position[1] * 4096.0) as i32,
+            (self.position[2] * 4096.0) as i32,
+        ]
     }
 
-    impl Eq for DedupKey {}
+    fn quantized_uv(&self) -> [i32; 2] {
+        [(self.uv[0] * 4096.0) as i32, (self.uv[1] * 4096.0) as i32]
+    }
 
-    impl std::hash::Hash for DedupKey {
-        fn hash<H>(&self, state: &mut H)
-        where
-            H: std::hash::Hasher,
-        {
-            use std::hash::Hash;
-            Hash::hash(&self.quantized_pos(), state);
+    fn quantized_normal(&self) -> [i32; 3] {
+        [
+            (self.normal[0] * 4096.0) as i32,
+            (self.normal[1] * 4096.0) as i32,
+            (self.normal[2] * 4096.0) as i32,
+        ]
+    }
+
+    fn quantized_tangent(&self) -> [i32; 4] {
+        [
+            (self.tangent[0] * 4096.0) as i32,
+            (self.tangent[1] * 4096.0) as i32,
+            (self.tangent[2] * 4096.0) as i32,
+            (self.tangent[3] * 4096.0) as i32,
+        ]
+    }
+}
+
+impl PartialEq for DedupKey {
+    fn eq(&self, o: &DedupKey) -> bool {
+        self.quantized_pos() == o.quantized_pos()
+            && self.quantized_uv() == o.quantized_uv()
+            && self.quantized_normal() == o.quantized_normal()
+            && self.quantized_tangent() == o.quantized_tangent()
+    }
+}
+
+impl Eq for DedupKey {}
+
+impl std::hash::Hash for DedupKey {
+    fn hash<H>(&self, state: &mut H)
+    where
+        H: std::hash::Hasher,
+    {
+        use std::hash::Hash;
+        Hash::hash(&self.quantized_pos(), state);
+        Hash::hash(&self.quantized_uv(), state);
+        Hash::hash(&self.quantized_normal(), state);
+        Hash::hash(&self.quantized_tangent(), state);
+    }
+}
+
+/// Load a glTF, OBJ or PLY file, picking the importer from the extension
+fn load_geometry<R: Rng>(rng: &mut R, path: &Path) -> Geometry {
+    match import::read(path) {
+        Some(Ok(imported)) => import_geometry(rng, imported),
+        Some(Err(e)) => panic!("Failed to read {}: {}", path.display(), e),
+        None => gen_geometry(rng, path),
+    }
+}
+
+/// Weld the triangles read by one of the importers, the same way as glTF
+/// primitives
+fn import_geometry<R: Rng>(rng: &mut R, imported: import::ImportedMesh) -> Geometry {
+    let mut verts = Vec::new();
+    let mut index_map = std::collections::HashMap::new();
+    let mut indicies = Vec::new();
+    let mut submeshes = Vec::new();
+    let mut vertex_colors = false;
+    let mut normals = true;
+
+    for group in &imported.groups {
+        let start = indicies.len();
+        for corner in &group.corners {
+            let index = index_map
+                .entry(DedupKey {
+                    position: corner.position,
+                    uv: corner.uv,
+                    normal: corner.normal.unwrap_or([0.0; 3]),
+                    tangent: [0.0; 4],
+                })
+                .or_insert_with(|| {
+                    let tr = verts.len() as u32;
+                    vertex_colors |= corner.color.is_some();
+                    normals &= corner.normal.is_some();
+                    verts.push(Vertex3DColored::new(
+                        corner.position,
+                        corner.color.unwrap_or_else(|| rand_color(rng, 1.1, 1.0)),
+                        corner.normal.unwrap_or([0.0; 3]),
+                        corner.uv,
+                    ));
+
+                    tr
+                });
+            indicies.push(*index);
         }
+        submeshes.push(SubMesh {
+            start,
+            count: indicies.len() - start,
+            material: group.material,
+        });
     }
+    Geometry {
+        bounds: culling::Aabb::from_points(verts.iter().map(|v| v.position.repr)),
+        source_vertices: imported.groups.iter().map(|g| g.corners.len()).sum(),
+        normals,
+        tangents: false,
+        vertices: verts,
+        indices: indicies,
+        submeshes,
+        materials: imported.materials,
+        textures: imported.textures,
+        images: imported.images,
+        rig: None,
+        vertex_colors,
+    }
+}
 
-    let (gltf, buffers, _) =
-        gltf::import("res/sphere_cluster.glb").expect("Failed to  read icosphere data");
+fn gen_geometry<R: Rng>(rng: &mut R, path: &Path) -> Geometry {
+    let (gltf, buffers, images) =
+        gltf::import(path).unwrap_or_else(|e| panic!("Failed to read {}: {}", path.display(), e));
 
     let mut verts = Vec::new();
     let mut index_map = std::collections::HashMap::new();
     let mut indicies = Vec::new();
+    let mut submeshes = Vec::new();
+    let mut source_vertices = 0;
+    // Generated for the whole mesh if any primitive lacks them
+    let mut normals = true;
+    let mut tangents = true;
+
+    // Materials are indexed like the glTF materials, with our default
+    // material for primitives without one at the end
+    let mut materials: Vec<_> = gltf
+        .materials()
+        .map(|m| material::Material::from_gltf(&m))
+        .collect();
+    let default_material = materials.len();
+    materials.push(material::Material::default());
 
     assert!(gltf.meshes().len() == 1);
     let mesh = gltf.meshes().next().unwrap();
 
     for primitive in mesh.primitives() {
-        use gltf::mesh::Semantic;
         assert!(primitive.mode() == gltf::mesh::Mode::Triangles);
 
         let reader = primitive.reader(|buffer| Some(&buffers[buffer.index()]));
+        let start = indicies.len();
         if let Some(iter) = reader.read_positions() {
-            for mut vertex_position in iter {
+            let positions: Vec<[f32; 3]> = iter.collect();
+            source_vertices += positions.len();
+            let vertex_normals: Vec<[f32; 3]> = match reader.read_normals() {
+                Some(vertex_normals) => vertex_normals.collect(),
+                None => {
+                    normals = false;
+                    vec![[0.0; 3]; positions.len()]
+                }
+            };
+            let vertex_tangents: Vec<[f32; 4]> = match reader.read_tangents() {
+                Some(vertex_tangents) => vertex_tangents.collect(),
+                None => {
+                    tangents = false;
+                    vec![[0.0; 4]; positions.len()]
+                }
+            };
+            let uvs: Vec<[f32; 2]> = match reader.read_tex_coords(0) {
+                Some(uvs) => uvs.into_f32().collect(),
+                None => vec![[0.0, 0.0]; positions.len()],
+            };
+            let primitive_indices: Vec<u32> = match reader.read_indices() {
+                Some(indices) => indices.into_u32().collect(),
+                None => (0..positions.len() as u32).collect(),
+            };
+            let mut joints: Vec<[f32; 4]> = match reader.read_joints(0) {
+                Some(joints) => joints
+                    .into_u16()
+                    .map(|j| [j[0] as f32, j[1] as f32, j[2] as f32, j[3] as f32])
+                    .collect(),
+                None => vec![[0.0; 4]; positions.len()],
+            };
+            let mut weights: Vec<[f32; 4]> = match reader.read_weights(0) {
+                Some(weights) => weights.into_f32().collect(),
+                None => vec![[0.0; 4]; positions.len()],
+            };
+            // Joints past MAX_JOINTS aren't animated, see Rig::from_gltf, and
+            // would index past the end of the shader's joint matrices
+            for (joints, weights) in joints.iter_mut().zip(weights.iter_mut()) {
+                for (joint, weight) in joints.iter_mut().zip(weights.iter_mut()) {
+                    if *joint >= animation::MAX_JOINTS as f32 {
+                        *joint = 0.0;
+                        *weight = 0.0;
+                    }
+                }
+            }
+            let mut morphs = vec![vec![[0.0; 3]; positions.len()]; animation::MAX_MORPH_TARGETS];
+            for (target, (target_positions, _, _)) in reader.read_morph_targets().enumerate() {
+                if target >= animation::MAX_MORPH_TARGETS {
+                    eprintln!(
+                        "{} has more than {} morph targets, ignoring the rest",
+                        path.display(),
+                        animation::MAX_MORPH_TARGETS
+                    );
+                    break;
+                }
+                if let Some(target_positions) = target_positions {
+                    morphs[target] = target_positions.collect();
+                }
+            }
+
+            for i in primitive_indices {
+                let (vertex_position, vertex_uv) = (positions[i as usize], uvs[i as usize]);
+                let vertex_normal = vertex_normals[i as usize];
+                let vertex_tangent = vertex_tangents[i as usize];
                 let index = index_map
                     .entry(DedupKey {
                         position: vertex_position,
+                        uv: vertex_uv,
+                        normal: vertex_normal,
+                        tangent: vertex_tangent,
                     })
                     .or_insert_with(|| {
                         let tr = verts.len() as u32;
+                        let i = i as usize;
                         verts.push(Vertex3DColored {
                             position: Vertex3DPosition::new(vertex_position),
-                            color: VertexColor::new(rand_color(1.1, 1.0)),
+                            color: VertexColor::new(rand_color(rng, 1.1, 1.0)),
+                            normal: VertexNormal::new(vertex_normal),
+                            uv: VertexUv::new(vertex_uv),
+                            joints: VertexJoints::new(joints[i]),
+                            weights: VertexWeights::new(weights[i]),
+                            morph_0: MorphPosition0::new(morphs[0][i]),
+                            morph_1: MorphPosition1::new(morphs[1][i]),
+                            morph_2: MorphPosition2::new(morphs[2][i]),
+                            morph_3: MorphPosition3::new(morphs[3][i]),
+                            tangent: VertexTangent::new(vertex_tangent),
                         });
 
                         tr
@@ -151,10 +617,188 @@ fn gen_geometry() -> (Vec<Vertex3DColored>, Vec<u32>) {
                 indicies.push(*index);
             }
         }
+
+        submeshes.push(SubMesh {
+            start,
+            count: indicies.len() - start,
+            material: primitive.material().index().unwrap_or(default_material),
+        });
     }
     assert!(indicies.len() % 3 == 0);
 
-    (verts, indicies)
+    // Morph targets move vertices out of the rest pose's box. Assuming the
+    // weights stay within [0, 1], each target moves them at most by its
+    // largest offset along each axis.
+    let mut bounds = culling::Aabb::from_points(verts.iter().map(|v| v.position.repr));
+    for target in 0..animation::MAX_MORPH_TARGETS {
+        let offsets = culling::Aabb::from_points(
+            verts
+                .iter()
+                .map(|v| {
+                    [
+                        v.morph_0.repr,
+                        v.morph_1.repr,
+                        v.morph_2.repr,
+                        v.morph_3.repr,
+                    ][target]
+                })
+                .chain(std::iter::once([0.0; 3])),
+        );
+        bounds = bounds.sweep(&offsets);
+    }
+
+    Geometry {
+        bounds,
+        rig: animation::Rig::from_gltf(&gltf, &buffers, &mesh),
+        source_vertices,
+        normals,
+        // glTF ignores tangents on meshes without normals
+        tangents: tangents && normals,
+        vertex_colors: false,
+        vertices: verts,
+        indices: indicies,
+        submeshes,
+        materials,
+        textures: gltf
+            .textures()
+            .map(|t| texture::TextureSource::from_gltf(&t))
+            .collect(),
+        images,
+    }
+}
+
+/// A model uploaded to the GPU, drawn by any number of scene nodes
+struct Mesh {
+    /// The vertices, with a random color each that the vertex shader cycles
+    /// from
+    tess: Tess,
+    submeshes: Vec<SubMesh>,
+    materials: Vec<material::Material>,
+    textures: texture::TextureCache,
+    /// Bounds of the vertices, in the mesh's own space
+    bounds: culling::BoundingSphere,
+    /// Copies of the mesh drawn in a single instanced call
+    instanced: Option<Tess>,
+    /// Sphere around the origin containing every copy, in the mesh's own
+    /// space
+    instance_bounds: Option<culling::BoundingSphere>,
+    /// Skin, morph targets and keyframes, for meshes that move on their own
+    rig: Option<animation::Rig>,
+}
+
+impl Mesh {
+    fn load<C: GraphicsContext, R: Rng>(
+        c: &mut C,
+        rng: &mut R,
+        source: &options::MeshSource,
+        normals: processing::NormalMode,
+        instances: &[MeshInstance],
+    ) -> Self {
+        let mut geometry = match source {
+            options::MeshSource::File(path) => load_geometry(rng, path),
+            options::MeshSource::Generated(shape) => {
+                let (vertices, indices) = shape.generate(rng);
+                Geometry::from_triangles(vertices, indices)
+            }
+        };
+        let stats = processing::process(&mut geometry, normals);
+        eprintln!("{}: {}", source, stats);
+        // Colors stored in the file are animated as they are
+        if !geometry.vertex_colors {
+            for vertex in geometry.vertices.iter_mut() {
+                vertex.color = VertexColor::new(rand_color(rng, 1.1, 1.0));
+            }
+        }
+        let textures = texture::TextureCache::new(
+            c,
+            &geometry.materials,
+            &geometry.textures,
+            &geometry.images,
+        )
+        .expect("Material texture upload");
+        let tess = TessBuilder::new(c)
+            .add_vertices(&geometry.vertices)
+            .set_indices(&geometry.indices)
+            .set_mode(Mode::Triangle)
+            .build()
+            .unwrap_or_else(|e| panic!("Geometry upload for {}: {:?}", source, e));
+        let instanced = if instances.is_empty() {
+            None
+        } else {
+            Some(
+                TessBuilder::new(c)
+                    .add_vertices(&geometry.vertices)
+                    .set_indices(&geometry.indices)
+                    .add_instances(instances)
+                    .set_mode(Mode::Triangle)
+                    .build()
+                    .unwrap_or_else(|e| panic!("Instance upload for {}: {:?}", source, e)),
+            )
+        };
+
+        let bounds = geometry.bounds.bounding_sphere();
+        let instance_bounds = if instances.is_empty() {
+            None
+        } else {
+            let reach = bounds.center.to_vec().magnitude() + bounds.radius;
+            let radius = instances
+                .iter()
+                .map(|instance| {
+                    let [x, y, z, scale] = instance.offset_scale.repr;
+                    cgmath::Vector3::new(x, y, z).magnitude() + reach * scale
+                })
+                .fold(0.0, f32::max);
+            Some(culling::BoundingSphere {
+                center: cgmath::Point3::origin(),
+                radius,
+            })
+        };
+
+        Self {
+            tess,
+            bounds,
+            submeshes: geometry.submeshes,
+            materials: geometry.materials,
+            textures,
+            instanced,
+            instance_bounds,
+            rig: geometry.rig,
+        }
+    }
+}
+
+/// Scatter `count` randomly rotated and scaled copies through a shell around
+/// the origin, with bright tints so they stress the bloom
+fn gen_instances<R: Rng>(rng: &mut R, count: u32) -> Vec<MeshInstance> {
+    let unit = Uniform::new_inclusive(-1.0f32, 1.0);
+    let distance = Uniform::new_inclusive(8.0f32, 30.0);
+    let scale = Uniform::new_inclusive(0.2f32, 0.6);
+
+    (0..count)
+        .map(|_| {
+            let random_unit = |rng: &mut R| {
+                cgmath::Vector4::new(
+                    unit.sample(rng),
+                    unit.sample(rng),
+                    unit.sample(rng),
+                    unit.sample(rng),
+                )
+                .normalize()
+            };
+            let direction = random_unit(rng).truncate().normalize();
+            let offset = direction * distance.sample(rng);
+            MeshInstance {
+                offset_scale: InstanceOffsetScale::new([
+                    offset.x,
+                    offset.y,
+                    offset.z,
+                    scale.sample(rng),
+                ]),
+                rotation: InstanceRotation::new(random_unit(rng).into()),
+                tint: InstanceTint::new(rand_color(rng, 3.0, 1.0)),
+            }
+        })
+        .collect()
 }
 
 fn compute_rectilinearize_matrix(width: f32, height: f32) -> Matrix4<f32> {
@@ -166,17 +810,28 @@ fn compute_rectilinearize_matrix(width: f32, height: f32) -> Matrix4<f32> {
 }
 
 fn main() {
+    let options = options::Options::from_args();
+    // Exports are always deterministic, so fall back to a fixed seed for them
+    let mut rng = match (options.seed, &options.export) {
+        (Some(seed), _) => StdRng::seed_from_u64(seed),
+        (None, Some(_)) => StdRng::seed_from_u64(0),
+        (None, None) => StdRng::from_entropy(),
+    };
+
     let mut surface = GlfwSurface::new(
         WindowDim::Windowed(1280, 720),
         "Hello, world!",
         WindowOpt::default().hide_cursor(true),
     )
     .expect("window creation");
+    // Screenshots, exports and the GPU profiler call OpenGL directly through
+    // our own `gl`, which needn't be the copy luminance-glfw loaded
+    gl::load_with(|symbol| surface.window.get_proc_address(symbol) as *const _);
     let (final_composite, _) = Program::<(), (), FinalShadeInterface>::from_strings(
         None,
         full_screen_tri::VS,
         None,
-        include_str!("bloom.glsl"),
+        concat!(include_str!("occlusion.glsl"), include_str!("bloom.glsl")),
     )
     .expect("full screen shade creation");
 
@@ -185,6 +840,15 @@ fn main() {
     )
     .expect("simple program creation");
 
+    let (debug_geometry_prog, _) =
+        Program::<Vertex3DColored, (), DebugGeometryShadeInterface>::from_strings(
+            None,
+            DEBUG_GEOMETRY_VS,
+            Some(DEBUG_GEOMETRY_GS),
+            DEBUG_GEOMETRY_FS,
+        )
+        .expect("debug geometry program creation");
+
     let fullscreen_triangles = TessBuilder::new(&mut surface)
         .set_vertex_nb(6)
         .set_mode(Mode::Triangle)
@@ -197,61 +861,171 @@ fn main() {
     };
     let mut aspect: f32 = surface.size()[1] as f32 / surface.size()[0] as f32;
 
-    let (mut geometry_buffers, vertex_count) = {
-        let (geometry, indices) = gen_geometry();
-        (
-            [
-                TessBuilder::new(&mut surface)
-                    .add_vertices(&geometry)
-                    .set_indices(&indices)
-                    .set_mode(Mode::Triangle)
-                    .build()
-                    .expect("geometry 0"),
-                TessBuilder::new(&mut surface)
-                    .add_vertices(&geometry)
-                    .set_indices(&indices)
-                    .set_mode(Mode::Triangle)
-                    .build()
-                    .expect("geometry 1"),
-                TessBuilder::new(&mut surface)
-                    .add_vertices(&geometry)
-                    .set_indices(&indices)
-                    .set_mode(Mode::Triangle)
-                    .build()
-                    .expect("geometry 2"),
-            ],
-            geometry.len(),
-        )
-    };
+    const CLUSTER_MESH: usize = 0;
+    // Also drawn at each point and spot light so they show up in the bloom
+    const SPHERE_MESH: usize = 1;
+    let meshes = vec![
+        {
+            let instances = gen_instances(&mut rng, options.instances);
+            Mesh::load(
+                &mut surface,
+                &mut rng,
+                &options.mesh,
+                options.normals,
+                &instances,
+            )
+        },
+        Mesh::load(
+            &mut surface,
+            &mut rng,
+            &options.light_mesh,
+            options.normals,
+            &[],
+        ),
+    ];
+    let mesh_bounds: Vec<_> = meshes.iter().map(|mesh| mesh.bounds).collect();
+
+    let mut scene = scene::Scene::new();
+    let cluster = scene.add_node(None, Matrix4::identity(), Some(CLUSTER_MESH));
+    // Orbits along with the cluster's spin, since it is a child of it
+    let satellite = scene.add_node(
+        Some(cluster),
+        Matrix4::from_translation(cgmath::Vector3::new(3.0, 0.0, 0.0)),
+        Some(SPHERE_MESH),
+    );
+    let small_cluster = scene.add_node(
+        None,
+        Matrix4::from_translation(cgmath::Vector3::new(-6.0, 0.0, 0.0)),
+        Some(CLUSTER_MESH),
+    );
 
     let mut buffers = {
         let size = surface.size();
         RenderBuffers::new(&mut surface, size)
     };
+    let mut settings = settings::Settings {
+        color_animation: options.color_animation,
+        ..settings::Settings::default()
+    };
+    let mut ui = ui::Ui::new();
+
     let mut blur_pass = {
         let size = surface.size();
         passes::BlurPass::new(
             &mut surface,
             [size[0] / BLUR_SIZE_FACTOR, size[1] / BLUR_SIZE_FACTOR],
             &fullscreen_triangles,
-            0.25,
+            settings.blur_radius_factor,
         )
         .expect("Blur pass creation")
     };
+    let mut ssao_pass = {
+        let size = surface.size();
+        passes::SsaoPass::new(&mut surface, size, &fullscreen_triangles)
+            .expect("SSAO pass creation")
+    };
+    let mut dof_pass = {
+        let size = surface.size();
+        passes::DepthOfFieldPass::new(
+            &mut surface,
+            size,
+            &fullscreen_triangles,
+            NEAR_PLANE,
+            FAR_PLANE,
+        )
+        .expect("Depth of field pass creation")
+    };
+    let mut shadow_pass =
+        passes::ShadowPass::new(&mut surface, SHADOW_MAP_SIZE).expect("Shadow pass creation");
+    let environment = options
+        .environment
+        .as_ref()
+        .and_then(|path| match environment::Equirect::load(path) {
+            Ok(equirect) => {
+                eprintln!("Filtering environment map {}", path.display());
+                Some(environment::Environment::new(&mut surface, &equirect))
+            }
+            Err(e) => {
+                eprintln!("Failed to load environment map {}: {}", path.display(), e);
+                None
+            }
+        })
+        .unwrap_or_else(|| environment::Environment::black(&mut surface))
+        .expect("Environment upload");
+    let palette = options
+        .palette
+        .as_ref()
+        .and_then(|path| match palette::Palette::load(path) {
+            Ok(palette) => Some(palette),
+            Err(e) => {
+                eprintln!("Failed to load palette {}: {}", path.display(), e);
+                None
+            }
+        })
+        .unwrap_or_default();
+    let audio = options.audio.as_ref().and_then(|path| {
+        match audio::AudioAnalysis::load(path, options.fps) {
+            Ok(audio) => {
+                eprintln!(
+                    "Analyzed {:.1} s of audio from {}",
+                    audio.duration(),
+                    path.display()
+                );
+                Some(audio)
+            }
+            Err(e) => {
+                eprintln!("Failed to load audio {}: {}", path.display(), e);
+                None
+            }
+        }
+    });
+    let skybox = passes::Skybox::new(&fullscreen_triangles).expect("Skybox creation");
+    let debug_view = passes::DebugView::new(&fullscreen_triangles, NEAR_PLANE, FAR_PLANE)
+        .expect("Debug view creation");
+    let mut debug_view_mode = passes::DebugViewMode::Off;
+    let mut render_mode = RenderMode::Shaded;
+    let mut profiler = timing::GpuProfiler::new(
+        options.timing_interval,
+        options.timing_csv.as_ref().map(|p| p.as_path()),
+    )
+    .expect("Profiler creation");
+    let mut hud = passes::Hud::new(&mut surface).expect("HUD creation");
+    if options.export.is_some() {
+        // Keep the overlay out of exported frames
+        hud.toggle();
+    }
     let mut resize_size = None;
     let mut frame = 0;
-
-    let mut colors: Vec<[f32; 4]> = (0..vertex_count).map(|_| rand_color(1.1, 1.0)).collect();
+    // Advanced by the animation speed setting rather than derived from the
+    // frame count, so changing the speed doesn't make colors jump
+    let mut color_time = 0.0;
 
     let mut look_angles = (90.0, 0.0);
     let mut look_angles_baseline = look_angles;
     let mut position = cgmath::Vector3::new(0.0, 0.0, 0.0);
     // Keys: WSADQE
     let mut key_states = [false, false, false, false, false, false];
-    let mut control_active = true;
+    // Mouse and keyboard input would make exports non-deterministic, so only
+    // Escape works while exporting
+    let mut control_active = options.export.is_none();
+
+    let screenshotter = capture::Screenshotter::new("screenshots");
+    let mut screenshot_request = None;
+
+    let mut exporter = options.export.as_ref().map(|target| {
+        eprintln!(
+            "Exporting {} frames at {} fps to {:?}",
+            options.export_frames, options.fps, target
+        );
+        capture::FrameExporter::new(target, buffers.size, options.fps).expect("exporter creation")
+    });
 
     'app: loop {
         for event in surface.poll_events() {
+            if ui.handle_event(&event) {
+                continue;
+            }
+
             match event {
                 WindowEvent::Close | WindowEvent::Key(Key::Escape, _, Action::Release, _) => {
                     break 'app;
@@ -274,18 +1048,57 @@ fn main() {
                 WindowEvent::Key(Key::E, _, action, _) => {
                     key_states[5] = action != Action::Release;
                 }
-                WindowEvent::Key(Key::Space, _, Action::Press, _) => {
+                WindowEvent::Key(Key::Space, _, Action::Press, _) if exporter.is_none() => {
                     control_active = !control_active;
                     if control_active {
-                        eprintln!("Controls enabled");
+                        hud.notify("Controls enabled");
                     } else {
-                        eprintln!("Controls disabled");
+                        hud.notify("Controls disabled");
                     }
                 }
-                WindowEvent::Key(Key::P, _, Action::Press, _) => {
-                    eprintln!("{:?} {:?}", position, look_angles);
+                WindowEvent::Key(Key::P, _, Action::Press, _) if exporter.is_none() => {
+                    hud.notify(format!("{:?} {:?}", position, look_angles));
+                }
+                WindowEvent::Key(Key::F12, _, Action::Press, modifiers) if exporter.is_none() => {
+                    // F12 captures the back buffer, Shift+F12 also dumps the
+                    // HDR buffers as OpenEXR, Ctrl+F12 as Radiance .hdr
+                    screenshot_request = Some(if modifiers.contains(Modifiers::Shift) {
+                        capture::ScreenshotRequest::WithHdr(capture::HdrFormat::OpenExr)
+                    } else if modifiers.contains(Modifiers::Control) {
+                        capture::ScreenshotRequest::WithHdr(capture::HdrFormat::Radiance)
+                    } else {
+                        capture::ScreenshotRequest::BackBuffer
+                    });
+                }
+                WindowEvent::Key(Key::F2, _, Action::Press, _) if exporter.is_none() => {
+                    debug_view_mode = debug_view_mode.next(blur_pass.buffer_count());
+                    hud.notify(format!("Debug view: {:?}", debug_view_mode));
                 }
-                WindowEvent::Key(Key::F1, _, Action::Press, _) => {
+                WindowEvent::Key(Key::F3, _, Action::Press, _) if exporter.is_none() => {
+                    debug_view_mode = match debug_view_mode {
+                        passes::DebugViewMode::Grid => passes::DebugViewMode::Off,
+                        _ => passes::DebugViewMode::Grid,
+                    };
+                    hud.notify(format!("Debug view: {:?}", debug_view_mode));
+                }
+                WindowEvent::Key(Key::F4, _, Action::Press, _) if exporter.is_none() => {
+                    render_mode = render_mode.next();
+                    hud.notify(format!("Render mode: {:?}", render_mode));
+                }
+                WindowEvent::Key(Key::F5, _, Action::Press, _) if exporter.is_none() => {
+                    if profiler.toggle_report() {
+                        hud.notify("Frame timing reports enabled");
+                    } else {
+                        hud.notify("Frame timing reports disabled");
+                    }
+                }
+                WindowEvent::Key(Key::Tab, _, Action::Press, _) if exporter.is_none() => {
+                    ui.toggle();
+                }
+                WindowEvent::Key(Key::H, _, Action::Press, _) if exporter.is_none() => {
+                    hud.toggle();
+                }
+                WindowEvent::Key(Key::F1, _, Action::Press, _) if exporter.is_none() => {
                     position = cgmath::Vector3::new(4.5, -4.5, 0.55);
                     look_angles = (94.6, -45.0);
                     look_angles_baseline = look_angles;
@@ -303,17 +1116,16 @@ fn main() {
             }
         }
 
-        const KEY_SPEED: f32 = 0.1;
-
         if control_active {
             use cgmath::{Deg, Matrix3, Vector3};
             let transform = Matrix3::from_angle_z(Deg(-look_angles.1))
                 * Matrix3::new(1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, -1.0)
                 * Matrix3::from_angle_x(Deg(-look_angles.0));
 
-            let up_vector = transform * Vector3::new(0.0, KEY_SPEED, 0.0);
-            let right_vector = transform * Vector3::new(KEY_SPEED, 0.0, 0.0);
-            let look_vector = transform * Vector3::new(0.0, 0.0, KEY_SPEED);
+            let speed = settings.move_speed;
+            let up_vector = transform * Vector3::new(0.0, speed, 0.0);
+            let right_vector = transform * Vector3::new(speed, 0.0, 0.0);
+            let look_vector = transform * Vector3::new(0.0, 0.0, speed);
             if key_states[0] {
                 position += look_vector;
             }
@@ -346,84 +1158,667 @@ fn main() {
                     ],
                 )
                 .expect("Blur pass resize");
+            ssao_pass
+                .resize_buffers(&mut surface, [width as u32, height as u32])
+                .expect("SSAO pass resize");
+            dof_pass
+                .resize_buffers(&mut surface, [width as u32, height as u32])
+                .expect("Depth of field pass resize");
             rectanglize = compute_rectilinearize_matrix(width as f32, height as f32);
             aspect = width as f32 / height as f32;
         }
 
-        let transform = cgmath::perspective(cgmath::Deg(75.0), aspect, 0.001, 1000.0)
-            * Matrix4::from_angle_x(cgmath::Deg(look_angles.0))
+        let view = Matrix4::from_angle_x(cgmath::Deg(look_angles.0))
             * Matrix4::from_nonuniform_scale(1.0, 1.0, -1.0)
             * Matrix4::from_angle_z(cgmath::Deg(look_angles.1))
-            * Matrix4::from_translation(position)
-            * Matrix4::from_angle_z(cgmath::Deg(frame as f32));
+            * Matrix4::from_translation(position);
+        // Animation follows the frame count rather than the clock, so exports
+        // stay deterministic
+        let time = frame as f32 / options.fps as f32;
+        let poses: Vec<_> = meshes
+            .iter()
+            .map(|mesh| match &mesh.rig {
+                Some(rig) if settings.animations => rig.pose(time),
+                Some(rig) => rig.rest_pose(),
+                None => animation::Pose::default(),
+            })
+            .collect();
+        // Degrees per second, so the scene spins at the same speed whatever
+        // --fps is
+        let spin = time * 60.0;
+        scene.node_mut(cluster).local = Matrix4::from_angle_z(cgmath::Deg(spin));
+        scene.node_mut(satellite).local =
+            Matrix4::from_translation(cgmath::Vector3::new(3.0, 0.0, 0.0))
+                * Matrix4::from_angle_x(cgmath::Deg(spin * 2.0))
+                * Matrix4::from_scale(0.4);
+        scene.node_mut(small_cluster).local =
+            Matrix4::from_translation(cgmath::Vector3::new(-6.0, 0.0, 0.0))
+                * Matrix4::from_angle_z(cgmath::Deg(spin * -0.5))
+                * Matrix4::from_scale(0.5);
+        let mut draws = scene.draws();
+        for draw in draws.iter_mut() {
+            draw.model = draw.model * poses[draw.mesh].transform;
+        }
+        let projection =
+            cgmath::perspective(cgmath::Deg(settings.fov), aspect, NEAR_PLANE, FAR_PLANE);
+        let view_projection = projection * view;
+
+        // Shadow casters outside the view still matter, so only the camera's
+        // passes are culled
+        let frustum = culling::Frustum::from_matrix(&view_projection);
+        let visible_draws: Vec<_> = draws
+            .iter()
+            .filter(|draw| {
+                // Skinned meshes can move anywhere, so their bounds say nothing
+                !settings.frustum_culling
+                    || poses[draw.mesh].skinned
+                    || frustum.intersects(&mesh_bounds[draw.mesh].transform(&draw.model))
+            })
+            .cloned()
+            .collect();
+        // The sky is infinitely far away, so only the camera rotation matters
+        let sky_inverse_view_projection = {
+            let mut rotation = view;
+            rotation.w = cgmath::Vector4::unit_w();
+            (projection * rotation)
+                .invert()
+                .unwrap_or(Matrix4::identity())
+        };
+        let camera_position = view
+            .invert()
+            .map(|inverse| inverse.w.truncate())
+            .unwrap_or(-position);
+
+        // Cycling vertex colors step by 0.01 every frame at normal speed
+        let color_speed = 0.01 * options.fps as f32;
+        let audio_bands = match &audio {
+            Some(audio) if settings.audio.enabled => audio.bands(time),
+            _ => [0.0; audio::BAND_COUNT],
+        };
+        // Bass pumps the vertex colors, everything above it the bloom
+        let color_intensity = 1.0 + settings.audio.color_gain * audio_bands[0];
+        let upper_energy = audio_bands[1..].iter().sum::<f32>() / (audio::BAND_COUNT - 1) as f32;
+        let bloom_strength = 1.0 + settings.audio.bloom_gain * upper_energy;
+        let scene_lights = if settings.point_lights {
+            lights::orbiting_lights(settings.point_light_count, time)
+        } else {
+            Vec::new()
+        };
+        let packed_lights = lights::PackedLights::new(&scene_lights);
 
-        let curr_geometry_buffer = frame % geometry_buffers.len();
+        profiler.begin_frame().expect("Writing frame timings");
+
+        if settings.shadows {
+            let instanced_meshes = meshes.iter().zip(&poses).filter(|_| settings.instances);
+            let shadow_draws: Vec<_> = draws
+                .iter()
+                .map(|draw| {
+                    let tess = &meshes[draw.mesh].tess;
+                    (tess, draw.model, &poses[draw.mesh], false)
+                })
+                .chain(instanced_meshes.clone().filter_map(|(mesh, pose)| {
+                    Some((mesh.instanced.as_ref()?, pose.transform, pose, true))
+                }))
+                .collect();
+            let scene_bounds = draws
+                .iter()
+                .map(|draw| mesh_bounds[draw.mesh].transform(&draw.model))
+                .chain(instanced_meshes.filter_map(|(mesh, pose)| {
+                    Some(mesh.instance_bounds?.transform(&pose.transform))
+                }));
+            // Leave some room so the edges of the scene aren't clipped
+            let scene_radius = scene::bounding_radius(scene_bounds) * 1.05;
+            shadow_pass.run(
+                &mut surface,
+                &shadow_draws,
+                scene_radius,
+                settings.light.direction(),
+                &mut profiler,
+            );
+        }
+        let light_space_transform = shadow_pass.light_transform();
 
         // Main render
+        let section = profiler.begin("geometry");
         surface.pipeline_builder().pipeline(
             &buffers.intermediate_buffer,
             [0.0, 0.0, 0.0, 0.0],
-            |_, shader_gate| {
-                shader_gate.shade(&simple_prog, |render_gate, interface| {
-                    interface.transform.update(transform.into());
-                    render_gate.render(
-                        RenderState::default().set_face_culling(FaceCulling::new(
-                            FaceCullingOrder::CCW,
-                            FaceCullingMode::Front,
-                        )),
-                        |tesselation_gate| {
-                            tesselation_gate.render(
-                                &mut surface,
-                                (&geometry_buffers[curr_geometry_buffer]).into(),
-                            );
-                        },
-                    )
-                })
+            |pipeline, shader_gate| {
+                let render_state = RenderState::default().set_face_culling(FaceCulling::new(
+                    FaceCullingOrder::CCW,
+                    FaceCullingMode::Front,
+                ));
+                if environment.is_loaded() && settings.skybox {
+                    skybox.render(
+                        &mut surface,
+                        &pipeline,
+                        &shader_gate,
+                        &environment.skybox,
+                        sky_inverse_view_projection.into(),
+                        settings.bloom_threshold,
+                    );
+                }
+
+                if render_mode == RenderMode::Shaded {
+                    shader_gate.shade(&simple_prog, |render_gate, interface| {
+                        interface.bloom_threshold.update(settings.bloom_threshold);
+                        interface
+                            .color_animation
+                            .update(settings.color_animation as i32);
+                        interface.color_time.update(color_time);
+                        interface.color_speed.update(color_speed);
+                        interface.color_intensity.update(color_intensity);
+                        interface.palette.update(palette.colors());
+                        interface.camera_position.update(camera_position.into());
+                        interface.light_direction.update(settings.light.direction());
+                        interface.light_color.update(settings.light.radiance());
+                        interface.ambient_color.update([settings.light.ambient; 3]);
+                        interface
+                            .emissive_strength
+                            .update(settings.emissive_strength);
+                        interface.light_count.update(packed_lights.len() as i32);
+                        interface
+                            .light_position_range
+                            .update(&packed_lights.position_range);
+                        interface
+                            .light_color_intensity
+                            .update(&packed_lights.color_intensity);
+                        interface
+                            .light_direction_kind
+                            .update(&packed_lights.direction_kind);
+                        interface.light_cone.update(&packed_lights.cone);
+                        let irradiance_map = pipeline.bind_texture(&environment.irradiance);
+                        let specular_maps: Vec<_> = environment
+                            .specular
+                            .iter()
+                            .map(|map| pipeline.bind_texture(map))
+                            .collect();
+                        interface.has_environment.update(environment.is_loaded());
+                        interface
+                            .environment_strength
+                            .update(settings.environment_strength);
+                        interface.irradiance_map.update(&irradiance_map);
+                        interface.specular_map_0.update(&specular_maps[0]);
+                        interface.specular_map_1.update(&specular_maps[1]);
+                        interface.specular_map_2.update(&specular_maps[2]);
+                        interface.specular_map_3.update(&specular_maps[3]);
+                        interface.specular_map_4.update(&specular_maps[4]);
+                        let shadow_map = pipeline.bind_texture(shadow_pass.texture());
+                        interface.shadows.update(settings.shadows);
+                        interface.shadow_map.update(&shadow_map);
+                        interface
+                            .light_space_transform
+                            .update(light_space_transform.into());
+                        interface.shadow_bias.update(settings.shadow_bias);
+                        interface
+                            .shadow_pcf_radius
+                            .update(settings.shadow_pcf_radius as i32);
+
+                        // Scene nodes, then the instanced copies, which carry
+                        // their own transforms
+                        let scene_batches = visible_draws.iter().map(|draw| {
+                            let mesh = &meshes[draw.mesh];
+                            (mesh, &poses[draw.mesh], &mesh.tess, draw.model, false)
+                        });
+                        let instanced_batches = meshes
+                            .iter()
+                            .zip(&poses)
+                            .filter(|_| settings.instances)
+                            .filter_map(|(mesh, pose)| {
+                                let tess = mesh.instanced.as_ref()?;
+                                Some((mesh, pose, tess, pose.transform, true))
+                            });
+
+                        for (mesh, pose, tess, model, instanced) in
+                            scene_batches.chain(instanced_batches)
+                        {
+                            let textures = &mesh.textures;
+                            interface.transform.update((view_projection * model).into());
+                            interface.model.update(model.into());
+                            interface.instanced.update(instanced);
+                            interface.skinned.update(pose.skinned);
+                            interface.joint_matrices.update(&pose.joint_matrices);
+                            interface.morph_weights.update(pose.morph_weights);
+
+                            for submesh in &mesh.submeshes {
+                                let material = &mesh.materials[submesh.material];
+                                interface
+                                    .base_color_factor
+                                    .update(material.base_color_factor);
+                                interface.metallic_factor.update(material.metallic_factor);
+                                interface.roughness_factor.update(material.roughness_factor);
+                                interface.emissive_factor.update(material.emissive_factor);
+                                interface.normal_scale.update(material.normal_scale);
+                                interface
+                                    .occlusion_strength
+                                    .update(material.occlusion_strength);
+                                interface
+                                    .has_normal_tex
+                                    .update(material.normal_texture.is_some());
+
+                                let base_color_tex = pipeline
+                                    .bind_texture(textures.color(material.base_color_texture));
+                                let metallic_roughness_tex = pipeline.bind_texture(
+                                    textures.data(material.metallic_roughness_texture),
+                                );
+                                let normal_tex =
+                                    pipeline.bind_texture(textures.normal(material.normal_texture));
+                                let occlusion_tex = pipeline
+                                    .bind_texture(textures.data(material.occlusion_texture));
+                                let emissive_tex = pipeline
+                                    .bind_texture(textures.color(material.emissive_texture));
+                                interface.base_color_tex.update(&base_color_tex);
+                                interface
+                                    .metallic_roughness_tex
+                                    .update(&metallic_roughness_tex);
+                                interface.normal_tex.update(&normal_tex);
+                                interface.occlusion_tex.update(&occlusion_tex);
+                                interface.emissive_tex.update(&emissive_tex);
+
+                                render_gate.render(render_state, |tesselation_gate| {
+                                    tesselation_gate.render(
+                                        &mut surface,
+                                        TessSlice::one_slice(
+                                            tess,
+                                            submesh.start,
+                                            submesh.start + submesh.count,
+                                        ),
+                                    );
+                                });
+                            }
+                        }
+
+                        if !settings.light_spheres || scene_lights.is_empty() {
+                            return;
+                        }
+
+                        // Black, purely emissive spheres in the light's color
+                        interface.instanced.update(false);
+                        interface.base_color_factor.update([0.0, 0.0, 0.0, 1.0]);
+                        interface.metallic_factor.update(0.0);
+                        interface.roughness_factor.update(1.0);
+                        interface.normal_scale.update(1.0);
+                        interface.occlusion_strength.update(0.0);
+                        interface.has_normal_tex.update(false);
+                        let light_sphere = &meshes[SPHERE_MESH];
+                        let sphere_pose = &poses[SPHERE_MESH];
+                        interface.skinned.update(sphere_pose.skinned);
+                        interface.joint_matrices.update(&sphere_pose.joint_matrices);
+                        interface.morph_weights.update(sphere_pose.morph_weights);
+                        let white = pipeline.bind_texture(light_sphere.textures.color(None));
+                        let flat_normal = pipeline.bind_texture(light_sphere.textures.normal(None));
+                        interface.base_color_tex.update(&white);
+                        interface.metallic_roughness_tex.update(&white);
+                        interface.normal_tex.update(&flat_normal);
+                        interface.occlusion_tex.update(&white);
+                        interface.emissive_tex.update(&white);
+
+                        for light in &scene_lights {
+                            let light_model =
+                                Matrix4::from_translation(cgmath::Vector3::from(light.position))
+                                    * Matrix4::from_scale(LIGHT_SPHERE_RADIUS);
+                            interface
+                                .transform
+                                .update((view_projection * light_model).into());
+                            interface.model.update(light_model.into());
+                            interface.emissive_factor.update([
+                                light.color[0] * light.intensity,
+                                light.color[1] * light.intensity,
+                                light.color[2] * light.intensity,
+                            ]);
+                            render_gate.render(render_state, |tesselation_gate| {
+                                tesselation_gate.render(&mut surface, (&light_sphere.tess).into());
+                            });
+                        }
+                    })
+                } else {
+                    // Show the back sides of the wireframe too
+                    let render_state = if render_mode == RenderMode::Wireframe {
+                        render_state.set_face_culling(None)
+                    } else {
+                        render_state
+                    };
+
+                    shader_gate.shade(&debug_geometry_prog, |render_gate, interface| {
+                        interface.mode.update(render_mode as i32);
+                        interface
+                            .color_animation
+                            .update(settings.color_animation as i32);
+                        interface.color_time.update(color_time);
+                        interface.color_speed.update(color_speed);
+                        interface.color_intensity.update(color_intensity);
+                        interface.palette.update(palette.colors());
+                        // Same batches as the shaded path
+                        let scene_batches = visible_draws.iter().map(|draw| {
+                            let mesh = &meshes[draw.mesh];
+                            (&poses[draw.mesh], &mesh.tess, draw.model, false)
+                        });
+                        let instanced_batches = meshes
+                            .iter()
+                            .zip(&poses)
+                            .filter(|_| settings.instances)
+                            .filter_map(|(mesh, pose)| {
+                                let tess = mesh.instanced.as_ref()?;
+                                Some((pose, tess, pose.transform, true))
+                            });
+
+                        for (pose, tess, model, instanced) in scene_batches.chain(instanced_batches)
+                        {
+                            interface.transform.update((view_projection * model).into());
+                            interface.model.update(model.into());
+                            interface.instanced.update(instanced);
+                            interface.skinned.update(pose.skinned);
+                            interface.joint_matrices.update(&pose.joint_matrices);
+                            interface.morph_weights.update(pose.morph_weights);
+                            render_gate.render(render_state, |tesselation_gate| {
+                                tesselation_gate.render(&mut surface, tess.into());
+                            });
+                        }
+                    })
+                }
             },
         );
+        profiler.end(section);
 
-        {
-            // Update the geometry by tweaking color values
-            let next_buffer_index =
-                (curr_geometry_buffer + geometry_buffers.len() - 1) % geometry_buffers.len();
-            let mut next_buffer_data = geometry_buffers[next_buffer_index]
-                .as_slice_mut::<Vertex3DColored>()
-                .expect("Getting next buffer binding");
-
-            for i in 0..next_buffer_data.len() {
-                next_buffer_data[i].color.repr = colors[i];
-                for j in 0..3 {
-                    colors[i][j] = (colors[i][j] + 0.01) % 1.1;
-                }
-            }
+        if settings.ssao {
+            ssao_pass.run(
+                &mut surface,
+                buffers.intermediate_buffer.depth_slot(),
+                projection,
+                settings.ssao_radius,
+                settings.ssao_bias,
+                &mut profiler,
+            );
         }
 
+        let ao_strength = if settings.ssao {
+            settings.ssao_strength
+        } else {
+            0.0
+        };
+        let dof = &settings.depth_of_field;
+        if dof.enabled {
+            let (color, _, ambient) = buffers.intermediate_buffer.color_slot();
+            dof_pass.run(
+                &mut surface,
+                &passes::DofInput {
+                    color,
+                    ambient,
+                    depth: buffers.intermediate_buffer.depth_slot(),
+                    ao: ssao_pass.texture(),
+                    ao_strength,
+                },
+                if dof.autofocus {
+                    passes::Focus::Auto
+                } else {
+                    passes::Focus::Distance(dof.focus_distance)
+                },
+                dof.aperture,
+                dof.max_radius,
+                &mut profiler,
+            );
+        }
+
+        blur_pass.set_radius_factor(settings.blur_radius_factor);
+
         // Blur the bright texture, first injecting the intermediate buffer
         // brightness texture, and then flipping between horizontal and vertical
         // blurs
-        blur_pass.run(&mut surface, &buffers.intermediate_buffer.color_slot().1);
+        blur_pass.run(
+            &mut surface,
+            &buffers.intermediate_buffer.color_slot().1,
+            &mut profiler,
+        );
+
+        let mut hud_lines = Vec::new();
+        if let Some(averages) = profiler.averages() {
+            // A frame can finish within the timer's resolution, which leaves
+            // no meaningful frame rate
+            hud_lines.push(if averages.cpu_ms > 0.0 {
+                format!(
+                    "FPS {:.0}  frame {:.2} ms",
+                    1000.0 / averages.cpu_ms,
+                    averages.cpu_ms
+                )
+            } else {
+                format!("frame {:.2} ms", averages.cpu_ms)
+            });
+            let gpu_total: f64 = averages.passes.iter().map(|(_, ms)| ms).sum();
+            hud_lines.push(format!("GPU {:.2} ms", gpu_total));
+            for (name, ms) in &averages.passes {
+                hud_lines.push(format!("  {:<18} {:.3} ms", name, ms));
+            }
+        }
+        hud_lines.push(format!(
+            "Camera {:.2} {:.2} {:.2}  look {:.1} {:.1}",
+            position.x, position.y, position.z, look_angles.0, look_angles.1
+        ));
+        hud_lines.push(format!(
+            "Render mode {:?}  debug view {:?}",
+            render_mode, debug_view_mode
+        ));
+        hud_lines.push(format!(
+            "Bloom {}  threshold {:.2}  radius factor {:.2}  blur scale 1/{}",
+            if settings.bloom { "on" } else { "off" },
+            settings.bloom_threshold,
+            blur_pass.radius_factor(),
+            BLUR_SIZE_FACTOR
+        ));
+        hud_lines.push(format!(
+            "Exposure {:.2}  tone mapper {:?}  fov {:.0}",
+            settings.exposure, settings.tone_mapper, settings.fov
+        ));
+        hud_lines.push(format!(
+            "Lights 1 directional + {}  draws {}/{} ({} culled)  instances {}",
+            packed_lights.len(),
+            visible_draws.len(),
+            draws.len(),
+            draws.len() - visible_draws.len(),
+            if settings.instances {
+                options.instances
+            } else {
+                0
+            }
+        ));
+        hud_lines.push(format!(
+            "Animations {}  rigged meshes {}",
+            if settings.animations { "on" } else { "off" },
+            meshes.iter().filter(|mesh| mesh.rig.is_some()).count()
+        ));
+        hud_lines.push(format!(
+            "Colors {:?}  speed {:.2}",
+            settings.color_animation, settings.color_animation_speed
+        ));
+
+        if audio.is_some() {
+            let mut line = String::from("Audio");
+            for (name, energy) in audio::BAND_NAMES.iter().zip(&audio_bands) {
+                line.push_str(&format!("  {} {:.2}", name, energy));
+            }
+            hud_lines.push(line);
+        }
+
+        let mut overlay = passes::OverlayBatch::new(buffers.size);
+        {
+            use settings::{ColorAnimation, ToneMapper};
+
+            let mut panel = ui.panel(&mut overlay, "Settings (Tab to close)");
+            panel.checkbox("Bloom", &mut settings.bloom);
+            panel.slider("Threshold", &mut settings.bloom_threshold, (0.0, 2.0));
+            panel.slider("Blur radius", &mut settings.blur_radius_factor, (0.0, 2.0));
+            panel.slider("Exposure", &mut settings.exposure, (0.1, 4.0));
+            let mut tone_mapper = settings.tone_mapper as usize;
+            if panel.combo("Tone mapper", &mut tone_mapper, &ToneMapper::NAMES) {
+                settings.tone_mapper = ToneMapper::ALL[tone_mapper];
+            }
+            panel.slider("FOV", &mut settings.fov, (20.0, 120.0));
+            panel.slider("Move speed", &mut settings.move_speed, (0.01, 1.0));
+            panel.slider("Light azimuth", &mut settings.light.azimuth, (0.0, 360.0));
+            panel.slider("Light elev.", &mut settings.light.elevation, (-90.0, 90.0));
+            panel.slider("Light power", &mut settings.light.intensity, (0.0, 4.0));
+            panel.slider("Ambient", &mut settings.light.ambient, (0.0, 1.0));
+            panel.checkbox("Frustum cull", &mut settings.frustum_culling);
+            panel.checkbox("Animations", &mut settings.animations);
+            if options.instances > 0 {
+                panel.checkbox("Instances", &mut settings.instances);
+            }
+            if environment.is_loaded() {
+                panel.checkbox("Skybox", &mut settings.skybox);
+                panel.slider(
+                    "Environment",
+                    &mut settings.environment_strength,
+                    (0.0, 4.0),
+                );
+            }
+            panel.slider("Emissive", &mut settings.emissive_strength, (0.0, 10.0));
+            panel.checkbox("Point lights", &mut settings.point_lights);
+            let mut light_count = settings.point_light_count as f32;
+            if panel.slider("Light count", &mut light_count, (0.0, 12.0)) {
+                settings.point_light_count = light_count.round() as usize;
+            }
+            panel.checkbox("Light spheres", &mut settings.light_spheres);
+            panel.checkbox("Shadows", &mut settings.shadows);
+            panel.slider("Shadow bias", &mut settings.shadow_bias, (0.0, 0.02));
+            let mut pcf_radius = settings.shadow_pcf_radius as f32;
+            if panel.slider("PCF radius", &mut pcf_radius, (0.0, 4.0)) {
+                settings.shadow_pcf_radius = pcf_radius.round() as u32;
+            }
+            panel.checkbox("SSAO", &mut settings.ssao);
+            panel.slider("SSAO radius", &mut settings.ssao_radius, (0.05, 2.0));
+            panel.slider("SSAO bias", &mut settings.ssao_bias, (0.0, 0.1));
+            panel.slider("SSAO strength", &mut settings.ssao_strength, (0.0, 1.0));
+            let dof = &mut settings.depth_of_field;
+            panel.checkbox("Depth of field", &mut dof.enabled);
+            panel.checkbox("Autofocus", &mut dof.autofocus);
+            panel.slider("Focus dist.", &mut dof.focus_distance, (0.1, 20.0));
+            panel.slider("Aperture", &mut dof.aperture, (0.0, 2.0));
+            panel.slider("Max blur", &mut dof.max_radius, (1.0, 32.0));
+            let mut color_animation = settings.color_animation as usize;
+            if panel.combo("Colors", &mut color_animation, &ColorAnimation::NAMES) {
+                settings.color_animation = ColorAnimation::ALL[color_animation];
+            }
+            panel.slider(
+                "Color speed",
+                &mut settings.color_animation_speed,
+                (0.0, 4.0),
+            );
+            if audio.is_some() {
+                let audio = &mut settings.audio;
+                panel.checkbox("Audio", &mut audio.enabled);
+                panel.slider("Audio colors", &mut audio.color_gain, (0.0, 4.0));
+                panel.slider("Audio bloom", &mut audio.bloom_gain, (0.0, 4.0));
+            }
+            panel.finish();
+        }
+        hud.prepare(&mut surface, overlay, &hud_lines)
+            .expect("HUD layout");
 
         // Final composite pass
+        let section = profiler.begin("composite");
         surface.pipeline_builder().pipeline(
             &buffers.back_buffer,
             [0.0, 0.0, 0.0, 0.0],
             |pipeline, shader_gate| {
-                let main_tex = pipeline.bind_texture(&buffers.intermediate_buffer.color_slot().0);
+                let main_tex = if settings.depth_of_field.enabled {
+                    pipeline.bind_texture(dof_pass.texture())
+                } else {
+                    pipeline.bind_texture(&buffers.intermediate_buffer.color_slot().0)
+                };
                 let bright_tex = pipeline.bind_texture(blur_pass.texture());
+                let ambient_tex =
+                    pipeline.bind_texture(&buffers.intermediate_buffer.color_slot().2);
+                let ao_tex = pipeline.bind_texture(ssao_pass.texture());
 
                 shader_gate.shade(&final_composite, |render_gate, interface| {
                     interface.main_tex.update(&main_tex);
                     interface.bright_tex.update(&bright_tex);
+                    interface.ambient_tex.update(&ambient_tex);
+                    interface.ao_tex.update(&ao_tex);
+                    // Depth of field already applied the occlusion
+                    interface
+                        .ao_strength
+                        .update(if settings.depth_of_field.enabled {
+                            0.0
+                        } else {
+                            ao_strength
+                        });
+                    interface.bloom_strength.update(if settings.bloom {
+                        bloom_strength
+                    } else {
+                        0.0
+                    });
+                    interface.exposure.update(settings.exposure);
+                    interface.tone_mapper.update(settings.tone_mapper as i32);
 
                     // interface.tex
                     render_gate.render(RenderState::default(), |tesselation_gate| {
                         tesselation_gate.render(&mut surface, (&fullscreen_triangles).into());
                     })
-                })
+                });
+
+                if debug_view_mode != passes::DebugViewMode::Off {
+                    use passes::DebugTarget;
+
+                    let scene_tex =
+                        pipeline.bind_texture(&buffers.intermediate_buffer.color_slot().0);
+                    let bright_pass_tex =
+                        pipeline.bind_texture(&buffers.intermediate_buffer.color_slot().1);
+                    let depth_tex = pipeline.bind_texture(buffers.intermediate_buffer.depth_slot());
+                    let blur_texs: Vec<_> = (0..blur_pass.buffer_count())
+                        .map(|i| pipeline.bind_texture(blur_pass.buffer_texture(i)))
+                        .collect();
+
+                    let mut views = vec![
+                        (DebugTarget::SceneColor, &scene_tex),
+                        (DebugTarget::BrightPass, &bright_pass_tex),
+                    ];
+                    views.extend(
+                        blur_texs
+                            .iter()
+                            .enumerate()
+                            .map(|(i, tex)| (DebugTarget::BlurBuffer(i), tex)),
+                    );
+                    views.push((DebugTarget::Bloom, &bright_tex));
+                    views.push((DebugTarget::Depth, &depth_tex));
+
+                    debug_view.render(&mut surface, &shader_gate, debug_view_mode, &views);
+                }
+
+                hud.render(&mut surface, &pipeline, &shader_gate);
             },
         );
+        profiler.end(section);
+
+        if let Some(request) = screenshot_request.take() {
+            let (main, bright, _) = buffers.intermediate_buffer.color_slot();
+            match screenshotter.capture(request, buffers.size, main, bright) {
+                Ok(path) => hud.notify(format!("Saved screenshot to {}", path.display())),
+                Err(e) => hud.notify(format!("Failed to save screenshot: {}", e)),
+            }
+        }
+
+        if let Some(exporter) = exporter.as_mut() {
+            let pixels = capture::read_back_buffer(buffers.size);
+            exporter
+                .write_frame(buffers.size, &pixels)
+                .expect("Writing exported frame");
+        }
 
         surface.swap_buffers();
         frame = frame + 1;
+        color_time += settings.color_animation_speed / options.fps as f32;
+
+        if exporter
+            .as_ref()
+            .map_or(false, |e| e.frames_written() >= options.export_frames)
+        {
+            break 'app;
+        }
+    }
+
+    if let Some(exporter) = exporter {
+        let frames = exporter.frames_written();
+        exporter.finish().expect("Finishing export");
+        eprintln!("Exported {} frames", frames);
     }
 }
@@ -0,0 +1,87 @@
+//! glTF metallic-roughness materials
+
+/// A reference to one of the glTF textures, along with the texture
+/// coordinate set used to sample it
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct TextureSlot {
+    pub texture: usize,
+    pub tex_coord: u32,
+}
+
+impl TextureSlot {
+    fn from_info(info: gltf::texture::Info) -> Self {
+        Self {
+            texture: info.texture().index(),
+            tex_coord: info.tex_coord(),
+        }
+    }
+}
+
+/// The `pbrMetallicRoughness` material model, plus the normal, occlusion and
+/// emissive maps that can accompany it
+#[derive(Clone, Debug, PartialEq)]
+pub struct Material {
+    pub base_color_factor: [f32; 4],
+    pub base_color_texture: Option<TextureSlot>,
+    pub metallic_factor: f32,
+    pub roughness_factor: f32,
+    /// Metalness in the blue channel, roughness in the green channel
+    pub metallic_roughness_texture: Option<TextureSlot>,
+    pub normal_texture: Option<TextureSlot>,
+    pub normal_scale: f32,
+    pub occlusion_texture: Option<TextureSlot>,
+    pub occlusion_strength: f32,
+    pub emissive_factor: [f32; 3],
+    pub emissive_texture: Option<TextureSlot>,
+}
+
+impl Material {
+    pub fn from_gltf(material: &gltf::Material) -> Self {
+        let pbr = material.pbr_metallic_roughness();
+        let normal = material.normal_texture();
+        let occlusion = material.occlusion_texture();
+
+        Self {
+            base_color_factor: pbr.base_color_factor(),
+            base_color_texture: pbr.base_color_texture().map(TextureSlot::from_info),
+            metallic_factor: pbr.metallic_factor(),
+            roughness_factor: pbr.roughness_factor(),
+            metallic_roughness_texture: pbr
+                .metallic_roughness_texture()
+                .map(TextureSlot::from_info),
+            normal_scale: normal.as_ref().map_or(1.0, |n| n.scale()),
+            normal_texture: normal.map(|n| TextureSlot {
+                texture: n.texture().index(),
+                tex_coord: n.tex_coord(),
+            }),
+            occlusion_strength: occlusion.as_ref().map_or(1.0, |o| o.strength()),
+            occlusion_texture: occlusion.map(|o| TextureSlot {
+                texture: o.texture().index(),
+                tex_coord: o.tex_coord(),
+            }),
+            emissive_factor: material.emissive_factor(),
+            emissive_texture: material.emissive_texture().map(TextureSlot::from_info),
+        }
+    }
+}
+
+/// What primitives without a material get: a plain dielectric rather than
+/// glTF's fully metallic default, which renders almost black without an
+/// environment to reflect
+impl Default for Material {
+    fn default() -> Self {
+        Self {
+            base_color_factor: [1.0, 1.0, 1.0, 1.0],
+            base_color_texture: None,
+            metallic_factor: 0.0,
+            roughness_factor: 0.5,
+            metallic_roughness_texture: None,
+            normal_texture: None,
+            normal_scale: 1.0,
+            occlusion_texture: None,
+            occlusion_strength: 1.0,
+            emissive_factor: [0.0, 0.0, 0.0],
+            emissive_texture: None,
+        }
+    }
+}
@@ -0,0 +1,11 @@
+// Screen-space ambient occlusion, darkening only the ambient and image-based
+// light the geometry pass wrote to ambient_tex
+uniform sampler2D ambient_tex;
+uniform sampler2D ao_tex;
+// 0 leaves the scene unoccluded, 1 applies the full SSAO term
+uniform float ao_strength;
+
+vec3 occluded_scene(sampler2D scene_tex, vec2 uv) {
+  float ao = mix(1.0, texture(ao_tex, uv).r, ao_strength);
+  return texture(scene_tex, uv).rgb - texture(ambient_tex, uv).rgb * (1.0 - ao);
+}
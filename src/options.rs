@@ -0,0 +1,299 @@
+//! Command line options
+use std::fmt;
+use std::path::PathBuf;
+
+use crate::procedural::Shape;
+use crate::processing::NormalMode;
+use crate::settings::ColorAnimation;
+
+const USAGE: &'static str = "\
+Usage: lumexp [OPTIONS]
+
+Options:
+    --export <PATH>     Render a fixed number of frames into PATH and exit.
+                        PATH ending in .y4m produces a YUV4MPEG2 video,
+                        anything else is treated as a directory of
+                        numbered PNG frames
+    --frames <N>        Number of frames to export (default 600)
+    --fps <N>           Frame rate the simulation is stepped at (default 60)
+    --seed <N>          Seed for the random vertex colors
+    --mesh <MESH>       Mesh at the center of the scene, either a glTF, OBJ
+                        or PLY file or a generated shape (default
+                        res/sphere_cluster.glb)
+    --light-mesh <MESH> Mesh drawn at each light (default res/icosphere.glb)
+    --normals <MODE>    Normals generated for meshes loaded without any,
+                        smooth or flat (default smooth)
+    --instances <N>     Draw N extra copies of the central mesh with
+                        instanced rendering (default 0)
+    --color-animation <MODE>
+                        How vertex colors change over time: cycle, hue,
+                        wave, flicker or palette (default cycle)
+    --palette <PATH>    Image whose middle row, left to right, is the
+                        gradient used by the palette color animation
+    --audio <PATH>      WAV file whose frequency bands drive the vertex
+                        color brightness and bloom strength, analyzed in
+                        step with the simulation (not played back)
+    --environment <PATH>
+                        Radiance .hdr equirectangular environment map used
+                        for the background and image-based lighting
+    --timing-csv <PATH> Log per frame CPU and GPU pass timings to PATH, as
+                        one frame,pass,ms row per pass
+    --timing-interval <N>
+                        Number of frames timings are averaged over before
+                        being reported (default 120)
+    -h, --help          Print this message
+
+Generated shapes for --mesh and --light-mesh, with optional parameters:
+    icosphere[:SUBDIVISIONS]
+    uv-sphere[:SEGMENTS[:RINGS]]
+    cube
+    torus[:MAJOR_RADIUS[:MINOR_RADIUS]]
+    plane[:DIVISIONS]
+    cluster[:COUNT[:MIN_RADIUS[:MAX_RADIUS]]]";
+
+#[derive(Clone, Debug)]
+pub enum ExportTarget {
+    Y4m(PathBuf),
+    ImageSequence(PathBuf),
+}
+
+/// Where a mesh comes from
+#[derive(Clone, Debug)]
+pub enum MeshSource {
+    /// A glTF, OBJ or PLY file, told apart by extension
+    File(PathBuf),
+    Generated(Shape),
+}
+
+impl MeshSource {
+    /// A generated shape if `spec` names one, otherwise a file path
+    fn parse(spec: &str) -> Result<Self, String> {
+        match Shape::parse(spec) {
+            Some(shape) => shape.map(MeshSource::Generated),
+            None => Ok(MeshSource::File(PathBuf::from(spec))),
+        }
+    }
+}
+
+impl fmt::Display for MeshSource {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MeshSource::File(path) => write!(f, "{}", path.display()),
+            MeshSource::Generated(shape) => write!(f, "{:?}", shape),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Options {
+    pub export: Option<ExportTarget>,
+    pub export_frames: u32,
+    pub fps: u32,
+    pub seed: Option<u64>,
+    pub mesh: MeshSource,
+    pub light_mesh: MeshSource,
+    pub normals: NormalMode,
+    pub environment: Option<PathBuf>,
+    pub color_animation: ColorAnimation,
+    pub palette: Option<PathBuf>,
+    pub audio: Option<PathBuf>,
+    pub instances: u32,
+    pub timing_csv: Option<PathBuf>,
+    pub timing_interval: usize,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            export: None,
+            export_frames: 600,
+            fps: 60,
+            seed: None,
+            mesh: MeshSource::File(PathBuf::from("res/sphere_cluster.glb")),
+            light_mesh: MeshSource::File(PathBuf::from("res/icosphere.glb")),
+            normals: NormalMode::Smooth,
+            environment: None,
+            color_animation: ColorAnimation::Cycle,
+            palette: None,
+            audio: None,
+            instances: 0,
+            timing_csv: None,
+            timing_interval: 120,
+        }
+    }
+}
+
+impl Options {
+    /// Parse the process arguments, printing usage and exiting on failure.
+    pub fn from_args() -> Self {
+        match Self::parse(std::env::args().skip(1)) {
+            Ok(options) => options,
+            Err(message) => {
+                if !message.is_empty() {
+                    eprintln!("{}\n", message);
+                }
+                eprintln!("{}", USAGE);
+                std::process::exit(if message.is_empty() { 0 } else { 1 });
+            }
+        }
+    }
+
+    /// Parse a list of arguments. An empty error means help was requested.
+    fn parse<I: Iterator<Item = String>>(mut args: I) -> Result<Self, String> {
+        let mut options = Options::default();
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "-h" | "--help" => return Err(String::new()),
+                "--export" => {
+                    let path = PathBuf::from(value(&arg, args.next())?);
+                    options.export = Some(if path.extension().map_or(false, |e| e == "y4m") {
+                        ExportTarget::Y4m(path)
+                    } else {
+                        ExportTarget::ImageSequence(path)
+                    });
+                }
+                "--frames" => options.export_frames = number(&arg, args.next())?,
+                "--fps" => options.fps = number(&arg, args.next())?,
+                "--seed" => options.seed = Some(number(&arg, args.next())?),
+                "--mesh" => options.mesh = MeshSource::parse(&value(&arg, args.next())?)?,
+                "--light-mesh" => {
+                    options.light_mesh = MeshSource::parse(&value(&arg, args.next())?)?
+                }
+                "--normals" => {
+                    let mode = value(&arg, args.next())?;
+                    options.normals = NormalMode::parse(&mode)
+                        .ok_or_else(|| format!("{} expects smooth or flat, got {:?}", arg, mode))?
+                }
+                "--instances" => options.instances = number(&arg, args.next())?,
+                "--environment" => {
+                    options.environment = Some(PathBuf::from(value(&arg, args.next())?))
+                }
+                "--color-animation" => {
+                    let mode = value(&arg, args.next())?;
+                    options.color_animation = ColorAnimation::parse(&mode).ok_or_else(|| {
+                        format!(
+                            "{} expects cycle, hue, wave, flicker or palette, got {:?}",
+                            arg, mode
+                        )
+                    })?
+                }
+                "--palette" => options.palette = Some(PathBuf::from(value(&arg, args.next())?)),
+                "--audio" => options.audio = Some(PathBuf::from(value(&arg, args.next())?)),
+                "--timing-csv" => {
+                    options.timing_csv = Some(PathBuf::from(value(&arg, args.next())?))
+                }
+                "--timing-interval" => options.timing_interval = number(&arg, args.next())?,
+                _ => return Err(format!("Unknown argument {:?}", arg)),
+            }
+        }
+
+        if options.fps == 0 {
+            return Err("--fps must be greater than zero".to_string());
+        }
+        if options.export_frames == 0 {
+            return Err("--frames must be greater than zero".to_string());
+        }
+
+        Ok(options)
+    }
+}
+
+fn value(arg: &str, value: Option<String>) -> Result<String, String> {
+    value.ok_or_else(|| format!("{} requires a value", arg))
+}
+
+fn number<T: std::str::FromStr>(arg: &str, v: Option<String>) -> Result<T, String> {
+    let v = value(arg, v)?;
+    v.parse()
+        .map_err(|_| format!("{} expects a number, got {:?}", arg, v))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Options, String> {
+        Options::parse(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn defaults() {
+        let options = parse(&[]).unwrap();
+        assert!(options.export.is_none());
+        assert_eq!(options.export_frames, 600);
+        assert_eq!(options.fps, 60);
+    }
+
+    #[test]
+    fn values() {
+        let options = parse(&[
+            "--export", "out.y4m", "--frames", "30", "--fps", "24", "--seed", "7",
+        ])
+        .unwrap();
+        assert!(matches!(options.export, Some(ExportTarget::Y4m(_))));
+        assert_eq!(options.export_frames, 30);
+        assert_eq!(options.fps, 24);
+        assert_eq!(options.seed, Some(7));
+
+        let options = parse(&["--export", "frames"]).unwrap();
+        assert!(matches!(
+            options.export,
+            Some(ExportTarget::ImageSequence(_))
+        ));
+    }
+
+    #[test]
+    fn color_animation() {
+        assert_eq!(parse(&[]).unwrap().color_animation, ColorAnimation::Cycle);
+        let options = parse(&["--color-animation", "flicker"]).unwrap();
+        assert_eq!(options.color_animation, ColorAnimation::Flicker);
+        let options = parse(&["--color-animation", "Palette"]).unwrap();
+        assert_eq!(options.color_animation, ColorAnimation::Palette);
+        assert!(parse(&["--color-animation", "rainbow"]).is_err());
+    }
+
+    #[test]
+    fn missing_value() {
+        assert_eq!(parse(&["--fps"]).unwrap_err(), "--fps requires a value");
+        assert_eq!(
+            parse(&["--seed", "1", "--export"]).unwrap_err(),
+            "--export requires a value"
+        );
+    }
+
+    #[test]
+    fn zero_fps_and_frames() {
+        assert_eq!(
+            parse(&["--fps", "0"]).unwrap_err(),
+            "--fps must be greater than zero"
+        );
+        assert_eq!(
+            parse(&["--frames", "0"]).unwrap_err(),
+            "--frames must be greater than zero"
+        );
+    }
+
+    #[test]
+    fn bad_numbers() {
+        assert_eq!(
+            parse(&["--fps", "fast"]).unwrap_err(),
+            "--fps expects a number, got \"fast\""
+        );
+        assert!(parse(&["--frames", "-1"]).is_err());
+    }
+
+    #[test]
+    fn unknown_flag() {
+        assert_eq!(
+            parse(&["--fsp", "60"]).unwrap_err(),
+            "Unknown argument \"--fsp\""
+        );
+    }
+
+    #[test]
+    fn help_is_an_empty_error() {
+        assert_eq!(parse(&["--help"]).unwrap_err(), "");
+        assert_eq!(parse(&["--fps", "30", "-h"]).unwrap_err(), "");
+    }
+}
@@ -0,0 +1,67 @@
+//! Color gradients sampled by the palette cycling vertex animation
+use std::path::Path;
+
+use crate::texture::srgb_to_linear;
+
+/// Number of colors in a palette. Must match `PALETTE_SIZE` in
+/// color_animation.glsl.
+pub const PALETTE_SIZE: usize = 32;
+
+/// Evenly spaced colors along a gradient, looping back to the first
+#[derive(Clone, Debug)]
+pub struct Palette {
+    colors: Vec<[f32; 4]>,
+}
+
+impl Palette {
+    /// Read a gradient from an image, left to right along its middle row
+    pub fn load(path: &Path) -> image::ImageResult<Self> {
+        let image = image::open(path)?.to_rgb();
+        let (width, height) = image.dimensions();
+        let colors = (0..PALETTE_SIZE)
+            .map(|i| {
+                let x = (i as u32 * width / PALETTE_SIZE as u32).min(width - 1);
+                let pixel = image.get_pixel(x, height / 2).data;
+                [
+                    srgb_to_linear(pixel[0] as f32 / 255.0),
+                    srgb_to_linear(pixel[1] as f32 / 255.0),
+                    srgb_to_linear(pixel[2] as f32 / 255.0),
+                    1.0,
+                ]
+            })
+            .collect();
+
+        Ok(Self { colors })
+    }
+
+    /// The colors laid out the way the vertex shader expects them
+    pub fn colors(&self) -> &[[f32; 4]] {
+        &self.colors
+    }
+}
+
+impl Default for Palette {
+    /// Black through red and yellow to a white hot enough to bloom
+    fn default() -> Self {
+        const STOPS: [[f32; 3]; 5] = [
+            [0.0, 0.0, 0.0],
+            [0.6, 0.02, 0.0],
+            [1.0, 0.35, 0.0],
+            [1.0, 0.9, 0.2],
+            [2.0, 2.0, 1.8],
+        ];
+        let colors = (0..PALETTE_SIZE)
+            .map(|i| {
+                // Up to the hottest stop and back down, so the loop is seamless
+                let t = 1.0 - (2.0 * i as f32 / PALETTE_SIZE as f32 - 1.0).abs();
+                let x = t * (STOPS.len() - 1) as f32;
+                let (low, f) = (x.floor() as usize, x.fract());
+                let high = (low + 1).min(STOPS.len() - 1);
+                let mix = |c: usize| STOPS[low][c] * (1.0 - f) + STOPS[high][c] * f;
+                [mix(0), mix(1), mix(2), 1.0]
+            })
+            .collect();
+
+        Self { colors }
+    }
+}
@@ -9,6 +9,15 @@ use luminance::tess::Tess;
 use luminance::texture::{Dim2, Flat, Texture};
 
 use crate::error::LuminanceError;
+use crate::timing::GpuProfiler;
+
+/// Profiler section names for each blur iteration
+const ITERATION_SECTIONS: [&'static str; 4] = [
+    "blur iteration 0",
+    "blur iteration 1",
+    "blur iteration 2",
+    "blur iteration 3",
+];
 
 luminance::uniform_interface! {
     struct BlurInterface {
@@ -61,13 +70,18 @@ impl<'a> BlurPass<'a> {
         Ok(())
     }
 
-    pub fn run<C, P>(&self, context: &mut C, texture: &Texture<Flat, Dim2, P>)
-    where
+    pub fn run<C, P>(
+        &self,
+        context: &mut C,
+        texture: &Texture<Flat, Dim2, P>,
+        profiler: &mut GpuProfiler,
+    ) where
         C: GraphicsContext,
         P: Pixel<SamplerType = Floating>,
     {
         let num_buffers = self.buffers.len();
         // Initial injection of new data
+        let section = profiler.begin("blur injection");
         context.pipeline_builder().pipeline(
             // Since we always read from buffer j + 1, and j starts at 0, we
             // want to put the initial data in buffer 1 regardless of the total
@@ -87,11 +101,13 @@ impl<'a> BlurPass<'a> {
                 })
             },
         );
+        profiler.end(section);
 
         for i in 0..2 {
             let rad: f32 = (num_buffers * i) as f32 * self.radius_factor + 0.25;
             // Blur through all the buffers, ending on the last one
             for j in 0..num_buffers {
+                let section = profiler.begin(ITERATION_SECTIONS[i * num_buffers + j]);
                 context.pipeline_builder().pipeline(
                     &self.buffers[j],
                     [0.0, 0.0, 0.0, 0.0],
@@ -110,10 +126,30 @@ impl<'a> BlurPass<'a> {
                         })
                     },
                 );
+                profiler.end(section);
             }
         }
     }
 
+    /// Blur radius growth per iteration, in texels
+    pub fn radius_factor(&self) -> f32 {
+        self.radius_factor
+    }
+
+    pub fn set_radius_factor(&mut self, radius_factor: f32) {
+        self.radius_factor = radius_factor;
+    }
+
+    /// Number of ping-pong buffers used by the blur
+    pub fn buffer_count(&self) -> usize {
+        self.buffers.len()
+    }
+
+    /// Get the texture of one of the ping-pong buffers
+    pub fn buffer_texture(&self, i: usize) -> &Texture<Flat, Dim2, R11G11B10F> {
+        &self.buffers[i].color_slot()
+    }
+
     /// Get the texture containing the output of the blur
     pub fn texture(&self) -> &Texture<Flat, Dim2, R11G11B10F> {
         let num_buffers = self.buffers.len();
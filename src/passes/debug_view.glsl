@@ -0,0 +1,22 @@
+in vec2 v_pos;
+
+uniform sampler2D tex;
+uniform bool is_depth;
+uniform float near;
+uniform float far;
+uniform float depth_range;
+
+out vec4 color;
+
+void main() {
+  vec4 texel = texture(tex, v_pos);
+
+  if (is_depth) {
+    // Undo the perspective projection to get eye space distance
+    float ndc = texel.r * 2.0 - 1.0;
+    float linear = (2.0 * near * far) / (far + near - ndc * (far - near));
+    color = vec4(vec3(1.0 - clamp(linear / depth_range, 0.0, 1.0)), 1.0);
+  } else {
+    color = vec4(pow(texel.rgb, vec3(1.0 / 2.2)), 1.0);
+  }
+}
@@ -0,0 +1,141 @@
+//! Debug visualization of the intermediate render targets
+use luminance::context::GraphicsContext;
+use luminance::pipeline::{BoundTexture, ShaderGate};
+use luminance::pixel::Floating;
+use luminance::render_state::RenderState;
+use luminance::shader::program::Program;
+use luminance::tess::Tess;
+use luminance::texture::{Dim2, Flat};
+
+use crate::error::LuminanceError;
+
+/// Fraction of the screen width/height taken by each thumbnail in grid mode
+const THUMBNAIL_SCALE: f32 = 0.25;
+/// Eye space distance that maps to black in the depth view
+const DEPTH_RANGE: f32 = 25.0;
+
+luminance::uniform_interface! {
+    struct DebugViewInterface {
+        tex: &'static BoundTexture<'static, Flat, Dim2, Floating>,
+        rect: [f32; 4],
+        is_depth: bool,
+        near: f32,
+        far: f32,
+        depth_range: f32
+    }
+}
+
+/// One of the intermediate targets that can be displayed
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum DebugTarget {
+    SceneColor,
+    BrightPass,
+    BlurBuffer(usize),
+    Bloom,
+    Depth,
+}
+
+/// How the debug targets are shown on top of the final composite
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum DebugViewMode {
+    Off,
+    Fullscreen(DebugTarget),
+    Grid,
+}
+
+impl DebugViewMode {
+    /// Step to the next fullscreen target, wrapping back around to `Off`
+    pub fn next(self, blur_buffers: usize) -> Self {
+        use DebugTarget::*;
+        match self {
+            DebugViewMode::Off | DebugViewMode::Grid => DebugViewMode::Fullscreen(SceneColor),
+            DebugViewMode::Fullscreen(target) => match target {
+                SceneColor => DebugViewMode::Fullscreen(BrightPass),
+                BrightPass => DebugViewMode::Fullscreen(BlurBuffer(0)),
+                BlurBuffer(i) if i + 1 < blur_buffers => {
+                    DebugViewMode::Fullscreen(BlurBuffer(i + 1))
+                }
+                BlurBuffer(_) => DebugViewMode::Fullscreen(Bloom),
+                Bloom => DebugViewMode::Fullscreen(Depth),
+                Depth => DebugViewMode::Off,
+            },
+        }
+    }
+}
+
+pub struct DebugView<'a> {
+    program: Program<(), (), DebugViewInterface>,
+    fullscreen_triangles: &'a Tess,
+    near: f32,
+    far: f32,
+}
+
+impl<'a> DebugView<'a> {
+    /// Create a debug view. `near` and `far` are the clip planes used to
+    /// render the depth buffer.
+    pub fn new(fullscreen_tris: &'a Tess, near: f32, far: f32) -> Result<Self, LuminanceError> {
+        let (program, warnings) = Program::from_strings(
+            None,
+            crate::full_screen_tri::RECT_VS,
+            None,
+            include_str!("debug_view.glsl"),
+        )?;
+        if warnings.len() != 0 {
+            eprintln!("Warnings during debug view program compilation:");
+            for warning in warnings {
+                eprintln!(" {:?}", warning)
+            }
+        }
+        Ok(Self {
+            program,
+            fullscreen_triangles: fullscreen_tris,
+            near,
+            far,
+        })
+    }
+
+    /// Draw the debug view on top of whatever has already been rendered by
+    /// the pipeline owning `shader_gate`. `views` holds every target that can
+    /// be displayed, already bound to the pipeline.
+    pub fn render<C>(
+        &self,
+        context: &mut C,
+        shader_gate: &ShaderGate<C>,
+        mode: DebugViewMode,
+        views: &[(DebugTarget, &BoundTexture<Flat, Dim2, Floating>)],
+    ) where
+        C: GraphicsContext,
+    {
+        let per_row = (1.0 / THUMBNAIL_SCALE) as usize;
+
+        for (i, (target, texture)) in views.iter().enumerate() {
+            let rect = match mode {
+                DebugViewMode::Off => return,
+                DebugViewMode::Fullscreen(shown) if shown == *target => [0.0, 0.0, 1.0, 1.0],
+                DebugViewMode::Fullscreen(_) => continue,
+                DebugViewMode::Grid => {
+                    let (column, row) = ((i % per_row) as f32, (i / per_row) as f32);
+                    [
+                        column * THUMBNAIL_SCALE,
+                        1.0 - (row + 1.0) * THUMBNAIL_SCALE,
+                        THUMBNAIL_SCALE,
+                        THUMBNAIL_SCALE,
+                    ]
+                }
+            };
+
+            shader_gate.shade(&self.program, |render_gate, interface| {
+                interface.tex.update(texture);
+                interface.rect.update(rect);
+                interface.is_depth.update(*target == DebugTarget::Depth);
+                interface.near.update(self.near);
+                interface.far.update(self.far);
+                interface.depth_range.update(DEPTH_RANGE);
+
+                render_gate.render(RenderState::default(), |tesselation_gate| {
+                    tesselation_gate.render(context, (self.fullscreen_triangles).into());
+                })
+            });
+        }
+    }
+}
@@ -0,0 +1,68 @@
+in vec2 v_pos;
+
+uniform sampler2D color_tex;
+uniform sampler2D depth_tex;
+uniform float near;
+uniform float far;
+uniform bool autofocus;
+uniform float focus_distance;
+uniform float aperture;
+// Largest circle of confusion, in pixels
+uniform float max_radius;
+
+out vec4 color;
+
+const int SAMPLES = 48;
+const float GOLDEN_ANGLE = 2.39996323;
+
+float linear_depth(vec2 uv) {
+  // Undo the perspective projection to get eye space distance
+  float ndc = texture(depth_tex, uv).r * 2.0 - 1.0;
+  return (2.0 * near * far) / (far + near - ndc * (far - near));
+}
+
+// Radius of the circle of confusion, in pixels
+float coc(float depth, float focus) {
+  return min(aperture * abs(depth - focus) / depth, 1.0) * max_radius;
+}
+
+void main() {
+  float focus = focus_distance;
+  if (autofocus) {
+    // A few taps around the center so thin edges don't make focus jump
+    vec2 texel = 1.0 / vec2(textureSize(depth_tex, 0));
+    focus = (linear_depth(vec2(0.5))
+      + linear_depth(vec2(0.5) + vec2(4.0, 0.0) * texel)
+      + linear_depth(vec2(0.5) - vec2(4.0, 0.0) * texel)
+      + linear_depth(vec2(0.5) + vec2(0.0, 4.0) * texel)
+      + linear_depth(vec2(0.5) - vec2(0.0, 4.0) * texel)) / 5.0;
+  }
+
+  vec2 texel = 1.0 / vec2(textureSize(color_tex, 0));
+  float center_depth = linear_depth(v_pos);
+  float center_coc = coc(center_depth, focus);
+
+  vec3 sum = occluded_scene(color_tex, v_pos);
+  float weight = 1.0;
+  // Gather over a disk with the samples on a golden angle spiral, so the
+  // blur has a round, even bokeh shape
+  for (int i = 0; i < SAMPLES; ++i) {
+    float radius = sqrt((float(i) + 0.5) / float(SAMPLES)) * max_radius;
+    float angle = float(i) * GOLDEN_ANGLE;
+    vec2 uv = v_pos + vec2(cos(angle), sin(angle)) * radius * texel;
+
+    float sample_depth = linear_depth(uv);
+    float sample_coc = coc(sample_depth, focus);
+    // Blurry background mustn't bleed over sharper foreground
+    if (sample_depth > center_depth) {
+      sample_coc = min(sample_coc, center_coc * 2.0);
+    }
+
+    // A sample contributes if its circle of confusion reaches this pixel
+    float contribution = smoothstep(radius - 1.0, radius + 1.0, sample_coc);
+    sum += occluded_scene(color_tex, uv) * contribution;
+    weight += contribution;
+  }
+
+  color = vec4(sum / weight, 1.0);
+}
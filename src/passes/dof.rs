@@ -0,0 +1,157 @@
+//! Depth of field, blurring the scene color by each pixel's circle of
+//! confusion
+use luminance::context::GraphicsContext;
+use luminance::framebuffer::Framebuffer;
+use luminance::pipeline::BoundTexture;
+use luminance::pixel::{Depth32F, Floating, R11G11B10F, R32F};
+use luminance::render_state::RenderState;
+use luminance::shader::program::Program;
+use luminance::tess::Tess;
+use luminance::texture::{Dim2, Flat, Texture};
+
+use crate::error::LuminanceError;
+use crate::timing::GpuProfiler;
+
+luminance::uniform_interface! {
+    struct DofInterface {
+        color_tex: &'static BoundTexture<'static, Flat, Dim2, Floating>,
+        ambient_tex: &'static BoundTexture<'static, Flat, Dim2, Floating>,
+        depth_tex: &'static BoundTexture<'static, Flat, Dim2, Floating>,
+        ao_tex: &'static BoundTexture<'static, Flat, Dim2, Floating>,
+        ao_strength: f32,
+        near: f32,
+        far: f32,
+        autofocus: bool,
+        focus_distance: f32,
+        aperture: f32,
+        max_radius: f32
+    }
+}
+
+/// The geometry pass output that gets blurred
+pub struct DofInput<'t> {
+    pub color: &'t Texture<Flat, Dim2, R11G11B10F>,
+    /// The part of `color` darkened by `ao`
+    pub ambient: &'t Texture<Flat, Dim2, R11G11B10F>,
+    pub depth: &'t Texture<Flat, Dim2, Depth32F>,
+    /// Screen-space ambient occlusion, applied before blurring so that it
+    /// goes out of focus along with the rest of the scene
+    pub ao: &'t Texture<Flat, Dim2, R32F>,
+    /// 0 leaves the scene unoccluded, 1 applies the full SSAO term
+    pub ao_strength: f32,
+}
+
+/// Where the lens is focused
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Focus {
+    /// On whatever is at the center of the screen
+    Auto,
+    /// At a fixed eye space distance
+    Distance(f32),
+}
+
+pub struct DepthOfFieldPass<'a> {
+    program: Program<(), (), DofInterface>,
+    buffer: Framebuffer<Flat, Dim2, R11G11B10F, ()>,
+    fullscreen_triangles: &'a Tess,
+    near: f32,
+    far: f32,
+}
+
+impl<'a> DepthOfFieldPass<'a> {
+    /// Create a new depth of field pass with the provided backbuffer
+    /// dimensions. `near` and `far` are the clip planes used to render the
+    /// depth buffer.
+    pub fn new(
+        c: &mut impl GraphicsContext,
+        d: [u32; 2],
+        fullscreen_tris: &'a Tess,
+        near: f32,
+        far: f32,
+    ) -> Result<Self, LuminanceError> {
+        let (program, warnings) = Program::from_strings(
+            None,
+            crate::full_screen_tri::VS,
+            None,
+            concat!(include_str!("../occlusion.glsl"), include_str!("dof.glsl")),
+        )?;
+        if warnings.len() != 0 {
+            eprintln!("Warnings during depth of field program compilation:");
+            for warning in warnings {
+                eprintln!(" {:?}", warning)
+            }
+        }
+        Ok(Self {
+            program,
+            buffer: Framebuffer::new(c, d, 0)?,
+            fullscreen_triangles: fullscreen_tris,
+            near,
+            far,
+        })
+    }
+
+    pub fn resize_buffers(
+        &mut self,
+        c: &mut impl GraphicsContext,
+        d: [u32; 2],
+    ) -> Result<(), LuminanceError> {
+        self.buffer = Framebuffer::new(c, d, 0)?;
+        Ok(())
+    }
+
+    /// Blur the occluded `input` color according to its depth. `aperture`
+    /// scales the circle of confusion, which never grows past `max_radius`
+    /// pixels.
+    pub fn run<C>(
+        &self,
+        context: &mut C,
+        input: &DofInput,
+        focus: Focus,
+        aperture: f32,
+        max_radius: f32,
+        profiler: &mut GpuProfiler,
+    ) where
+        C: GraphicsContext,
+    {
+        let section = profiler.begin("depth of field");
+        context.pipeline_builder().pipeline(
+            &self.buffer,
+            [0.0, 0.0, 0.0, 0.0],
+            |pipeline, shader_gate| {
+                let color_tex = pipeline.bind_texture(input.color);
+                let ambient_tex = pipeline.bind_texture(input.ambient);
+                let depth_tex = pipeline.bind_texture(input.depth);
+                let ao_tex = pipeline.bind_texture(input.ao);
+
+                shader_gate.shade(&self.program, |render_gate, interface| {
+                    interface.color_tex.update(&color_tex);
+                    interface.ambient_tex.update(&ambient_tex);
+                    interface.depth_tex.update(&depth_tex);
+                    interface.ao_tex.update(&ao_tex);
+                    interface.ao_strength.update(input.ao_strength);
+                    interface.near.update(self.near);
+                    interface.far.update(self.far);
+                    match focus {
+                        Focus::Auto => interface.autofocus.update(true),
+                        Focus::Distance(distance) => {
+                            interface.autofocus.update(false);
+                            interface.focus_distance.update(distance);
+                        }
+                    }
+                    interface.aperture.update(aperture);
+                    interface.max_radius.update(max_radius);
+
+                    render_gate.render(RenderState::default(), |tesselation_gate| {
+                        tesselation_gate.render(context, (self.fullscreen_triangles).into());
+                    })
+                })
+            },
+        );
+        profiler.end(section);
+    }
+
+    /// Get the texture containing the blurred scene
+    pub fn texture(&self) -> &Texture<Flat, Dim2, R11G11B10F> {
+        self.buffer.color_slot()
+    }
+}
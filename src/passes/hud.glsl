@@ -0,0 +1,18 @@
+in vec2 v_uv;
+in vec4 v_color;
+
+uniform sampler2D font;
+
+out vec4 color;
+
+void main() {
+  if (v_uv.x < 0.0) {
+    // Solid rectangle
+    color = v_color;
+    return;
+  }
+
+  // Glyphs sit on a translucent backdrop so they stay readable over bloom
+  float coverage = texture(font, v_uv).r;
+  color = mix(vec4(0.0, 0.0, 0.0, 0.5), v_color, coverage);
+}
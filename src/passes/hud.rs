@@ -0,0 +1,280 @@
+//! On-screen text and widget overlay
+//!
+//! Text is drawn with the 6x10 glyphs from the public domain X11 misc-fixed
+//! font, stored in `res/font_6x10.raw` as a 1 bit per pixel atlas of the 96
+//! printable ASCII characters, 16 to a row.
+use std::time::{Duration, Instant};
+
+use luminance::blending::{Equation, Factor};
+use luminance::context::GraphicsContext;
+use luminance::pipeline::{BoundTexture, Pipeline, ShaderGate};
+use luminance::pixel::{Floating, R32F};
+use luminance::render_state::RenderState;
+use luminance::shader::program::Program;
+use luminance::tess::{Mode, Tess, TessBuilder};
+use luminance::texture::{Dim2, Flat, GenMipmaps, MagFilter, MinFilter, Sampler, Texture};
+
+use crate::error::LuminanceError;
+
+const FONT_DATA: &'static [u8] = include_bytes!("../../res/font_6x10.raw");
+const GLYPH_SIZE: [u32; 2] = [6, 10];
+const GLYPHS_PER_ROW: u32 = 16;
+const GLYPH_ROWS: u32 = 6;
+const FIRST_GLYPH: u8 = b' ';
+
+/// Screen pixels per font pixel
+const SCALE: f32 = 2.0;
+/// Distance of the text from the top left corner, in screen pixels
+const MARGIN: f32 = 8.0;
+/// How long status messages stay on screen
+const MESSAGE_DURATION: Duration = Duration::from_secs(3);
+
+const TEXT_COLOR: [f32; 4] = [1.0, 1.0, 1.0, 1.0];
+const MESSAGE_COLOR: [f32; 4] = [1.0, 0.85, 0.3, 1.0];
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, VertexAttribSem)]
+pub enum HudVertexSemantics {
+    #[sem(name = "pos", repr = "[f32; 2]", type_name = "HudPosition")]
+    Position,
+    #[sem(name = "uv", repr = "[f32; 2]", type_name = "HudUv")]
+    Uv,
+    #[sem(name = "color", repr = "[f32; 4]", type_name = "HudColor")]
+    Color,
+}
+
+#[derive(Vertex)]
+#[vertex(sem = "HudVertexSemantics")]
+struct HudVertex {
+    position: HudPosition,
+    uv: HudUv,
+    color: HudColor,
+}
+
+luminance::uniform_interface! {
+    struct HudInterface {
+        font: &'static BoundTexture<'static, Flat, Dim2, Floating>
+    }
+}
+
+pub struct Hud {
+    program: Program<HudVertex, (), HudInterface>,
+    font: Texture<Flat, Dim2, R32F>,
+    text: Option<Tess>,
+    message: Option<(String, Instant)>,
+    visible: bool,
+}
+
+impl Hud {
+    pub fn new(c: &mut impl GraphicsContext) -> Result<Self, LuminanceError> {
+        let (program, warnings) = Program::from_strings(
+            None,
+            include_str!("hud_vs.glsl"),
+            None,
+            include_str!("hud.glsl"),
+        )?;
+        if warnings.len() != 0 {
+            eprintln!("Warnings during HUD program compilation:");
+            for warning in warnings {
+                eprintln!(" {:?}", warning)
+            }
+        }
+
+        let atlas_size = [GLYPH_SIZE[0] * GLYPHS_PER_ROW, GLYPH_SIZE[1] * GLYPH_ROWS];
+        let sampler = Sampler {
+            mag_filter: MagFilter::Nearest,
+            min_filter: MinFilter::Nearest,
+            ..Sampler::default()
+        };
+        let font = Texture::new(c, atlas_size, 0, &sampler)?;
+        let texels: Vec<f32> = (0..(atlas_size[0] * atlas_size[1]) as usize)
+            .map(|bit| ((FONT_DATA[bit / 8] >> (7 - bit % 8)) & 1) as f32)
+            .collect();
+        font.upload(GenMipmaps::No, &texels);
+
+        Ok(Self {
+            program,
+            font,
+            text: None,
+            message: None,
+            visible: true,
+        })
+    }
+
+    /// Show a status message for a few seconds. The message is also printed
+    /// to stderr.
+    pub fn notify<S: Into<String>>(&mut self, message: S) {
+        let message = message.into();
+        eprintln!("{}", message);
+        self.message = Some((message, Instant::now()));
+    }
+
+    pub fn toggle(&mut self) {
+        self.visible = !self.visible;
+    }
+
+    /// Lay out `lines` on top of everything already in `overlay`, ready for
+    /// the next call to `render`.
+    pub fn prepare<C, S>(
+        &mut self,
+        c: &mut C,
+        mut overlay: OverlayBatch,
+        lines: &[S],
+    ) -> Result<(), LuminanceError>
+    where
+        C: GraphicsContext,
+        S: AsRef<str>,
+    {
+        self.text = None;
+        if self
+            .message
+            .as_ref()
+            .map_or(false, |(_, shown)| shown.elapsed() > MESSAGE_DURATION)
+        {
+            self.message = None;
+        }
+
+        if self.visible {
+            let line_height = OverlayBatch::glyph_size()[1];
+            let mut top = MARGIN;
+            for line in lines {
+                overlay.text([MARGIN, top], line.as_ref(), TEXT_COLOR);
+                top += line_height;
+            }
+            if let Some((message, _)) = &self.message {
+                overlay.text([MARGIN, top], message, MESSAGE_COLOR);
+            }
+        }
+
+        if !overlay.vertices.is_empty() {
+            self.text = Some(
+                TessBuilder::new(c)
+                    .add_vertices(&overlay.vertices)
+                    .set_mode(Mode::Triangle)
+                    .build()?,
+            );
+        }
+        Ok(())
+    }
+
+    /// Draw the text laid out by the last `prepare` on top of whatever the
+    /// pipeline has already rendered.
+    pub fn render<C>(&self, c: &mut C, pipeline: &Pipeline, shader_gate: &ShaderGate<C>)
+    where
+        C: GraphicsContext,
+    {
+        let text = match &self.text {
+            Some(text) => text,
+            None => return,
+        };
+
+        let font = pipeline.bind_texture(&self.font);
+        shader_gate.shade(&self.program, |render_gate, interface| {
+            interface.font.update(&font);
+
+            let render_state = RenderState::default().set_blending((
+                Equation::Additive,
+                Factor::SrcAlpha,
+                Factor::SrcAlphaComplement,
+            ));
+            render_gate.render(render_state, |tesselation_gate| {
+                tesselation_gate.render(c, text.into());
+            })
+        });
+    }
+}
+
+/// Rectangles and text to be drawn by the [`Hud`] pass, in screen pixels
+/// with the origin at the top left corner.
+pub struct OverlayBatch {
+    size: [u32; 2],
+    vertices: Vec<HudVertex>,
+}
+
+impl OverlayBatch {
+    /// Create an empty batch for a render target of the given size
+    pub fn new(size: [u32; 2]) -> Self {
+        Self {
+            size,
+            vertices: Vec::new(),
+        }
+    }
+
+    /// Size of one character cell, in screen pixels
+    pub fn glyph_size() -> [f32; 2] {
+        [GLYPH_SIZE[0] as f32 * SCALE, GLYPH_SIZE[1] as f32 * SCALE]
+    }
+
+    /// Size of the render target the batch is laid out for
+    pub fn size(&self) -> [u32; 2] {
+        self.size
+    }
+
+    /// Add a solid rectangle
+    pub fn rect(&mut self, position: [f32; 2], size: [f32; 2], color: [f32; 4]) {
+        // Negative texture coordinates tell the shader to skip the font
+        self.quad(position, size, [-1.0, -1.0], [-1.0, -1.0], color);
+    }
+
+    /// Add a line of text, with its top left corner at `position`
+    pub fn text(&mut self, position: [f32; 2], line: &str, color: [f32; 4]) {
+        let glyph_size = Self::glyph_size();
+
+        for (column, c) in line.chars().enumerate() {
+            let glyph = match c {
+                ' '..='~' => c as u8 - FIRST_GLYPH,
+                _ => b'?' - FIRST_GLYPH,
+            } as u32;
+            let uv_min = [
+                (glyph % GLYPHS_PER_ROW) as f32 / GLYPHS_PER_ROW as f32,
+                (glyph / GLYPHS_PER_ROW) as f32 / GLYPH_ROWS as f32,
+            ];
+            let uv_max = [
+                uv_min[0] + 1.0 / GLYPHS_PER_ROW as f32,
+                uv_min[1] + 1.0 / GLYPH_ROWS as f32,
+            ];
+
+            self.quad(
+                [position[0] + column as f32 * glyph_size[0], position[1]],
+                glyph_size,
+                uv_min,
+                uv_max,
+                color,
+            );
+        }
+    }
+
+    /// Append two triangles covering the given rectangle
+    fn quad(
+        &mut self,
+        position: [f32; 2],
+        size: [f32; 2],
+        uv_min: [f32; 2],
+        uv_max: [f32; 2],
+        color: [f32; 4],
+    ) {
+        let target = self.size;
+        let to_ndc = |x: f32, y: f32| {
+            [
+                x / target[0] as f32 * 2.0 - 1.0,
+                1.0 - y / target[1] as f32 * 2.0,
+            ]
+        };
+
+        let (left, top) = (position[0], position[1]);
+        let (right, bottom) = (left + size[0], top + size[1]);
+        let corners = [
+            (to_ndc(left, top), [uv_min[0], uv_min[1]]),
+            (to_ndc(left, bottom), [uv_min[0], uv_max[1]]),
+            (to_ndc(right, top), [uv_max[0], uv_min[1]]),
+            (to_ndc(right, bottom), [uv_max[0], uv_max[1]]),
+        ];
+        for &i in &[0, 1, 2, 2, 1, 3] {
+            let (position, uv) = corners[i];
+            self.vertices.push(HudVertex {
+                position: HudPosition::new(position),
+                uv: HudUv::new(uv),
+                color: HudColor::new(color),
+            });
+        }
+    }
+}
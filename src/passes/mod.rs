@@ -1,5 +1,17 @@
 //! Contains all the passes in the pipeline
 
 mod blur;
+mod debug_view;
+mod dof;
+mod hud;
+mod shadow;
+mod skybox;
+mod ssao;
 
 pub use blur::BlurPass;
+pub use debug_view::{DebugTarget, DebugView, DebugViewMode};
+pub use dof::{DepthOfFieldPass, DofInput, Focus};
+pub use hud::{Hud, OverlayBatch};
+pub use shadow::ShadowPass;
+pub use skybox::Skybox;
+pub use ssao::SsaoPass;
@@ -0,0 +1,131 @@
+//! Depth-only pass rendering the scene from the directional light
+use cgmath::prelude::*;
+use cgmath::{Matrix4, Point3, Vector3};
+
+use luminance::context::GraphicsContext;
+use luminance::framebuffer::Framebuffer;
+use luminance::pixel::Depth32F;
+use luminance::render_state::RenderState;
+use luminance::shader::program::Program;
+use luminance::tess::Tess;
+use luminance::texture::{Dim2, Flat, Texture};
+
+use crate::animation::Pose;
+use crate::error::LuminanceError;
+use crate::timing::GpuProfiler;
+use crate::Vertex3DColored;
+
+luminance::uniform_interface! {
+    struct ShadowInterface {
+        transform: [[f32; 4]; 4],
+        skinned: bool,
+        joint_matrices: &'static [[[f32; 4]; 4]],
+        morph_weights: [f32; 4],
+        instanced: bool
+    }
+}
+
+pub struct ShadowPass {
+    program: Program<Vertex3DColored, (), ShadowInterface>,
+    buffer: Framebuffer<Flat, Dim2, (), Depth32F>,
+    light_transform: Matrix4<f32>,
+}
+
+impl ShadowPass {
+    /// Create a shadow pass with a square shadow map of `size` texels
+    pub fn new(c: &mut impl GraphicsContext, size: u32) -> Result<Self, LuminanceError> {
+        let (program, warnings) = Program::from_strings(
+            None,
+            concat!(
+                include_str!("../deform.glsl"),
+                include_str!("../instance.glsl"),
+                include_str!("shadow_vs.glsl")
+            ),
+            None,
+            include_str!("shadow.glsl"),
+        )?;
+        if warnings.len() != 0 {
+            eprintln!("Warnings during shadow pass program compilation:");
+            for warning in warnings {
+                eprintln!(" {:?}", warning)
+            }
+        }
+        Ok(Self {
+            program,
+            buffer: Framebuffer::new(c, [size, size], 0)?,
+            light_transform: Matrix4::identity(),
+        })
+    }
+
+    /// Render the depth of each tesselation, deformed by its pose, spread
+    /// over its instances if it is instanced and transformed by its model
+    /// matrix, as seen by a light travelling in `light_direction`. Everything
+    /// must be within `scene_radius` of the origin.
+    pub fn run<C>(
+        &mut self,
+        context: &mut C,
+        draws: &[(&Tess, Matrix4<f32>, &Pose, bool)],
+        scene_radius: f32,
+        light_direction: [f32; 3],
+        profiler: &mut GpuProfiler,
+    ) where
+        C: GraphicsContext,
+    {
+        self.light_transform = compute_light_transform(light_direction.into(), scene_radius);
+        let light_transform = self.light_transform;
+
+        let section = profiler.begin("shadow");
+        context.pipeline_builder().pipeline(
+            &self.buffer,
+            [0.0, 0.0, 0.0, 0.0],
+            |_, shader_gate| {
+                shader_gate.shade(&self.program, |render_gate, interface| {
+                    // No culling, so single-sided geometry (the plane shape,
+                    // open glTF meshes) casts a shadow whichever side the
+                    // light is on. The depth then comes from the lit side of
+                    // closed meshes too, so keeping acne off it is left to
+                    // the shadow bias.
+                    let render_state = RenderState::default().set_face_culling(None);
+
+                    for (tess, model, pose, instanced) in draws {
+                        interface.transform.update((light_transform * model).into());
+                        interface.instanced.update(*instanced);
+                        interface.skinned.update(pose.skinned);
+                        interface.joint_matrices.update(&pose.joint_matrices);
+                        interface.morph_weights.update(pose.morph_weights);
+                        render_gate.render(render_state, |tesselation_gate| {
+                            tesselation_gate.render(context, (*tess).into());
+                        });
+                    }
+                })
+            },
+        );
+        profiler.end(section);
+    }
+
+    /// World space to shadow map clip space, as used by the last `run`
+    pub fn light_transform(&self) -> Matrix4<f32> {
+        self.light_transform
+    }
+
+    /// Get the shadow map depth texture
+    pub fn texture(&self) -> &Texture<Flat, Dim2, Depth32F> {
+        self.buffer.depth_slot()
+    }
+}
+
+/// Orthographic projection looking down the light direction, fitted to a
+/// sphere of `radius` around the origin
+fn compute_light_transform(direction: Vector3<f32>, radius: f32) -> Matrix4<f32> {
+    let direction = direction.normalize();
+    // Any up vector works as long as it isn't parallel to the light
+    let up = if direction.z.abs() > 0.99 {
+        Vector3::unit_y()
+    } else {
+        Vector3::unit_z()
+    };
+    let eye = Point3::origin() - direction * (radius * 2.0);
+    let view = Matrix4::look_at(eye, Point3::origin(), up);
+    let projection = cgmath::ortho(-radius, radius, -radius, radius, radius, radius * 3.0);
+    projection * view
+}
@@ -0,0 +1,14 @@
+layout (location = 0) in vec3 pos;
+
+uniform mat4 transform;
+
+void main() {
+  vec3 p = pos;
+  // Only the position matters for depth
+  vec3 n = vec3(0.0);
+  deform(p, n);
+  if (instanced) {
+    p = place_instance(p);
+  }
+  gl_Position = transform * vec4(p, 1.);
+}
@@ -0,0 +1,24 @@
+in vec2 v_pos;
+
+uniform samplerCube skybox;
+uniform mat4 inverse_view_projection;
+uniform float bloom_threshold;
+
+layout(location = 0) out vec4 main_color;
+layout(location = 1) out vec4 bright_color;
+layout(location = 2) out vec4 ambient_light;
+
+void main() {
+  vec4 far_point = inverse_view_projection * vec4(v_pos * 2.0 - 1.0, 1.0, 1.0);
+  vec3 direction = far_point.xyz / far_point.w;
+  vec3 sky = texture(skybox, direction).rgb;
+
+  main_color = vec4(sky, 1.0);
+  // Bright parts of the sky, like the sun, bloom like the rest of the scene
+  if (dot(sky, vec3(0.2126, 0.7152, 0.0722)) > bloom_threshold) {
+    bright_color = main_color;
+  } else {
+    bright_color = vec4(0.0);
+  }
+  ambient_light = vec4(0.0);
+}
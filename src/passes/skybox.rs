@@ -0,0 +1,76 @@
+//! Environment cubemap drawn behind the scene
+use luminance::context::GraphicsContext;
+use luminance::pipeline::{BoundTexture, Pipeline, ShaderGate};
+use luminance::pixel::Floating;
+use luminance::render_state::RenderState;
+use luminance::shader::program::Program;
+use luminance::tess::Tess;
+use luminance::texture::{Cubemap, Flat};
+
+use crate::environment::EnvironmentTexture;
+use crate::error::LuminanceError;
+
+luminance::uniform_interface! {
+    struct SkyboxInterface {
+        skybox: &'static BoundTexture<'static, Flat, Cubemap, Floating>,
+        inverse_view_projection: [[f32; 4]; 4],
+        bloom_threshold: f32
+    }
+}
+
+pub struct Skybox<'a> {
+    program: Program<(), (), SkyboxInterface>,
+    fullscreen_triangles: &'a Tess,
+}
+
+impl<'a> Skybox<'a> {
+    pub fn new(fullscreen_tris: &'a Tess) -> Result<Self, LuminanceError> {
+        let (program, warnings) = Program::from_strings(
+            None,
+            crate::full_screen_tri::VS,
+            None,
+            include_str!("skybox.glsl"),
+        )?;
+        if warnings.len() != 0 {
+            eprintln!("Warnings during skybox program compilation:");
+            for warning in warnings {
+                eprintln!(" {:?}", warning)
+            }
+        }
+        Ok(Self {
+            program,
+            fullscreen_triangles: fullscreen_tris,
+        })
+    }
+
+    /// Fill the pipeline's color targets with `skybox`, without touching
+    /// depth, so it should be drawn before the scene. `inverse_view_projection`
+    /// must not include the camera translation.
+    pub fn render<C>(
+        &self,
+        context: &mut C,
+        pipeline: &Pipeline,
+        shader_gate: &ShaderGate<C>,
+        skybox: &EnvironmentTexture,
+        inverse_view_projection: [[f32; 4]; 4],
+        bloom_threshold: f32,
+    ) where
+        C: GraphicsContext,
+    {
+        let skybox = pipeline.bind_texture(skybox);
+        shader_gate.shade(&self.program, |render_gate, interface| {
+            interface.skybox.update(&skybox);
+            interface
+                .inverse_view_projection
+                .update(inverse_view_projection);
+            interface.bloom_threshold.update(bloom_threshold);
+
+            render_gate.render(
+                RenderState::default().set_depth_test(None),
+                |tesselation_gate| {
+                    tesselation_gate.render(context, (self.fullscreen_triangles).into());
+                },
+            )
+        });
+    }
+}
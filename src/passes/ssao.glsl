@@ -0,0 +1,59 @@
+in vec2 v_pos;
+
+// Must match KERNEL_SIZE in ssao.rs
+#define KERNEL_SIZE 16
+
+uniform sampler2D depth_tex;
+uniform sampler2D noise_tex;
+// Hemisphere samples around +Z, in units of radius
+uniform vec4 kernel[KERNEL_SIZE];
+uniform mat4 projection;
+uniform mat4 inverse_projection;
+uniform float radius;
+uniform float bias;
+
+out float occlusion;
+
+vec3 view_position(vec2 uv) {
+  float depth = texture(depth_tex, uv).r;
+  vec4 clip = vec4(vec3(uv, depth) * 2.0 - 1.0, 1.0);
+  vec4 view = inverse_projection * clip;
+  return view.xyz / view.w;
+}
+
+void main() {
+  if (texture(depth_tex, v_pos).r >= 1.0) {
+    // Nothing was drawn here
+    occlusion = 1.0;
+    return;
+  }
+
+  vec3 p = view_position(v_pos);
+  // Face normal from the depth buffer, as there's no normal buffer
+  vec3 n = normalize(cross(dFdx(p), dFdy(p)));
+  if (dot(n, p) > 0.0) {
+    n = -n;
+  }
+
+  vec2 noise_scale = vec2(textureSize(depth_tex, 0)) / vec2(textureSize(noise_tex, 0));
+  vec3 random = texture(noise_tex, v_pos * noise_scale).xyz;
+  // Gram-Schmidt a randomly rotated tangent frame around the normal
+  vec3 t = normalize(random - n * dot(random, n));
+  vec3 b = cross(n, t);
+  mat3 tbn = mat3(t, b, n);
+
+  float occluded = 0.0;
+  for (int i = 0; i < KERNEL_SIZE; ++i) {
+    vec3 sample_pos = p + tbn * kernel[i].xyz * radius;
+
+    vec4 offset = projection * vec4(sample_pos, 1.0);
+    vec2 sample_uv = offset.xy / offset.w * 0.5 + 0.5;
+    float scene_z = view_position(sample_uv).z;
+
+    // Ignore occluders far outside the radius, such as the background
+    float range_check = smoothstep(0.0, 1.0, radius / abs(p.z - scene_z));
+    occluded += (scene_z >= sample_pos.z + bias ? 1.0 : 0.0) * range_check;
+  }
+
+  occlusion = 1.0 - occluded / float(KERNEL_SIZE);
+}
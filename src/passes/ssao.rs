@@ -0,0 +1,214 @@
+//! Screen-space ambient occlusion from the intermediate depth buffer
+use cgmath::prelude::*;
+use cgmath::{Matrix4, Vector3};
+
+use luminance::context::GraphicsContext;
+use luminance::framebuffer::Framebuffer;
+use luminance::pipeline::BoundTexture;
+use luminance::pixel::{Depth32F, Floating, R32F, RGB32F};
+use luminance::render_state::RenderState;
+use luminance::shader::program::Program;
+use luminance::tess::Tess;
+use luminance::texture::{Dim2, Flat, GenMipmaps, MagFilter, MinFilter, Sampler, Texture, Wrap};
+
+use rand::distributions::{Distribution, Uniform};
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+
+use crate::error::LuminanceError;
+use crate::timing::GpuProfiler;
+
+/// Must match `KERNEL_SIZE` in ssao.glsl
+const KERNEL_SIZE: usize = 16;
+/// Width and height of the tiled rotation noise, in texels. The blur
+/// averages over the same footprint to hide the tiling pattern.
+const NOISE_SIZE: u32 = 4;
+/// The kernel and noise are fixed so that exports stay deterministic
+const SEED: u64 = 0x55a0;
+
+luminance::uniform_interface! {
+    struct SsaoInterface {
+        depth_tex: &'static BoundTexture<'static, Flat, Dim2, Floating>,
+        noise_tex: &'static BoundTexture<'static, Flat, Dim2, Floating>,
+        kernel: &'static [[f32; 4]],
+        projection: [[f32; 4]; 4],
+        inverse_projection: [[f32; 4]; 4],
+        radius: f32,
+        bias: f32
+    }
+}
+
+luminance::uniform_interface! {
+    struct SsaoBlurInterface {
+        ao_tex: &'static BoundTexture<'static, Flat, Dim2, Floating>,
+        noise_size: i32
+    }
+}
+
+pub struct SsaoPass<'a> {
+    program: Program<(), (), SsaoInterface>,
+    blur_program: Program<(), (), SsaoBlurInterface>,
+    occlusion: Framebuffer<Flat, Dim2, R32F, ()>,
+    blurred: Framebuffer<Flat, Dim2, R32F, ()>,
+    noise: Texture<Flat, Dim2, RGB32F>,
+    kernel: Vec<[f32; 4]>,
+    fullscreen_triangles: &'a Tess,
+}
+
+impl<'a> SsaoPass<'a> {
+    /// Create a new SSAO pass with the provided backbuffer dimensions.
+    pub fn new(
+        c: &mut impl GraphicsContext,
+        d: [u32; 2],
+        fullscreen_tris: &'a Tess,
+    ) -> Result<Self, LuminanceError> {
+        let (program, warnings) = Program::from_strings(
+            None,
+            crate::full_screen_tri::VS,
+            None,
+            include_str!("ssao.glsl"),
+        )?;
+        if warnings.len() != 0 {
+            eprintln!("Warnings during SSAO program compilation:");
+            for warning in warnings {
+                eprintln!(" {:?}", warning)
+            }
+        }
+        let (blur_program, warnings) = Program::from_strings(
+            None,
+            crate::full_screen_tri::VS,
+            None,
+            include_str!("ssao_blur.glsl"),
+        )?;
+        if warnings.len() != 0 {
+            eprintln!("Warnings during SSAO blur program compilation:");
+            for warning in warnings {
+                eprintln!(" {:?}", warning)
+            }
+        }
+
+        let mut rng = StdRng::seed_from_u64(SEED);
+        let signed = Uniform::new_inclusive(-1.0f32, 1.0);
+        let unsigned = Uniform::new_inclusive(0.0f32, 1.0);
+
+        // Samples in the +Z hemisphere, packed closer to the origin so nearby
+        // occluders count for more
+        let kernel = (0..KERNEL_SIZE)
+            .map(|i| {
+                let direction = Vector3::new(
+                    signed.sample(&mut rng),
+                    signed.sample(&mut rng),
+                    unsigned.sample(&mut rng),
+                )
+                .normalize();
+                let t = i as f32 / KERNEL_SIZE as f32;
+                let scale = 0.1 + 0.9 * t * t;
+                let sample = direction * unsigned.sample(&mut rng) * scale;
+                [sample.x, sample.y, sample.z, 0.0]
+            })
+            .collect();
+
+        // Random rotations around the surface normal, tiled over the screen
+        let noise_texels: Vec<(f32, f32, f32)> = (0..NOISE_SIZE * NOISE_SIZE)
+            .map(|_| (signed.sample(&mut rng), signed.sample(&mut rng), 0.0))
+            .collect();
+        let sampler = Sampler {
+            wrap_s: Wrap::Repeat,
+            wrap_t: Wrap::Repeat,
+            mag_filter: MagFilter::Nearest,
+            min_filter: MinFilter::Nearest,
+            ..Sampler::default()
+        };
+        let noise = Texture::new(c, [NOISE_SIZE, NOISE_SIZE], 0, &sampler)?;
+        noise.upload(GenMipmaps::No, &noise_texels);
+
+        Ok(Self {
+            program,
+            blur_program,
+            occlusion: Framebuffer::new(c, d, 0)?,
+            blurred: Framebuffer::new(c, d, 0)?,
+            noise,
+            kernel,
+            fullscreen_triangles: fullscreen_tris,
+        })
+    }
+
+    pub fn resize_buffers(
+        &mut self,
+        c: &mut impl GraphicsContext,
+        d: [u32; 2],
+    ) -> Result<(), LuminanceError> {
+        self.occlusion = Framebuffer::new(c, d, 0)?;
+        self.blurred = Framebuffer::new(c, d, 0)?;
+        Ok(())
+    }
+
+    /// Compute the occlusion of the scene in `depth`, rendered with
+    /// `projection`. `radius` is the view space distance searched for
+    /// occluders, and `bias` the depth difference below which a sample isn't
+    /// considered occluded.
+    pub fn run<C>(
+        &self,
+        context: &mut C,
+        depth: &Texture<Flat, Dim2, Depth32F>,
+        projection: Matrix4<f32>,
+        radius: f32,
+        bias: f32,
+        profiler: &mut GpuProfiler,
+    ) where
+        C: GraphicsContext,
+    {
+        let inverse_projection = projection.invert().unwrap_or(Matrix4::identity());
+
+        let section = profiler.begin("ssao");
+        context.pipeline_builder().pipeline(
+            &self.occlusion,
+            [1.0, 1.0, 1.0, 1.0],
+            |pipeline, shader_gate| {
+                let depth_tex = pipeline.bind_texture(depth);
+                let noise_tex = pipeline.bind_texture(&self.noise);
+
+                shader_gate.shade(&self.program, |render_gate, interface| {
+                    interface.depth_tex.update(&depth_tex);
+                    interface.noise_tex.update(&noise_tex);
+                    interface.kernel.update(&self.kernel);
+                    interface.projection.update(projection.into());
+                    interface
+                        .inverse_projection
+                        .update(inverse_projection.into());
+                    interface.radius.update(radius);
+                    interface.bias.update(bias);
+
+                    render_gate.render(RenderState::default(), |tesselation_gate| {
+                        tesselation_gate.render(context, (self.fullscreen_triangles).into());
+                    })
+                })
+            },
+        );
+        profiler.end(section);
+
+        let section = profiler.begin("ssao blur");
+        context.pipeline_builder().pipeline(
+            &self.blurred,
+            [1.0, 1.0, 1.0, 1.0],
+            |pipeline, shader_gate| {
+                let ao_tex = pipeline.bind_texture(self.occlusion.color_slot());
+
+                shader_gate.shade(&self.blur_program, |render_gate, interface| {
+                    interface.ao_tex.update(&ao_tex);
+                    interface.noise_size.update(NOISE_SIZE as i32);
+
+                    render_gate.render(RenderState::default(), |tesselation_gate| {
+                        tesselation_gate.render(context, (self.fullscreen_triangles).into());
+                    })
+                })
+            },
+        );
+        profiler.end(section);
+    }
+
+    /// Get the blurred occlusion, 1 where the scene is fully unoccluded
+    pub fn texture(&self) -> &Texture<Flat, Dim2, R32F> {
+        self.blurred.color_slot()
+    }
+}
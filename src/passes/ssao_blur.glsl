@@ -0,0 +1,19 @@
+in vec2 v_pos;
+
+uniform sampler2D ao_tex;
+// Side of the SSAO noise tile, which the blur averages over
+uniform int noise_size;
+
+out float occlusion;
+
+void main() {
+  vec2 texel = 1.0 / vec2(textureSize(ao_tex, 0));
+  float offset = float(noise_size) * 0.5 - 0.5;
+  float result = 0.0;
+  for (int x = 0; x < noise_size; ++x) {
+    for (int y = 0; y < noise_size; ++y) {
+      result += texture(ao_tex, v_pos + (vec2(x, y) - offset) * texel).r;
+    }
+  }
+  occlusion = result / float(noise_size * noise_size);
+}
@@ -0,0 +1,567 @@
+//! Built-in mesh generators, for running without glTF assets
+//!
+//! Every shape is centered on the origin with +Z up, wound counter-clockwise
+//! when seen from outside, and sized to fit in the [-1, 1] cube unless its
+//! parameters say otherwise.
+use std::collections::HashMap;
+use std::f32::consts::PI;
+use std::ops::RangeInclusive;
+
+use cgmath::prelude::*;
+use cgmath::Vector3;
+
+use rand::distributions::{Distribution, Uniform};
+use rand::Rng;
+
+use crate::{rand_color, Vertex3DColored};
+
+/// Spheres in a cluster are placed within this distance of the origin
+const CLUSTER_RADIUS: f32 = 2.0;
+/// Subdivisions of the icospheres making up a cluster
+const CLUSTER_SUBDIVISIONS: u32 = 2;
+/// Each subdivision quadruples the triangle count, so 8 already gives over a
+/// million
+const MAX_ICOSPHERE_SUBDIVISIONS: u32 = 8;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Shape {
+    /// Unit sphere made by splitting each triangle of an icosahedron in four,
+    /// `subdivisions` times
+    Icosphere {
+        subdivisions: u32,
+    },
+    /// Unit sphere of `segments` slices around Z and `rings` stacks from pole
+    /// to pole
+    UvSphere {
+        segments: u32,
+        rings: u32,
+    },
+    Cube,
+    /// Ring around Z, with a tube of `minor_radius` at `major_radius` from
+    /// the origin
+    Torus {
+        major_radius: f32,
+        minor_radius: f32,
+    },
+    /// Square in the XY plane, split into `divisions` by `divisions` quads
+    Plane {
+        divisions: u32,
+    },
+    /// `count` icospheres with radii between `min_radius` and `max_radius`,
+    /// scattered randomly around the origin
+    SphereCluster {
+        count: u32,
+        min_radius: f32,
+        max_radius: f32,
+    },
+}
+
+impl Shape {
+    /// Parse a shape description such as `icosphere:3` or `cluster:40:0.1:0.3`,
+    /// or return `None` if `spec` doesn't name a shape. Omitted parameters
+    /// take default values.
+    pub fn parse(spec: &str) -> Option<Result<Self, String>> {
+        let mut parts = spec.split(':');
+        let name = parts.next().unwrap_or("");
+        let params: Vec<_> = parts.collect();
+        let param = |i: usize, default: f32| -> Result<f32, String> {
+            match params.get(i) {
+                Some(p) => p
+                    .parse()
+                    .map_err(|_| format!("Invalid parameter {:?} for shape {}", p, name)),
+                None => Ok(default),
+            }
+        };
+        // Whole numbers in `range`, which keeps the generated meshes a
+        // sensible size
+        let count = |i: usize, default: u32, range: RangeInclusive<u32>| -> Result<u32, String> {
+            let value = param(i, default as f32)?;
+            if value.fract() != 0.0 || value < *range.start() as f32 || value > *range.end() as f32
+            {
+                return Err(format!(
+                    "Parameter {} of shape {} must be a whole number from {} to {}",
+                    value,
+                    name,
+                    range.start(),
+                    range.end()
+                ));
+            }
+            Ok(value as u32)
+        };
+        // Finite and above zero, since anything else gives NaN or inside out
+        // geometry
+        let radius = |i: usize, default: f32| -> Result<f32, String> {
+            let value = param(i, default)?;
+            if !value.is_finite() || value <= 0.0 {
+                return Err(format!(
+                    "Parameter {} of shape {} must be a positive radius",
+                    value, name
+                ));
+            }
+            Ok(value)
+        };
+
+        let shape = match name {
+            "icosphere" => count(0, 2, 0..=MAX_ICOSPHERE_SUBDIVISIONS)
+                .map(|subdivisions| Shape::Icosphere { subdivisions }),
+            "uv-sphere" => count(0, 32, 3..=1024).and_then(|segments| {
+                Ok(Shape::UvSphere {
+                    segments,
+                    rings: count(1, (segments / 2).max(2), 2..=1024)?,
+                })
+            }),
+            "cube" => Ok(Shape::Cube),
+            "torus" => radius(0, 0.7).and_then(|major_radius| {
+                Ok(Shape::Torus {
+                    major_radius,
+                    minor_radius: radius(1, 0.3)?,
+                })
+            }),
+            "plane" => count(0, 10, 1..=1024).map(|divisions| Shape::Plane { divisions }),
+            "cluster" => count(0, 24, 1..=10_000).and_then(|count| {
+                let (min_radius, max_radius) = (radius(1, 0.1)?, radius(2, 0.4)?);
+                if min_radius > max_radius {
+                    return Err(format!(
+                        "Shape {} has a minimum radius of {} above its maximum of {}",
+                        name, min_radius, max_radius
+                    ));
+                }
+                Ok(Shape::SphereCluster {
+                    count,
+                    min_radius,
+                    max_radius,
+                })
+            }),
+            _ => return None,
+        };
+        Some(shape)
+    }
+
+    /// Triangles of the shape, with random vertex colors drawn from `rng`
+    pub fn generate<R: Rng>(&self, rng: &mut R) -> (Vec<Vertex3DColored>, Vec<u32>) {
+        let mut builder = Builder::default();
+        match *self {
+            Shape::Icosphere { subdivisions } => {
+                builder.icosphere(subdivisions, Vector3::zero(), 1.0)
+            }
+            Shape::UvSphere { segments, rings } => builder.uv_sphere(segments, rings),
+            Shape::Cube => builder.cube(),
+            Shape::Torus {
+                major_radius,
+                minor_radius,
+            } => builder.torus(major_radius, minor_radius),
+            Shape::Plane { divisions } => builder.plane(divisions),
+            Shape::SphereCluster {
+                count,
+                min_radius,
+                max_radius,
+            } => {
+                let unit = Uniform::new_inclusive(-1.0f32, 1.0);
+                let radius = Uniform::new_inclusive(min_radius, max_radius);
+                for _ in 0..count {
+                    // Rejection sample a point in the unit ball
+                    let center = loop {
+                        let p = Vector3::new(unit.sample(rng), unit.sample(rng), unit.sample(rng));
+                        if p.magnitude2() <= 1.0 {
+                            break p * CLUSTER_RADIUS;
+                        }
+                    };
+                    builder.icosphere(CLUSTER_SUBDIVISIONS, center, radius.sample(rng));
+                }
+            }
+        }
+
+        let vertices = builder
+            .vertices
+            .into_iter()
+            .map(|(position, normal, uv)| {
+                Vertex3DColored::new(
+                    position.into(),
+                    rand_color(rng, 1.1, 1.0),
+                    normal.into(),
+                    uv,
+                )
+            })
+            .collect();
+        (vertices, builder.indices)
+    }
+}
+
+#[derive(Default)]
+struct Builder {
+    /// Position, normal and texture coordinates
+    vertices: Vec<(Vector3<f32>, Vector3<f32>, [f32; 2])>,
+    indices: Vec<u32>,
+}
+
+impl Builder {
+    fn vertex(&mut self, position: Vector3<f32>, normal: Vector3<f32>, uv: [f32; 2]) -> u32 {
+        self.vertices.push((position, normal, uv));
+        self.vertices.len() as u32 - 1
+    }
+
+    /// A grid of `columns + 1` by `rows + 1` vertices from `f(u, v)`, with
+    /// `u` and `v` in [0, 1], triangulated so it faces the direction of
+    /// d/du cross d/dv
+    fn grid<F>(&mut self, columns: u32, rows: u32, mut f: F)
+    where
+        F: FnMut(f32, f32) -> (Vector3<f32>, Vector3<f32>),
+    {
+        let first = self.vertices.len() as u32;
+        for row in 0..=rows {
+            for column in 0..=columns {
+                let (u, v) = (column as f32 / columns as f32, row as f32 / rows as f32);
+                let (position, normal) = f(u, v);
+                self.vertex(position, normal, [u, v]);
+            }
+        }
+
+        let index = |column: u32, row: u32| first + row * (columns + 1) + column;
+        for row in 0..rows {
+            for column in 0..columns {
+                let a = index(column, row);
+                let b = index(column + 1, row);
+                let c = index(column + 1, row + 1);
+                let d = index(column, row + 1);
+                self.indices.extend_from_slice(&[a, b, c, a, c, d]);
+            }
+        }
+    }
+
+    fn icosphere(&mut self, subdivisions: u32, center: Vector3<f32>, radius: f32) {
+        let t = (1.0 + 5.0f32.sqrt()) / 2.0;
+        let mut positions: Vec<Vector3<f32>> = [
+            [-1.0, t, 0.0],
+            [1.0, t, 0.0],
+            [-1.0, -t, 0.0],
+            [1.0, -t, 0.0],
+            [0.0, -1.0, t],
+            [0.0, 1.0, t],
+            [0.0, -1.0, -t],
+            [0.0, 1.0, -t],
+            [t, 0.0, -1.0],
+            [t, 0.0, 1.0],
+            [-t, 0.0, -1.0],
+            [-t, 0.0, 1.0],
+        ]
+        .iter()
+        .map(|&p| Vector3::from(p).normalize())
+        .collect();
+        let mut triangles: Vec<[u32; 3]> = vec![
+            [0, 11, 5],
+            [0, 5, 1],
+            [0, 1, 7],
+            [0, 7, 10],
+            [0, 10, 11],
+            [1, 5, 9],
+            [5, 11, 4],
+            [11, 10, 2],
+            [10, 7, 6],
+            [7, 1, 8],
+            [3, 9, 4],
+            [3, 4, 2],
+            [3, 2, 6],
+            [3, 6, 8],
+            [3, 8, 9],
+            [4, 9, 5],
+            [2, 4, 11],
+            [6, 2, 10],
+            [8, 6, 7],
+            [9, 8, 1],
+        ];
+
+        for _ in 0..subdivisions {
+            // Edges are shared by two triangles, which must share the midpoint
+            let mut midpoints = HashMap::new();
+            let mut midpoint = |a: u32, b: u32| {
+                *midpoints.entry((a.min(b), a.max(b))).or_insert_with(|| {
+                    let p = (positions[a as usize] + positions[b as usize]).normalize();
+                    positions.push(p);
+                    positions.len() as u32 - 1
+                })
+            };
+            triangles = triangles
+                .iter()
+                .flat_map(|&[a, b, c]| {
+                    let (ab, bc, ca) = (midpoint(a, b), midpoint(b, c), midpoint(c, a));
+                    vec![[a, ab, ca], [b, bc, ab], [c, ca, bc], [ab, bc, ca]]
+                })
+                .collect();
+        }
+
+        let first = self.vertices.len() as u32;
+        for &direction in &positions {
+            let u = 0.5 + direction.y.atan2(direction.x) / (2.0 * PI);
+            let v = 0.5 - direction.z.asin() / PI;
+            self.vertex(center + direction * radius, direction, [u, v]);
+        }
+        self.indices
+            .extend(triangles.iter().flat_map(|t| t.iter().map(|&i| first + i)));
+    }
+
+    fn uv_sphere(&mut self, segments: u32, rings: u32) {
+        // u goes around Z and v from the south to the north pole, so the grid
+        // faces outwards
+        self.grid(segments, rings, |u, v| {
+            let (phi, theta) = (u * 2.0 * PI, (1.0 - v) * PI);
+            let normal = Vector3::new(
+                theta.sin() * phi.cos(),
+                theta.sin() * phi.sin(),
+                theta.cos(),
+            );
+            (normal, normal)
+        });
+    }
+
+    fn cube(&mut self) {
+        // Each face spans u and v, with u cross v pointing out of the cube
+        let x = Vector3::unit_x();
+        let y = Vector3::unit_y();
+        let z = Vector3::unit_z();
+        for &(u, v) in &[(y, z), (z, y), (z, x), (x, z), (x, y), (y, x)] {
+            let normal = u.cross(v);
+            self.grid(1, 1, |s, t| {
+                (normal + u * (s * 2.0 - 1.0) + v * (t * 2.0 - 1.0), normal)
+            });
+        }
+    }
+
+    fn torus(&mut self, major_radius: f32, minor_radius: f32) {
+        // u goes around Z and v around the tube, from the outside up
+        self.grid(48, 24, |u, v| {
+            let (outer, inner) = (u * 2.0 * PI, v * 2.0 * PI);
+            let around = Vector3::new(outer.cos(), outer.sin(), 0.0);
+            let normal = around * inner.cos() + Vector3::unit_z() * inner.sin();
+            (around * major_radius + normal * minor_radius, normal)
+        });
+    }
+
+    fn plane(&mut self, divisions: u32) {
+        self.grid(divisions, divisions, |u, v| {
+            (
+                Vector3::new(u * 2.0 - 1.0, v * 2.0 - 1.0, 0.0),
+                Vector3::unit_z(),
+            )
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    fn generate(shape: Shape) -> (Vec<Vector3<f32>>, Vec<u32>) {
+        let (vertices, indices) = shape.generate(&mut StdRng::seed_from_u64(0));
+        let positions = vertices
+            .iter()
+            .map(|v| Vector3::from(v.position.repr))
+            .collect();
+        (positions, indices)
+    }
+
+    /// Non-degenerate triangles, as their centroid and (unnormalized) normal
+    fn faces(positions: &[Vector3<f32>], indices: &[u32]) -> Vec<(Vector3<f32>, Vector3<f32>)> {
+        indices
+            .chunks(3)
+            .map(|t| {
+                let [a, b, c] = [
+                    positions[t[0] as usize],
+                    positions[t[1] as usize],
+                    positions[t[2] as usize],
+                ];
+                ((a + b + c) / 3.0, (b - a).cross(c - a))
+            })
+            .filter(|(_, normal)| normal.magnitude() > 1e-6)
+            .collect()
+    }
+
+    fn assert_outward(shape: Shape) {
+        let (positions, indices) = generate(shape);
+        for (centroid, normal) in faces(&positions, &indices) {
+            assert!(
+                normal.dot(centroid) > 0.0,
+                "{:?} has an inward facing triangle at {:?}",
+                shape,
+                centroid
+            );
+        }
+    }
+
+    #[test]
+    fn icosphere_counts() {
+        let (positions, indices) = generate(Shape::Icosphere { subdivisions: 0 });
+        assert_eq!((positions.len(), indices.len() / 3), (12, 20));
+        let (positions, indices) = generate(Shape::Icosphere { subdivisions: 1 });
+        assert_eq!((positions.len(), indices.len() / 3), (42, 80));
+    }
+
+    #[test]
+    fn cube_counts() {
+        let (positions, indices) = generate(Shape::Cube);
+        assert_eq!((positions.len(), indices.len() / 3), (24, 12));
+    }
+
+    #[test]
+    fn uv_sphere_counts() {
+        let (positions, indices) = generate(Shape::UvSphere {
+            segments: 8,
+            rings: 4,
+        });
+        assert_eq!(positions.len(), 9 * 5);
+        assert_eq!(indices.len() / 3, 8 * 4 * 2);
+    }
+
+    #[test]
+    fn spheres_have_unit_radius() {
+        for &shape in &[
+            Shape::Icosphere { subdivisions: 3 },
+            Shape::UvSphere {
+                segments: 16,
+                rings: 8,
+            },
+        ] {
+            let (positions, _) = generate(shape);
+            for p in positions {
+                assert!((p.magnitude() - 1.0).abs() < 1e-5, "{:?} at {:?}", shape, p);
+            }
+        }
+    }
+
+    #[test]
+    fn closed_shapes_wind_outwards() {
+        assert_outward(Shape::Icosphere { subdivisions: 2 });
+        assert_outward(Shape::UvSphere {
+            segments: 16,
+            rings: 8,
+        });
+        assert_outward(Shape::Cube);
+    }
+
+    #[test]
+    fn torus_winds_away_from_its_tube() {
+        let (positions, indices) = generate(Shape::Torus {
+            major_radius: 0.7,
+            minor_radius: 0.3,
+        });
+        for (centroid, normal) in faces(&positions, &indices) {
+            let around = Vector3::new(centroid.x, centroid.y, 0.0).normalize();
+            assert!(normal.dot(centroid - around * 0.7) > 0.0);
+        }
+    }
+
+    #[test]
+    fn plane_faces_up() {
+        let (positions, indices) = generate(Shape::Plane { divisions: 3 });
+        assert_eq!((positions.len(), indices.len() / 3), (16, 18));
+        for (_, normal) in faces(&positions, &indices) {
+            assert!(normal.z > 0.0);
+        }
+    }
+
+    #[test]
+    fn cluster_has_one_icosphere_per_count() {
+        let (positions, indices) = generate(Shape::SphereCluster {
+            count: 5,
+            min_radius: 0.1,
+            max_radius: 0.4,
+        });
+        assert_eq!(positions.len(), 5 * 162);
+        assert_eq!(indices.len() / 3, 5 * 320);
+    }
+
+    #[test]
+    fn parse_defaults() {
+        assert_eq!(
+            Shape::parse("icosphere"),
+            Some(Ok(Shape::Icosphere { subdivisions: 2 }))
+        );
+        assert_eq!(
+            Shape::parse("uv-sphere"),
+            Some(Ok(Shape::UvSphere {
+                segments: 32,
+                rings: 16
+            }))
+        );
+        assert_eq!(
+            Shape::parse("uv-sphere:12"),
+            Some(Ok(Shape::UvSphere {
+                segments: 12,
+                rings: 6
+            }))
+        );
+        assert_eq!(Shape::parse("cube"), Some(Ok(Shape::Cube)));
+        assert_eq!(
+            Shape::parse("torus:1"),
+            Some(Ok(Shape::Torus {
+                major_radius: 1.0,
+                minor_radius: 0.3
+            }))
+        );
+        assert_eq!(
+            Shape::parse("plane"),
+            Some(Ok(Shape::Plane { divisions: 10 }))
+        );
+        assert_eq!(
+            Shape::parse("cluster:40:0.2"),
+            Some(Ok(Shape::SphereCluster {
+                count: 40,
+                min_radius: 0.2,
+                max_radius: 0.4
+            }))
+        );
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert_eq!(Shape::parse("res/icosphere.glb"), None);
+        assert_eq!(Shape::parse("sphere"), None);
+        assert!(matches!(Shape::parse("icosphere:lots"), Some(Err(_))));
+        assert!(matches!(Shape::parse("torus:0.7:thin"), Some(Err(_))));
+        assert!(matches!(Shape::parse("cluster:10:0.1:"), Some(Err(_))));
+    }
+
+    #[test]
+    fn parse_rejects_counts_out_of_range() {
+        assert_eq!(
+            Shape::parse("icosphere:8"),
+            Some(Ok(Shape::Icosphere { subdivisions: 8 }))
+        );
+        assert!(matches!(Shape::parse("icosphere:9"), Some(Err(_))));
+        assert!(matches!(Shape::parse("icosphere:-1"), Some(Err(_))));
+        assert!(matches!(Shape::parse("icosphere:1.5"), Some(Err(_))));
+        assert!(matches!(Shape::parse("cluster:0"), Some(Err(_))));
+        assert!(matches!(Shape::parse("plane:0"), Some(Err(_))));
+        assert!(matches!(Shape::parse("plane:-4"), Some(Err(_))));
+        assert!(matches!(Shape::parse("uv-sphere:-8"), Some(Err(_))));
+        assert!(matches!(Shape::parse("uv-sphere:2"), Some(Err(_))));
+        assert!(matches!(Shape::parse("uv-sphere:8:1"), Some(Err(_))));
+        assert!(matches!(Shape::parse("cluster:10:nan"), Some(Err(_))));
+        assert!(matches!(Shape::parse("cluster:10:0.1:inf"), Some(Err(_))));
+        assert!(matches!(Shape::parse("cluster:10:-0.1"), Some(Err(_))));
+        assert!(matches!(Shape::parse("cluster:10:0"), Some(Err(_))));
+        assert!(matches!(Shape::parse("cluster:10:0.5:0.2"), Some(Err(_))));
+        // Above the default maximum of 0.4
+        assert!(matches!(Shape::parse("cluster:10:0.5"), Some(Err(_))));
+        assert!(matches!(Shape::parse("torus:nan"), Some(Err(_))));
+        assert!(matches!(Shape::parse("torus:1:-0.3"), Some(Err(_))));
+        assert!(matches!(Shape::parse("torus:-inf"), Some(Err(_))));
+        assert_eq!(
+            Shape::parse("cluster:10:0.3:0.3"),
+            Some(Ok(Shape::SphereCluster {
+                count: 10,
+                min_radius: 0.3,
+                max_radius: 0.3
+            }))
+        );
+        assert_eq!(
+            Shape::parse("uv-sphere:3"),
+            Some(Ok(Shape::UvSphere {
+                segments: 3,
+                rings: 2
+            }))
+        );
+    }
+}
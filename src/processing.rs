@@ -0,0 +1,592 @@
+//! Processing applied to every mesh after loading
+//!
+//! Generates normals for meshes that don't have any, computes tangents for
+//! meshes loaded without them and reorders the triangles of each submesh, first for the
+//! post-transform vertex cache and then for less overdraw.
+use std::collections::HashMap;
+use std::fmt;
+
+use cgmath::prelude::*;
+use cgmath::Vector3;
+
+use crate::{DedupKey, Geometry, Vertex3DColored, VertexNormal, VertexTangent};
+
+/// Size of the LRU cache the triangle order is optimized for
+const CACHE_SIZE: usize = 32;
+/// Size of the FIFO cache simulated to measure ACMR, typical of the post
+/// transform caches of current GPUs
+const FIFO_SIZE: usize = 16;
+
+// Vertex scoring from Tom Forsyth's "Linear-Speed Vertex Cache Optimisation"
+const CACHE_DECAY_POWER: f32 = 1.5;
+const LAST_TRIANGLE_SCORE: f32 = 0.75;
+const VALENCE_BOOST_SCALE: f32 = 2.0;
+const VALENCE_BOOST_POWER: f32 = 0.5;
+
+/// Reordering for overdraw is dropped if it makes the ACMR worse than this
+/// much of the cache optimized order
+const OVERDRAW_ACMR_THRESHOLD: f32 = 1.05;
+
+/// Normals generated for meshes loaded without any
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum NormalMode {
+    /// Averaged across every vertex sharing a position, weighted by face area
+    Smooth,
+    /// The normal of each face, splitting vertices between faces
+    Flat,
+}
+
+impl NormalMode {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "smooth" => Some(NormalMode::Smooth),
+            "flat" => Some(NormalMode::Flat),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct Stats {
+    /// Vertices read by the loader, before welding
+    pub source_vertices: usize,
+    pub vertices: usize,
+    pub triangles: usize,
+    /// Average cache misses per triangle of the loaded triangle order
+    pub acmr_before: f32,
+    /// Average cache misses per triangle of the optimized triangle order
+    pub acmr_after: f32,
+}
+
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} vertices ({} before welding), {} triangles, ACMR {:.3} -> {:.3}",
+            self.vertices, self.source_vertices, self.triangles, self.acmr_before, self.acmr_after
+        )
+    }
+}
+
+pub fn process(geometry: &mut Geometry, normals: NormalMode) -> Stats {
+    if !geometry.normals {
+        match normals {
+            NormalMode::Smooth => smooth_normals(&mut geometry.vertices, &geometry.indices),
+            NormalMode::Flat => flat_normals(&mut geometry.vertices, &mut geometry.indices),
+        }
+        geometry.normals = true;
+    }
+    if !geometry.tangents {
+        generate_tangents(&mut geometry.vertices, &mut geometry.indices);
+        geometry.tangents = true;
+    }
+
+    let acmr_before = acmr(&geometry.indices);
+    for submesh in &geometry.submeshes {
+        let indices = &mut geometry.indices[submesh.start..submesh.start + submesh.count];
+        optimize_vertex_cache(indices, geometry.vertices.len());
+        optimize_overdraw(indices, &geometry.vertices);
+    }
+
+    Stats {
+        source_vertices: geometry.source_vertices,
+        vertices: geometry.vertices.len(),
+        triangles: geometry.indices.len() / 3,
+        acmr_before,
+        acmr_after: acmr(&geometry.indices),
+    }
+}
+
+fn position(vertex: &Vertex3DColored) -> Vector3<f32> {
+    Vector3::from(vertex.position.repr)
+}
+
+/// Twice the area of the triangle, along its normal
+fn face_normal(vertices: &[Vertex3DColored], triangle: &[u32]) -> Vector3<f32> {
+    let corner = |i: usize| position(&vertices[triangle[i] as usize]);
+    (corner(1) - corner(0)).cross(corner(2) - corner(0))
+}
+
+/// Smooth normals across every vertex sharing a position, weighted by face
+/// area. This deliberately ignores texture coordinates so UV seams don't show
+/// up as shading seams.
+fn smooth_normals(vertices: &mut [Vertex3DColored], indices: &[u32]) {
+    let position_key = |vertex: &Vertex3DColored| DedupKey {
+        position: vertex.position.repr,
+        uv: [0.0, 0.0],
+        normal: [0.0; 3],
+        tangent: [0.0; 4],
+    };
+    let mut normals = HashMap::new();
+    for triangle in indices.chunks(3) {
+        let face_normal = face_normal(vertices, triangle);
+        for &index in triangle {
+            *normals
+                .entry(position_key(&vertices[index as usize]))
+                .or_insert_with(Vector3::zero) += face_normal;
+        }
+    }
+    for vertex in vertices.iter_mut() {
+        let normal = normals[&position_key(vertex)];
+        vertex.normal = VertexNormal::new(normal.normalize().into());
+    }
+}
+
+/// Give every triangle its own three vertices, with the face normal
+fn flat_normals(vertices: &mut Vec<Vertex3DColored>, indices: &mut Vec<u32>) {
+    let mut split = Vec::with_capacity(indices.len());
+    for triangle in indices.chunks(3) {
+        let normal = VertexNormal::new(face_normal(vertices, triangle).normalize().into());
+        for &index in triangle {
+            let mut vertex = vertices[index as usize];
+            vertex.normal = normal;
+            split.push(vertex);
+        }
+    }
+    *vertices = split;
+    *indices = (0..indices.len() as u32).collect();
+}
+
+/// MikkTSpace tangents, which is what glTF expects normal maps to be baked
+/// against, with the handedness of the bitangent in `w`. Corners of a vertex
+/// that end up with different tangent frames, such as on either side of a UV
+/// mirror seam, are split into their own vertices.
+fn generate_tangents(vertices: &mut Vec<Vertex3DColored>, indices: &mut [u32]) {
+    struct Corners<'a> {
+        vertices: &'a [Vertex3DColored],
+        indices: &'a [u32],
+        tangents: Vec<[f32; 4]>,
+    }
+
+    impl<'a> Corners<'a> {
+        fn vertex(&self, face: usize, vert: usize) -> &Vertex3DColored {
+            &self.vertices[self.indices[face * 3 + vert] as usize]
+        }
+    }
+
+    impl<'a> mikktspace::Geometry for Corners<'a> {
+        fn num_faces(&self) -> usize {
+            self.indices.len() / 3
+        }
+
+        fn num_vertices_of_face(&self, _face: usize) -> usize {
+            3
+        }
+
+        fn position(&self, face: usize, vert: usize) -> [f32; 3] {
+            self.vertex(face, vert).position.repr
+        }
+
+        fn normal(&self, face: usize, vert: usize) -> [f32; 3] {
+            self.vertex(face, vert).normal.repr
+        }
+
+        fn tex_coord(&self, face: usize, vert: usize) -> [f32; 2] {
+            self.vertex(face, vert).uv.repr
+        }
+
+        fn set_tangent_encoded(&mut self, tangent: [f32; 4], face: usize, vert: usize) {
+            self.tangents[face * 3 + vert] = tangent;
+        }
+    }
+
+    let mut corners = Corners {
+        vertices,
+        indices,
+        tangents: vec![[0.0; 4]; indices.len()],
+    };
+    if !mikktspace::generate_tangents(&mut corners) {
+        eprintln!("Tangent generation failed, normal maps will be ignored");
+        return;
+    }
+    let tangents = corners.tangents;
+
+    // The first tangent seen for a vertex is stored in place, any other gets a
+    // copy of the vertex
+    let mut assigned = vec![false; vertices.len()];
+    let mut split = HashMap::new();
+    for (index, tangent) in indices.iter_mut().zip(tangents) {
+        let quantized = [
+            (tangent[0] * 4096.0) as i32,
+            (tangent[1] * 4096.0) as i32,
+            (tangent[2] * 4096.0) as i32,
+            (tangent[3] * 4096.0) as i32,
+        ];
+        let original = *index;
+        *index = *split.entry((original, quantized)).or_insert_with(|| {
+            if !assigned[original as usize] {
+                assigned[original as usize] = true;
+                vertices[original as usize].tangent = VertexTangent::new(tangent);
+                return original;
+            }
+            let mut vertex = vertices[original as usize];
+            vertex.tangent = VertexTangent::new(tangent);
+            vertices.push(vertex);
+            vertices.len() as u32 - 1
+        });
+    }
+}
+
+/// Average number of vertices transformed per triangle, with a FIFO cache
+fn acmr(indices: &[u32]) -> f32 {
+    if indices.is_empty() {
+        return 0.0;
+    }
+    let mut cache = std::collections::VecDeque::with_capacity(FIFO_SIZE);
+    let mut misses = 0;
+    for &index in indices {
+        if !cache.contains(&index) {
+            misses += 1;
+            if cache.len() == FIFO_SIZE {
+                cache.pop_front();
+            }
+            cache.push_back(index);
+        }
+    }
+    misses as f32 / (indices.len() / 3) as f32
+}
+
+fn vertex_score(cache_position: Option<usize>, remaining: usize) -> f32 {
+    if remaining == 0 {
+        return -1.0;
+    }
+    let cache_score = match cache_position {
+        // The triangle just drawn, which is equally good in any order
+        Some(position) if position < 3 => LAST_TRIANGLE_SCORE,
+        Some(position) => {
+            let scaled = (position - 3) as f32 / (CACHE_SIZE - 3) as f32;
+            (1.0 - scaled).powf(CACHE_DECAY_POWER)
+        }
+        None => 0.0,
+    };
+    // Vertices with few triangles left are finished off first
+    cache_score + VALENCE_BOOST_SCALE * (remaining as f32).powf(-VALENCE_BOOST_POWER)
+}
+
+/// Reorder triangles with Tom Forsyth's greedy algorithm: always draw the
+/// triangle whose vertices score best, given a simulated LRU cache
+fn optimize_vertex_cache(indices: &mut [u32], vertex_count: usize) {
+    let triangle_count = indices.len() / 3;
+
+    // Triangles not yet drawn using each vertex, packed into one array
+    let mut offsets = vec![0; vertex_count + 1];
+    for &index in indices.iter() {
+        offsets[index as usize + 1] += 1;
+    }
+    for i in 0..vertex_count {
+        offsets[i + 1] += offsets[i];
+    }
+    let mut remaining = vec![0; vertex_count];
+    let mut adjacency = vec![0; indices.len()];
+    for (triangle, corners) in indices.chunks(3).enumerate() {
+        for &index in corners {
+            let index = index as usize;
+            adjacency[offsets[index] + remaining[index]] = triangle;
+            remaining[index] += 1;
+        }
+    }
+
+    let mut cache_positions: Vec<Option<usize>> = vec![None; vertex_count];
+    let mut scores: Vec<f32> = remaining.iter().map(|&r| vertex_score(None, r)).collect();
+    let mut drawn = vec![false; triangle_count];
+    let mut cache: Vec<u32> = Vec::with_capacity(CACHE_SIZE + 3);
+    let mut order = Vec::with_capacity(indices.len());
+    let mut next_undrawn = 0;
+    let mut best = None;
+
+    while order.len() < indices.len() {
+        // With nothing in the cache left to use, carry on in the loaded order
+        let triangle = best.unwrap_or_else(|| {
+            while drawn[next_undrawn] {
+                next_undrawn += 1;
+            }
+            next_undrawn
+        });
+        drawn[triangle] = true;
+        let corners = [
+            indices[triangle * 3],
+            indices[triangle * 3 + 1],
+            indices[triangle * 3 + 2],
+        ];
+        order.extend_from_slice(&corners);
+
+        for &index in &corners {
+            let index = index as usize;
+            let live = &mut adjacency[offsets[index]..offsets[index] + remaining[index]];
+            if let Some(position) = live.iter().position(|&t| t == triangle) {
+                let last = live.len() - 1;
+                live.swap(position, last);
+            }
+            remaining[index] -= 1;
+        }
+
+        let mut new_cache = corners.to_vec();
+        new_cache.extend(cache.iter().filter(|index| !corners.contains(index)));
+        for &evicted in new_cache.iter().skip(CACHE_SIZE) {
+            cache_positions[evicted as usize] = None;
+            scores[evicted as usize] = vertex_score(None, remaining[evicted as usize]);
+        }
+        new_cache.truncate(CACHE_SIZE);
+        for (position, &index) in new_cache.iter().enumerate() {
+            let index = index as usize;
+            cache_positions[index] = Some(position);
+            scores[index] = vertex_score(Some(position), remaining[index]);
+        }
+        cache = new_cache;
+
+        best = None;
+        let mut best_score = f32::MIN;
+        for &index in &cache {
+            let index = index as usize;
+            for &candidate in &adjacency[offsets[index]..offsets[index] + remaining[index]] {
+                let score: f32 = indices[candidate * 3..candidate * 3 + 3]
+                    .iter()
+                    .map(|&i| scores[i as usize])
+                    .sum();
+                if score > best_score {
+                    best_score = score;
+                    best = Some(candidate);
+                }
+            }
+        }
+    }
+
+    indices.copy_from_slice(&order);
+}
+
+/// Reorder clusters of triangles so those facing outwards from the middle of
+/// the mesh are drawn first, and hide what's behind them from the fragment
+/// shader. Clusters start wherever the cache simulation misses on all three
+/// vertices, so moving them around barely affects the vertex cache.
+fn optimize_overdraw(indices: &mut [u32], vertices: &[Vertex3DColored]) {
+    let triangle_count = indices.len() / 3;
+    if triangle_count == 0 {
+        return;
+    }
+
+    let mut cluster_starts = Vec::new();
+    let mut cache = std::collections::VecDeque::with_capacity(FIFO_SIZE);
+    for (triangle, corners) in indices.chunks(3).enumerate() {
+        let mut misses = 0;
+        for &index in corners {
+            if !cache.contains(&index) {
+                misses += 1;
+                if cache.len() == FIFO_SIZE {
+                    cache.pop_front();
+                }
+                cache.push_back(index);
+            }
+        }
+        if misses == 3 {
+            cluster_starts.push(triangle);
+        }
+    }
+    if cluster_starts.len() < 2 {
+        return;
+    }
+    cluster_starts.push(triangle_count);
+
+    let centroid = |triangle: &[u32]| {
+        triangle
+            .iter()
+            .map(|&i| position(&vertices[i as usize]))
+            .sum::<Vector3<f32>>()
+            / 3.0
+    };
+    let mesh_center = indices.chunks(3).map(centroid).sum::<Vector3<f32>>() / triangle_count as f32;
+
+    let mut clusters: Vec<(f32, &[u32])> = cluster_starts
+        .windows(2)
+        .map(|range| {
+            let triangles = &indices[range[0] * 3..range[1] * 3];
+            let mut center = Vector3::zero();
+            let mut normal = Vector3::zero();
+            let mut area = 0.0;
+            for triangle in triangles.chunks(3) {
+                let face_normal = face_normal(vertices, triangle);
+                let weight = face_normal.magnitude();
+                center += centroid(triangle) * weight;
+                normal += face_normal;
+                area += weight;
+            }
+            let facing = if area > 0.0 && normal.magnitude2() > 0.0 {
+                (center / area - mesh_center).dot(normal.normalize())
+            } else {
+                0.0
+            };
+            (facing, triangles)
+        })
+        .collect();
+    clusters.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap_or(std::cmp::Ordering::Equal));
+
+    let order: Vec<u32> = clusters
+        .iter()
+        .flat_map(|(_, triangles)| triangles.iter().copied())
+        .collect();
+    if acmr(&order) <= acmr(indices) * OVERDRAW_ACMR_THRESHOLD {
+        indices.copy_from_slice(&order);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    /// A grid of `size` by `size` quads, two triangles each, in row order
+    fn grid(size: u32) -> Vec<u32> {
+        let index = |x: u32, y: u32| y * (size + 1) + x;
+        let mut indices = Vec::new();
+        for y in 0..size {
+            for x in 0..size {
+                let (a, b) = (index(x, y), index(x + 1, y));
+                let (c, d) = (index(x + 1, y + 1), index(x, y + 1));
+                indices.extend_from_slice(&[a, b, c, a, c, d]);
+            }
+        }
+        indices
+    }
+
+    fn sorted_triangles(indices: &[u32]) -> Vec<[u32; 3]> {
+        let mut triangles: Vec<_> = indices.chunks(3).map(|t| [t[0], t[1], t[2]]).collect();
+        triangles.sort();
+        triangles
+    }
+
+    #[test]
+    fn acmr_of_a_strip() {
+        // Each triangle after the first reuses two vertices of the last
+        let strip: Vec<u32> = (0..10).flat_map(|i| vec![i, i + 1, i + 2]).collect();
+        assert_eq!(acmr(&strip), 12.0 / 10.0);
+        assert_eq!(acmr(&[]), 0.0);
+        // Disjoint triangles miss every vertex
+        let soup: Vec<u32> = (0..30).collect();
+        assert_eq!(acmr(&soup), 3.0);
+    }
+
+    #[test]
+    fn acmr_counts_fifo_evictions() {
+        // Six disjoint triangles fill the cache with vertices 2 to 17, so
+        // only 17 is still cached when it is used again
+        let mut indices: Vec<u32> = (0..FIFO_SIZE as u32 + 2).collect();
+        indices.extend_from_slice(&[0, 1, FIFO_SIZE as u32 + 1]);
+        assert_eq!(acmr(&indices), (FIFO_SIZE + 4) as f32 / 7.0);
+    }
+
+    #[test]
+    fn vertex_cache_optimization_keeps_every_triangle() {
+        let mut indices = grid(24);
+        // Shuffle the triangles, keeping each one's winding
+        let mut rng = StdRng::seed_from_u64(1);
+        let mut triangles: Vec<[u32; 3]> = sorted_triangles(&indices);
+        for i in (1..triangles.len()).rev() {
+            triangles.swap(i, rng.gen_range(0, i + 1));
+        }
+        indices = triangles.iter().flat_map(|t| t.iter().copied()).collect();
+        let before = acmr(&indices);
+
+        let mut optimized = indices.clone();
+        optimize_vertex_cache(&mut optimized, 25 * 25);
+        assert_eq!(sorted_triangles(&optimized), sorted_triangles(&indices));
+        assert!(acmr(&optimized) <= before);
+        // A grid can't do better than 0.5, shuffled it is close to 3
+        assert!(acmr(&optimized) < 1.0, "ACMR {}", acmr(&optimized));
+    }
+
+    #[test]
+    fn vertex_cache_optimization_of_an_ordered_grid() {
+        let indices = grid(8);
+        let mut optimized = indices.clone();
+        optimize_vertex_cache(&mut optimized, 9 * 9);
+        assert_eq!(sorted_triangles(&optimized), sorted_triangles(&indices));
+        assert!(acmr(&optimized) <= acmr(&indices));
+    }
+
+    #[test]
+    fn vertex_cache_optimization_of_disconnected_triangles() {
+        let mut indices: Vec<u32> = (0..12).rev().collect();
+        optimize_vertex_cache(&mut indices, 12);
+        assert_eq!(
+            sorted_triangles(&indices),
+            sorted_triangles(&(0..12).rev().collect::<Vec<_>>())
+        );
+    }
+
+    #[test]
+    fn tangents_follow_u() {
+        let vertex =
+            |p: [f32; 3], uv: [f32; 2]| Vertex3DColored::new(p, [1.0; 4], [0.0, 0.0, 1.0], uv);
+        let mut vertices = vec![
+            vertex([0.0, 0.0, 0.0], [0.0, 0.0]),
+            vertex([1.0, 0.0, 0.0], [1.0, 0.0]),
+            vertex([0.0, 1.0, 0.0], [0.0, 1.0]),
+            // Mirrored in u
+            vertex([0.0, 0.0, 1.0], [1.0, 0.0]),
+            vertex([1.0, 0.0, 1.0], [0.0, 0.0]),
+            vertex([0.0, 1.0, 1.0], [1.0, 1.0]),
+        ];
+        generate_tangents(&mut vertices, &mut [0, 1, 2, 3, 4, 5]);
+        let assert_tangent = |vertex: &Vertex3DColored, expected: [f32; 4]| {
+            for (a, b) in vertex.tangent.repr.iter().zip(&expected) {
+                assert!((a - b).abs() < 1e-5, "{:?}", vertex.tangent.repr);
+            }
+        };
+        for vertex in &vertices[..3] {
+            assert_tangent(vertex, [1.0, 0.0, 0.0, 1.0]);
+        }
+        for vertex in &vertices[3..] {
+            assert_tangent(vertex, [-1.0, 0.0, 0.0, -1.0]);
+        }
+    }
+
+    #[test]
+    fn mirror_seam_splits_vertices() {
+        let vertex =
+            |p: [f32; 3], uv: [f32; 2]| Vertex3DColored::new(p, [1.0; 4], [0.0, 0.0, 1.0], uv);
+        // Two triangles sharing the edge from 0 to 1, with u increasing
+        // towards the seam from both sides
+        let mut vertices = vec![
+            vertex([0.0, 0.0, 0.0], [1.0, 0.0]),
+            vertex([0.0, 1.0, 0.0], [1.0, 1.0]),
+            vertex([-1.0, 0.0, 0.0], [0.0, 0.0]),
+            vertex([1.0, 0.0, 0.0], [0.0, 0.0]),
+        ];
+        let mut indices = [2, 0, 1, 0, 3, 1];
+        generate_tangents(&mut vertices, &mut indices);
+
+        assert_eq!(vertices.len(), 6);
+        assert_eq!(&indices[..3], &[2, 0, 1]);
+        assert_eq!(indices[4], 3);
+        // The seam vertices are split, keeping everything but the tangent
+        for &(left, right) in &[(indices[1], indices[3]), (indices[2], indices[5])] {
+            assert_ne!(left, right);
+            let (left, right) = (&vertices[left as usize], &vertices[right as usize]);
+            assert_eq!(left.position.repr, right.position.repr);
+            assert_eq!(left.uv.repr, right.uv.repr);
+            for (a, b) in left.tangent.repr.iter().zip(&[1.0, 0.0, 0.0, 1.0]) {
+                assert!((a - b).abs() < 1e-5, "{:?}", left.tangent.repr);
+            }
+            for (a, b) in right.tangent.repr.iter().zip(&[-1.0, 0.0, 0.0, -1.0]) {
+                assert!((a - b).abs() < 1e-5, "{:?}", right.tangent.repr);
+            }
+        }
+    }
+
+    #[test]
+    fn smooth_uvs_keep_shared_vertices() {
+        let vertex =
+            |p: [f32; 3], uv: [f32; 2]| Vertex3DColored::new(p, [1.0; 4], [0.0, 0.0, 1.0], uv);
+        let mut vertices = vec![
+            vertex([0.0, 0.0, 0.0], [0.0, 0.0]),
+            vertex([1.0, 0.0, 0.0], [1.0, 0.0]),
+            vertex([1.0, 1.0, 0.0], [1.0, 1.0]),
+            vertex([0.0, 1.0, 0.0], [0.0, 1.0]),
+        ];
+        let mut indices = [0, 1, 2, 0, 2, 3];
+        generate_tangents(&mut vertices, &mut indices);
+        assert_eq!(vertices.len(), 4);
+        assert_eq!(indices, [0, 1, 2, 0, 2, 3]);
+    }
+}
@@ -0,0 +1,99 @@
+//! Scene graph of transformed nodes referencing meshes
+use cgmath::prelude::*;
+use cgmath::Matrix4;
+
+use crate::culling::BoundingSphere;
+
+/// Handle to a node of a [`Scene`]
+#[derive(Copy, Clone, Debug, Eq, Hash, PartialEq)]
+pub struct NodeId(usize);
+
+pub struct Node {
+    /// Transform from this node's space to its parent's
+    pub local: Matrix4<f32>,
+    /// Index of the mesh drawn at this node, if any
+    pub mesh: Option<usize>,
+    children: Vec<NodeId>,
+}
+
+/// A mesh to draw, with the transform from its space to world space
+#[derive(Copy, Clone, Debug)]
+pub struct Draw {
+    pub mesh: usize,
+    pub model: Matrix4<f32>,
+}
+
+pub struct Scene {
+    nodes: Vec<Node>,
+    roots: Vec<NodeId>,
+}
+
+impl Scene {
+    pub fn new() -> Self {
+        Self {
+            nodes: Vec::new(),
+            roots: Vec::new(),
+        }
+    }
+
+    /// Add a node under `parent`, or at the root of the scene if `parent` is
+    /// `None`
+    pub fn add_node(
+        &mut self,
+        parent: Option<NodeId>,
+        local: Matrix4<f32>,
+        mesh: Option<usize>,
+    ) -> NodeId {
+        let id = NodeId(self.nodes.len());
+        self.nodes.push(Node {
+            local,
+            mesh,
+            children: Vec::new(),
+        });
+        match parent {
+            Some(parent) => self.nodes[parent.0].children.push(id),
+            None => self.roots.push(id),
+        }
+        id
+    }
+
+    pub fn node(&self, id: NodeId) -> &Node {
+        &self.nodes[id.0]
+    }
+
+    pub fn node_mut(&mut self, id: NodeId) -> &mut Node {
+        &mut self.nodes[id.0]
+    }
+
+    /// Every node with a mesh, along with its world transform. Parents come
+    /// before their children.
+    pub fn draws(&self) -> Vec<Draw> {
+        let mut draws = Vec::new();
+        let mut stack: Vec<_> = self
+            .roots
+            .iter()
+            .rev()
+            .map(|&id| (id, Matrix4::identity()))
+            .collect();
+
+        while let Some((id, parent_transform)) = stack.pop() {
+            let node = self.node(id);
+            let world = parent_transform * node.local;
+            if let Some(mesh) = node.mesh {
+                draws.push(Draw { mesh, model: world });
+            }
+            stack.extend(node.children.iter().rev().map(|&child| (child, world)));
+        }
+
+        draws
+    }
+}
+
+/// Radius of a sphere around the origin containing every one of `bounds`,
+/// which are in world space
+pub fn bounding_radius<I: IntoIterator<Item = BoundingSphere>>(bounds: I) -> f32 {
+    bounds
+        .into_iter()
+        .map(|bounds| bounds.center.to_vec().magnitude() + bounds.radius)
+        .fold(0.0, f32::max)
+}
@@ -0,0 +1,231 @@
+//! Renderer parameters that can be changed at runtime
+
+/// Curve used to map the HDR composite into displayable range
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum ToneMapper {
+    Clamp = 0,
+    Reinhard = 1,
+    Aces = 2,
+}
+
+impl ToneMapper {
+    pub const ALL: [ToneMapper; 3] = [ToneMapper::Clamp, ToneMapper::Reinhard, ToneMapper::Aces];
+    pub const NAMES: [&'static str; 3] = ["Clamp", "Reinhard", "ACES"];
+}
+
+/// How vertex colors change over time
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum ColorAnimation {
+    /// Every channel creeps up and wraps around
+    Cycle = 0,
+    /// The hue of each vertex turns around the color wheel
+    Hue = 1,
+    /// Bands of brightness travel through world space
+    Wave = 2,
+    /// Each vertex flickers with its own noise
+    Flicker = 3,
+    /// Vertices loop through the colors of a gradient
+    Palette = 4,
+}
+
+impl ColorAnimation {
+    pub const ALL: [ColorAnimation; 5] = [
+        ColorAnimation::Cycle,
+        ColorAnimation::Hue,
+        ColorAnimation::Wave,
+        ColorAnimation::Flicker,
+        ColorAnimation::Palette,
+    ];
+    pub const NAMES: [&'static str; 5] = ["Cycle", "Hue", "Wave", "Flicker", "Palette"];
+
+    /// The animation named `name`, in any case
+    pub fn parse(name: &str) -> Option<Self> {
+        Self::NAMES
+            .iter()
+            .position(|n| n.eq_ignore_ascii_case(name))
+            .map(|i| Self::ALL[i])
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Settings {
+    /// Blur radius growth per blur iteration, in texels
+    pub blur_radius_factor: f32,
+    /// Luminance above which fragments feed the bloom
+    pub bloom_threshold: f32,
+    /// Whether the blurred bright pass is added to the composite
+    pub bloom: bool,
+    /// Linear scale applied before tone mapping
+    pub exposure: f32,
+    pub tone_mapper: ToneMapper,
+    /// Scale applied to material emissive colors
+    pub emissive_strength: f32,
+    /// Vertical field of view, in degrees
+    pub fov: f32,
+    /// Camera movement per frame while a movement key is held
+    pub move_speed: f32,
+    pub light: LightSettings,
+    /// Whether the orbiting point and spot lights are shaded
+    pub point_lights: bool,
+    /// Number of orbiting point lights, besides the spot light
+    pub point_light_count: usize,
+    /// Whether the point and spot lights are drawn as glowing spheres
+    pub light_spheres: bool,
+    /// Whether the directional light casts shadows
+    pub shadows: bool,
+    /// Depth offset against shadow acne, in shadow map depth units
+    pub shadow_bias: f32,
+    /// Radius of the percentage-closer filtering kernel, in texels
+    pub shadow_pcf_radius: u32,
+    /// Whether screen-space ambient occlusion darkens the composite
+    pub ssao: bool,
+    /// View space distance searched for occluders
+    pub ssao_radius: f32,
+    /// Depth difference below which SSAO samples don't count as occluded
+    pub ssao_bias: f32,
+    /// How much of the occlusion is applied, from 0 to 1
+    pub ssao_strength: f32,
+    pub depth_of_field: DepthOfFieldSettings,
+    /// Scale applied to image-based lighting, when an environment is loaded
+    pub environment_strength: f32,
+    /// Whether the environment is drawn behind the scene
+    pub skybox: bool,
+    /// Whether the instanced copies requested with `--instances` are drawn
+    pub instances: bool,
+    /// Whether scene nodes outside the camera's view are skipped
+    pub frustum_culling: bool,
+    /// Whether glTF animations play, rather than holding the rest pose
+    pub animations: bool,
+    pub color_animation: ColorAnimation,
+    /// Rate the vertex color animation plays at, 1 being normal speed
+    pub color_animation_speed: f32,
+    pub audio: AudioSettings,
+}
+
+/// How the track passed with `--audio` modulates the scene
+#[derive(Clone, Debug)]
+pub struct AudioSettings {
+    pub enabled: bool,
+    /// Brightness added to the vertex colors at full bass energy
+    pub color_gain: f32,
+    /// Bloom strength added at full energy in the upper bands
+    pub bloom_gain: f32,
+}
+
+impl Default for AudioSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            color_gain: 1.0,
+            bloom_gain: 1.0,
+        }
+    }
+}
+
+/// Lens parameters for the depth of field pass
+#[derive(Clone, Debug)]
+pub struct DepthOfFieldSettings {
+    pub enabled: bool,
+    /// Focus on whatever is at the center of the screen instead of at
+    /// `focus_distance`
+    pub autofocus: bool,
+    /// Eye space distance that is in perfect focus
+    pub focus_distance: f32,
+    /// Scale of the circle of confusion, larger is blurrier
+    pub aperture: f32,
+    /// Largest circle of confusion, in pixels
+    pub max_radius: f32,
+}
+
+impl Default for DepthOfFieldSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            autofocus: true,
+            focus_distance: 4.0,
+            aperture: 0.5,
+            max_radius: 12.0,
+        }
+    }
+}
+
+/// The directional light used by the geometry pass
+#[derive(Clone, Debug)]
+pub struct LightSettings {
+    /// Compass direction the light comes from, in degrees around +Z
+    pub azimuth: f32,
+    /// Angle of the light above the XY plane, in degrees
+    pub elevation: f32,
+    pub color: [f32; 3],
+    pub intensity: f32,
+    /// Uniform ambient term, as a fraction of the base color
+    pub ambient: f32,
+}
+
+impl LightSettings {
+    /// Direction the light travels in
+    pub fn direction(&self) -> [f32; 3] {
+        let (azimuth, elevation) = (self.azimuth.to_radians(), self.elevation.to_radians());
+        [
+            -elevation.cos() * azimuth.cos(),
+            -elevation.cos() * azimuth.sin(),
+            -elevation.sin(),
+        ]
+    }
+
+    /// Color scaled by intensity
+    pub fn radiance(&self) -> [f32; 3] {
+        [
+            self.color[0] * self.intensity,
+            self.color[1] * self.intensity,
+            self.color[2] * self.intensity,
+        ]
+    }
+}
+
+impl Default for LightSettings {
+    fn default() -> Self {
+        Self {
+            azimuth: 45.0,
+            elevation: 60.0,
+            color: [1.0, 1.0, 1.0],
+            intensity: 1.0,
+            ambient: 0.15,
+        }
+    }
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            blur_radius_factor: 0.25,
+            bloom_threshold: 0.8,
+            bloom: true,
+            exposure: 1.0,
+            tone_mapper: ToneMapper::Clamp,
+            emissive_strength: 1.0,
+            fov: 75.0,
+            move_speed: 0.1,
+            light: LightSettings::default(),
+            point_lights: true,
+            point_light_count: 4,
+            light_spheres: true,
+            shadows: true,
+            shadow_bias: 0.005,
+            shadow_pcf_radius: 1,
+            ssao: true,
+            ssao_radius: 0.3,
+            ssao_bias: 0.01,
+            ssao_strength: 1.0,
+            depth_of_field: DepthOfFieldSettings::default(),
+            environment_strength: 1.0,
+            skybox: true,
+            instances: true,
+            frustum_culling: true,
+            animations: true,
+            color_animation: ColorAnimation::Cycle,
+            color_animation_speed: 1.0,
+            audio: AudioSettings::default(),
+        }
+    }
+}
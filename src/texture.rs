@@ -0,0 +1,207 @@
+//! Material textures decoded from glTF images
+use std::collections::HashMap;
+
+use luminance::context::GraphicsContext;
+use luminance::pixel::{NormRGBA8UI, Pixel, SRGBA8UI};
+use luminance::texture::{Dim2, Flat, GenMipmaps, MagFilter, MinFilter, Sampler, Texture, Wrap};
+
+use crate::error::LuminanceError;
+use crate::material::{Material, TextureSlot};
+
+/// Colors (base color, emissive), which the sampler decodes to linear
+pub type ColorTexture = Texture<Flat, Dim2, SRGBA8UI>;
+/// Data (normals, metalness, roughness, occlusion), sampled as stored
+pub type DataTexture = Texture<Flat, Dim2, NormRGBA8UI>;
+
+/// Texels as they are stored in both kinds of texture
+type Rgba8 = (u8, u8, u8, u8);
+
+/// A glTF texture: an image plus the sampler state used to read it
+#[derive(Copy, Clone, Debug)]
+pub struct TextureSource {
+    pub image: usize,
+    pub sampler: Sampler,
+}
+
+impl TextureSource {
+    pub fn from_gltf(texture: &gltf::Texture) -> Self {
+        use gltf::texture::{MagFilter as GltfMag, MinFilter as GltfMin, WrappingMode};
+
+        let gltf_sampler = texture.sampler();
+        let wrap = |mode| match mode {
+            WrappingMode::ClampToEdge => Wrap::ClampToEdge,
+            WrappingMode::MirroredRepeat => Wrap::MirroredRepeat,
+            WrappingMode::Repeat => Wrap::Repeat,
+        };
+
+        let mut sampler = Sampler::default();
+        sampler.wrap_s = wrap(gltf_sampler.wrap_s());
+        sampler.wrap_t = wrap(gltf_sampler.wrap_t());
+        sampler.mag_filter = match gltf_sampler.mag_filter() {
+            Some(GltfMag::Nearest) => MagFilter::Nearest,
+            Some(GltfMag::Linear) | None => MagFilter::Linear,
+        };
+        sampler.min_filter = match gltf_sampler.min_filter() {
+            Some(GltfMin::Nearest) => MinFilter::Nearest,
+            Some(GltfMin::Linear) => MinFilter::Linear,
+            Some(GltfMin::NearestMipmapNearest) => MinFilter::NearestMipmapNearest,
+            Some(GltfMin::LinearMipmapNearest) => MinFilter::LinearMipmapNearest,
+            Some(GltfMin::NearestMipmapLinear) => MinFilter::NearestMipmapLinear,
+            Some(GltfMin::LinearMipmapLinear) | None => MinFilter::LinearMipmapLinear,
+        };
+
+        Self {
+            image: texture.source().index(),
+            sampler,
+        }
+    }
+}
+
+/// All the textures referenced by a set of materials, uploaded to the GPU,
+/// plus neutral fallbacks for slots a material leaves empty
+pub struct TextureCache {
+    colors: HashMap<usize, ColorTexture>,
+    data: HashMap<usize, DataTexture>,
+    white: ColorTexture,
+    white_data: DataTexture,
+    flat_normal: DataTexture,
+}
+
+impl TextureCache {
+    pub fn new<C: GraphicsContext>(
+        c: &mut C,
+        materials: &[Material],
+        sources: &[TextureSource],
+        images: &[gltf::image::Data],
+    ) -> Result<Self, LuminanceError> {
+        let mut colors = HashMap::new();
+        let mut data = HashMap::new();
+
+        for material in materials {
+            let color_slots = [material.base_color_texture, material.emissive_texture];
+            let data_slots = [
+                material.metallic_roughness_texture,
+                material.normal_texture,
+                material.occlusion_texture,
+            ];
+
+            for slot in color_slots.iter().filter_map(|slot| slot.as_ref()) {
+                check_tex_coord(slot);
+                if !colors.contains_key(&slot.texture) {
+                    let source = &sources[slot.texture];
+                    let texture = upload(c, &images[source.image], &source.sampler)?;
+                    colors.insert(slot.texture, texture);
+                }
+            }
+            for slot in data_slots.iter().filter_map(|slot| slot.as_ref()) {
+                check_tex_coord(slot);
+                if !data.contains_key(&slot.texture) {
+                    let source = &sources[slot.texture];
+                    let texture = upload(c, &images[source.image], &source.sampler)?;
+                    data.insert(slot.texture, texture);
+                }
+            }
+        }
+
+        Ok(Self {
+            colors,
+            data,
+            white: solid(c, (255, 255, 255, 255))?,
+            white_data: solid(c, (255, 255, 255, 255))?,
+            // 128 is as close to 0 as 8 bits get once remapped to [-1, 1]
+            flat_normal: solid(c, (128, 128, 255, 255))?,
+        })
+    }
+
+    /// The texture for a color slot, or white if the slot is empty
+    pub fn color(&self, slot: Option<TextureSlot>) -> &ColorTexture {
+        get(&self.colors, slot, &self.white)
+    }
+
+    /// The texture for a data slot, or white if the slot is empty
+    pub fn data(&self, slot: Option<TextureSlot>) -> &DataTexture {
+        get(&self.data, slot, &self.white_data)
+    }
+
+    /// The texture for a normal map slot, or a flat normal map if the slot is
+    /// empty
+    pub fn normal(&self, slot: Option<TextureSlot>) -> &DataTexture {
+        get(&self.data, slot, &self.flat_normal)
+    }
+}
+
+fn get<'a, T>(
+    textures: &'a HashMap<usize, T>,
+    slot: Option<TextureSlot>,
+    fallback: &'a T,
+) -> &'a T {
+    slot.and_then(|slot| textures.get(&slot.texture))
+        .unwrap_or(fallback)
+}
+
+fn check_tex_coord(slot: &TextureSlot) {
+    if slot.tex_coord != 0 {
+        eprintln!(
+            "Texture {} uses TEXCOORD_{}, only TEXCOORD_0 is supported",
+            slot.texture, slot.tex_coord
+        );
+    }
+}
+
+/// Upload a decoded glTF image as 8-bit texels, generating a full mipmap
+/// chain
+fn upload<C, P>(
+    c: &mut C,
+    image: &gltf::image::Data,
+    sampler: &Sampler,
+) -> Result<Texture<Flat, Dim2, P>, LuminanceError>
+where
+    C: GraphicsContext,
+    P: Pixel<Encoding = Rgba8>,
+{
+    use gltf::image::Format;
+
+    let channels = match image.format {
+        Format::R8 => 1,
+        Format::R8G8 => 2,
+        Format::R8G8B8 => 3,
+        Format::R8G8B8A8 => 4,
+    };
+
+    let texels: Vec<Rgba8> = image
+        .pixels
+        .chunks(channels)
+        .map(|p| match channels {
+            1 => (p[0], p[0], p[0], 255),
+            2 => (p[0], p[0], p[0], p[1]),
+            3 => (p[0], p[1], p[2], 255),
+            _ => (p[0], p[1], p[2], p[3]),
+        })
+        .collect();
+
+    let largest_side = image.width.max(image.height).max(1);
+    let mipmaps = (32 - largest_side.leading_zeros() - 1) as usize;
+    let texture = Texture::new(c, [image.width, image.height], mipmaps, sampler)?;
+    texture.upload(GenMipmaps::Yes, &texels);
+
+    Ok(texture)
+}
+
+/// A 1x1 texture of a single texel
+fn solid<C, P>(c: &mut C, texel: Rgba8) -> Result<Texture<Flat, Dim2, P>, LuminanceError>
+where
+    C: GraphicsContext,
+    P: Pixel<Encoding = Rgba8>,
+{
+    let texture = Texture::new(c, [1, 1], 0, &Sampler::default())?;
+    texture.upload(GenMipmaps::No, &[texel]);
+    Ok(texture)
+}
+
+pub fn srgb_to_linear(value: f32) -> f32 {
+    if value <= 0.04045 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
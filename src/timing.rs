@@ -0,0 +1,295 @@
+//! GPU and CPU frame timing
+//!
+//! GPU work is timed with timestamp queries placed around each pass. Results
+//! are read back a few frames later so waiting on them never stalls the
+//! pipeline.
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::time::Instant;
+
+use gl::types::GLuint;
+
+/// Number of frames of queries kept in flight before their results are read
+const FRAMES_IN_FLIGHT: usize = 4;
+
+/// Timestamp queries issued during one frame
+struct FrameQueries {
+    frame: u64,
+    sections: Vec<&'static str>,
+    /// Start and end query for each section
+    queries: Vec<GLuint>,
+    cpu_ms: f64,
+    pending: bool,
+}
+
+impl FrameQueries {
+    fn new() -> Self {
+        Self {
+            frame: 0,
+            sections: Vec::new(),
+            queries: Vec::new(),
+            cpu_ms: 0.0,
+            pending: false,
+        }
+    }
+
+    fn timestamp(&mut self, query_index: usize) {
+        if query_index == self.queries.len() {
+            let mut query = 0;
+            unsafe { gl::GenQueries(1, &mut query) };
+            self.queries.push(query);
+        }
+        unsafe { gl::QueryCounter(self.queries[query_index], gl::TIMESTAMP) };
+    }
+
+    /// Read back the results, blocking if they aren't available yet
+    fn resolve(&mut self) -> FrameTimings {
+        self.pending = false;
+
+        let passes = self
+            .sections
+            .iter()
+            .enumerate()
+            .map(|(i, name)| {
+                let (mut start, mut end) = (0u64, 0u64);
+                unsafe {
+                    gl::GetQueryObjectui64v(self.queries[2 * i], gl::QUERY_RESULT, &mut start);
+                    gl::GetQueryObjectui64v(self.queries[2 * i + 1], gl::QUERY_RESULT, &mut end);
+                }
+                (*name, end.saturating_sub(start) as f64 / 1.0e6)
+            })
+            .collect();
+
+        FrameTimings {
+            frame: self.frame,
+            cpu_ms: self.cpu_ms,
+            passes,
+        }
+    }
+}
+
+impl Drop for FrameQueries {
+    fn drop(&mut self) {
+        if !self.queries.is_empty() {
+            unsafe { gl::DeleteQueries(self.queries.len() as i32, self.queries.as_ptr()) };
+        }
+    }
+}
+
+/// Timings for a single frame, in milliseconds
+#[derive(Clone, Debug)]
+pub struct FrameTimings {
+    pub frame: u64,
+    pub cpu_ms: f64,
+    pub passes: Vec<(&'static str, f64)>,
+}
+
+/// A section opened by [`GpuProfiler::begin`]
+#[must_use]
+pub struct Section(usize);
+
+pub struct GpuProfiler {
+    frames: Vec<FrameQueries>,
+    current: usize,
+    frame: u64,
+    frame_start: Option<Instant>,
+    interval: usize,
+    window: Vec<FrameTimings>,
+    averages: Option<FrameTimings>,
+    report: bool,
+    csv: Option<BufWriter<File>>,
+}
+
+impl GpuProfiler {
+    /// Create a profiler that averages over `interval` frames, optionally
+    /// logging every frame to a CSV file at `csv_path`.
+    pub fn new(interval: usize, csv_path: Option<&Path>) -> io::Result<Self> {
+        let csv = match csv_path {
+            Some(path) => {
+                let mut csv = BufWriter::new(File::create(path)?);
+                // One row per pass rather than per frame, since passes that
+                // can be toggled at runtime come and go
+                writeln!(csv, "frame,pass,ms")?;
+                Some(csv)
+            }
+            None => None,
+        };
+
+        Ok(Self {
+            frames: (0..FRAMES_IN_FLIGHT).map(|_| FrameQueries::new()).collect(),
+            current: 0,
+            frame: 0,
+            frame_start: None,
+            interval: interval.max(1),
+            window: Vec::new(),
+            averages: None,
+            report: true,
+            csv,
+        })
+    }
+
+    /// Toggle periodic printing of the averages to stderr
+    pub fn toggle_report(&mut self) -> bool {
+        self.report = !self.report;
+        self.report
+    }
+
+    /// The most recent averages, if a full interval has been measured
+    pub fn averages(&self) -> Option<&FrameTimings> {
+        self.averages.as_ref()
+    }
+
+    /// Start a new frame. Must be called once per frame, before any sections
+    /// are opened.
+    pub fn begin_frame(&mut self) -> io::Result<()> {
+        let now = Instant::now();
+        if let Some(start) = self.frame_start.replace(now) {
+            let elapsed = now - start;
+            self.frames[self.current].cpu_ms =
+                elapsed.as_secs() as f64 * 1.0e3 + elapsed.subsec_nanos() as f64 / 1.0e6;
+        }
+
+        self.current = (self.current + 1) % self.frames.len();
+        if self.frames[self.current].pending {
+            let timings = self.frames[self.current].resolve();
+            self.record(timings)?;
+        }
+
+        let queries = &mut self.frames[self.current];
+        queries.frame = self.frame;
+        queries.sections.clear();
+        queries.pending = true;
+        self.frame += 1;
+
+        Ok(())
+    }
+
+    /// Open a timed section. Sections must not overlap.
+    pub fn begin(&mut self, name: &'static str) -> Section {
+        let queries = &mut self.frames[self.current];
+        let index = queries.sections.len();
+        queries.sections.push(name);
+        queries.timestamp(2 * index);
+        Section(index)
+    }
+
+    /// Close a section opened by `begin`
+    pub fn end(&mut self, section: Section) {
+        self.frames[self.current].timestamp(2 * section.0 + 1);
+    }
+
+    fn record(&mut self, timings: FrameTimings) -> io::Result<()> {
+        if let Some(csv) = self.csv.as_mut() {
+            write_csv_rows(csv, &timings)?;
+        }
+
+        self.window.push(timings);
+        if self.window.len() >= self.interval {
+            let averages = average(&self.window);
+            self.window.clear();
+
+            if self.report {
+                eprintln!(
+                    "Frame timings over {} frames (ms): cpu {:.3}",
+                    self.interval, averages.cpu_ms
+                );
+                for (name, ms) in &averages.passes {
+                    eprintln!("  {:<20} {:.3}", name, ms);
+                }
+            }
+            self.averages = Some(averages);
+        }
+
+        Ok(())
+    }
+}
+
+/// The CPU time and each pass of a frame, as `frame,pass,ms` rows
+fn write_csv_rows<W: Write>(csv: &mut W, timings: &FrameTimings) -> io::Result<()> {
+    writeln!(csv, "{},cpu,{:.4}", timings.frame, timings.cpu_ms)?;
+    for (name, ms) in &timings.passes {
+        writeln!(csv, "{},{},{:.4}", timings.frame, name, ms)?;
+    }
+    Ok(())
+}
+
+/// Average of every pass that ran during the window, over the frames it ran
+/// in, in the order the passes first appeared
+fn average(window: &[FrameTimings]) -> FrameTimings {
+    let mut passes: Vec<(&'static str, f64, usize)> = Vec::new();
+    for timings in window {
+        for &(name, ms) in &timings.passes {
+            match passes.iter_mut().find(|(n, _, _)| *n == name) {
+                Some(pass) => {
+                    pass.1 += ms;
+                    pass.2 += 1;
+                }
+                None => passes.push((name, ms, 1)),
+            }
+        }
+    }
+
+    FrameTimings {
+        frame: window[window.len() - 1].frame,
+        cpu_ms: window.iter().map(|t| t.cpu_ms).sum::<f64>() / window.len() as f64,
+        passes: passes
+            .into_iter()
+            .map(|(name, total, frames)| (name, total / frames as f64))
+            .collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(frame: u64, cpu_ms: f64, passes: &[(&'static str, f64)]) -> FrameTimings {
+        FrameTimings {
+            frame,
+            cpu_ms,
+            passes: passes.to_vec(),
+        }
+    }
+
+    #[test]
+    fn average_keeps_passes_toggled_off() {
+        let window = [
+            frame(0, 10.0, &[("geometry", 2.0), ("shadow", 1.0)]),
+            frame(1, 20.0, &[("geometry", 4.0), ("shadow", 3.0)]),
+            frame(2, 30.0, &[("geometry", 6.0)]),
+        ];
+        let averages = average(&window);
+        assert_eq!(averages.frame, 2);
+        assert_eq!(averages.cpu_ms, 20.0);
+        assert_eq!(averages.passes, vec![("geometry", 4.0), ("shadow", 2.0)]);
+    }
+
+    #[test]
+    fn average_includes_passes_toggled_on() {
+        let window = [
+            frame(0, 1.0, &[("geometry", 1.0)]),
+            frame(1, 1.0, &[("geometry", 1.0), ("ssao", 0.5)]),
+        ];
+        assert_eq!(
+            average(&window).passes,
+            vec![("geometry", 1.0), ("ssao", 0.5)]
+        );
+    }
+
+    #[test]
+    fn csv_rows_have_fixed_columns() {
+        let mut csv = Vec::new();
+        write_csv_rows(&mut csv, &frame(3, 16.5, &[("geometry", 2.0)])).unwrap();
+        write_csv_rows(
+            &mut csv,
+            &frame(4, 16.0, &[("geometry", 2.0), ("dof", 0.25)]),
+        )
+        .unwrap();
+        assert_eq!(
+            String::from_utf8(csv).unwrap(),
+            "3,cpu,16.5000\n3,geometry,2.0000\n\
+             4,cpu,16.0000\n4,geometry,2.0000\n4,dof,0.2500\n"
+        );
+    }
+}
@@ -0,0 +1,294 @@
+//! A small immediate-mode UI for tweaking parameters at runtime
+//!
+//! Widgets are laid out top to bottom in a panel on the right side of the
+//! window. A panel that reaches the bottom of the window continues in another
+//! column to the left, so every widget stays reachable. Each frame the caller opens a [`Panel`], calls one method per
+//! widget with the value it edits, and the widget both draws itself into the
+//! overlay and applies any mouse interaction to the value.
+use luminance_glfw::event::{Action, MouseButton, WindowEvent};
+
+use crate::passes::OverlayBatch;
+
+const PANEL_WIDTH: f32 = 440.0;
+const MARGIN: f32 = 8.0;
+const PADDING: f32 = 4.0;
+/// Width of the label column, in characters
+const LABEL_COLUMNS: usize = 14;
+
+const PANEL_COLOR: [f32; 4] = [0.1, 0.1, 0.12, 0.85];
+const WIDGET_COLOR: [f32; 4] = [0.25, 0.25, 0.3, 1.0];
+const HOT_COLOR: [f32; 4] = [0.35, 0.35, 0.45, 1.0];
+const ACCENT_COLOR: [f32; 4] = [0.9, 0.6, 0.2, 1.0];
+const TEXT_COLOR: [f32; 4] = [1.0, 1.0, 1.0, 1.0];
+
+pub struct Ui {
+    visible: bool,
+    cursor: [f32; 2],
+    mouse_down: bool,
+    /// Set when the mouse button went down since the last frame
+    clicked: bool,
+    /// Label of the slider being dragged
+    active: Option<String>,
+    /// Label of the combo box whose options are shown
+    open_combo: Option<String>,
+}
+
+impl Ui {
+    pub fn new() -> Self {
+        Self {
+            visible: false,
+            cursor: [0.0, 0.0],
+            mouse_down: false,
+            clicked: false,
+            active: None,
+            open_combo: None,
+        }
+    }
+
+    pub fn toggle(&mut self) {
+        self.visible = !self.visible;
+        self.active = None;
+        self.open_combo = None;
+    }
+
+    /// Feed a window event to the UI. Returns true if the UI consumed the
+    /// event, in which case it shouldn't be handled by anything else.
+    pub fn handle_event(&mut self, event: &WindowEvent) -> bool {
+        if !self.visible {
+            return false;
+        }
+
+        match *event {
+            WindowEvent::CursorPos(x, y) => {
+                self.cursor = [x as f32, y as f32];
+                true
+            }
+            WindowEvent::MouseButton(MouseButton::Button1, Action::Press, _) => {
+                self.mouse_down = true;
+                self.clicked = true;
+                true
+            }
+            WindowEvent::MouseButton(MouseButton::Button1, Action::Release, _) => {
+                self.mouse_down = false;
+                self.active = None;
+                true
+            }
+            WindowEvent::MouseButton(..) => true,
+            _ => false,
+        }
+    }
+
+    /// Start laying out this frame's widgets into `overlay`
+    pub fn panel<'a>(&'a mut self, overlay: &'a mut OverlayBatch, title: &str) -> Panel<'a> {
+        let origin = [overlay.size()[0] as f32 - PANEL_WIDTH - MARGIN, MARGIN];
+        let bottom = overlay.size()[1] as f32 - MARGIN;
+        let mut panel = Panel {
+            ui: self,
+            overlay,
+            origin,
+            top: origin[1],
+            bottom,
+        };
+        if panel.ui.visible {
+            let row = panel.row();
+            panel
+                .overlay
+                .text([row.0 + PADDING, row.1], title, ACCENT_COLOR);
+        }
+        panel
+    }
+}
+
+pub struct Panel<'a> {
+    ui: &'a mut Ui,
+    overlay: &'a mut OverlayBatch,
+    /// Top left corner of the current column
+    origin: [f32; 2],
+    /// Top of the next row
+    top: f32,
+    /// Rows past this start a new column
+    bottom: f32,
+}
+
+impl<'a> Panel<'a> {
+    /// A horizontal slider editing `value` within `range`. Returns true if the
+    /// value changed.
+    pub fn slider(&mut self, label: &str, value: &mut f32, range: (f32, f32)) -> bool {
+        if !self.ui.visible {
+            return false;
+        }
+
+        let (x, y) = self.labelled_row(label);
+        let size = self.widget_size(x);
+        let hot = self.hovered([x, y], size);
+
+        if hot && self.ui.clicked {
+            self.ui.active = Some(label.to_string());
+        }
+
+        let mut changed = false;
+        let dragging = self.ui.active.as_ref().map_or(false, |a| a == label);
+        if dragging && self.ui.mouse_down {
+            let t = ((self.ui.cursor[0] - x) / size[0]).max(0.0).min(1.0);
+            let new_value = range.0 + t * (range.1 - range.0);
+            changed = new_value != *value;
+            *value = new_value;
+        }
+
+        let t = ((*value - range.0) / (range.1 - range.0)).max(0.0).min(1.0);
+        self.overlay
+            .rect([x, y], size, if hot { HOT_COLOR } else { WIDGET_COLOR });
+        self.overlay
+            .rect([x, y], [size[0] * t, size[1]], ACCENT_COLOR);
+        self.overlay
+            .text([x + PADDING, y], &format!("{:.3}", value), TEXT_COLOR);
+
+        changed
+    }
+
+    /// A checkbox toggling `value`. Returns true if the value changed.
+    pub fn checkbox(&mut self, label: &str, value: &mut bool) -> bool {
+        if !self.ui.visible {
+            return false;
+        }
+
+        let (x, y) = self.labelled_row(label);
+        let side = OverlayBatch::glyph_size()[1];
+        let hot = self.hovered([x, y], [side, side]);
+
+        let changed = hot && self.ui.clicked;
+        if changed {
+            *value = !*value;
+        }
+
+        self.overlay.rect(
+            [x, y],
+            [side, side],
+            if hot { HOT_COLOR } else { WIDGET_COLOR },
+        );
+        if *value {
+            self.overlay.rect(
+                [x + PADDING, y + PADDING],
+                [side - 2.0 * PADDING, side - 2.0 * PADDING],
+                ACCENT_COLOR,
+            );
+        }
+
+        changed
+    }
+
+    /// A drop down selecting one of `options`. Returns true if the selection
+    /// changed.
+    pub fn combo(&mut self, label: &str, selected: &mut usize, options: &[&str]) -> bool {
+        if !self.ui.visible {
+            return false;
+        }
+
+        let (x, y) = self.labelled_row(label);
+        let size = self.widget_size(x);
+        let hot = self.hovered([x, y], size);
+        let is_open = |ui: &Ui| ui.open_combo.as_ref().map_or(false, |o| o == label);
+        let open = is_open(self.ui);
+
+        self.overlay
+            .rect([x, y], size, if hot { HOT_COLOR } else { WIDGET_COLOR });
+        self.overlay.text(
+            [x + PADDING, y],
+            &format!("{} v", options.get(*selected).unwrap_or(&"?")),
+            TEXT_COLOR,
+        );
+
+        if hot && self.ui.clicked {
+            self.ui.open_combo = if open { None } else { Some(label.to_string()) };
+            // Don't let the click that opened the list also pick an option
+            self.ui.clicked = false;
+        }
+        if !is_open(self.ui) {
+            return false;
+        }
+
+        let mut changed = false;
+        for (i, option) in options.iter().enumerate() {
+            // Lined up with the combo box, even if the list continues in the
+            // next column
+            let (x, y) = self.row();
+            let x = x + Self::label_width();
+            let hot = self.hovered([x, y], size);
+            if hot && self.ui.clicked {
+                changed = *selected != i;
+                *selected = i;
+                self.ui.open_combo = None;
+            }
+
+            let color = if i == *selected {
+                ACCENT_COLOR
+            } else if hot {
+                HOT_COLOR
+            } else {
+                WIDGET_COLOR
+            };
+            self.overlay.rect([x, y], size, color);
+            self.overlay.text([x + PADDING, y], option, TEXT_COLOR);
+        }
+
+        changed
+    }
+
+    /// Finish the frame, drawing the mouse cursor on top of the panel
+    pub fn finish(self) {
+        if self.ui.visible {
+            let cursor = self.ui.cursor;
+            self.overlay
+                .rect([cursor[0] - 1.0, cursor[1] - 6.0], [2.0, 12.0], TEXT_COLOR);
+            self.overlay
+                .rect([cursor[0] - 6.0, cursor[1] - 1.0], [12.0, 2.0], TEXT_COLOR);
+        }
+        self.ui.clicked = false;
+    }
+
+    /// Allocate the next row, drawing the panel background behind it and
+    /// moving to a new column on the left if the current one is full. Returns
+    /// the top left corner of the row.
+    fn row(&mut self) -> (f32, f32) {
+        let height = OverlayBatch::glyph_size()[1] + PADDING;
+        // A window too short for even one row still gets one per column
+        if self.top + height > self.bottom && self.top > MARGIN {
+            self.origin[0] -= PANEL_WIDTH + MARGIN;
+            self.top = MARGIN;
+        }
+        let top = self.top;
+        self.overlay
+            .rect([self.origin[0], top], [PANEL_WIDTH, height], PANEL_COLOR);
+        self.top += height;
+        (self.origin[0], top)
+    }
+
+    /// Allocate a row with `label` on the left. Returns the top left corner
+    /// of the space left for the widget.
+    fn labelled_row(&mut self, label: &str) -> (f32, f32) {
+        let (x, y) = self.row();
+        self.overlay.text([x + PADDING, y], label, TEXT_COLOR);
+        (x + Self::label_width(), y)
+    }
+
+    /// Offset from the left of a row to its widget
+    fn label_width() -> f32 {
+        PADDING + LABEL_COLUMNS as f32 * OverlayBatch::glyph_size()[0]
+    }
+
+    /// Size of a full width widget starting at `x`
+    fn widget_size(&self, x: f32) -> [f32; 2] {
+        [
+            self.origin[0] + PANEL_WIDTH - PADDING - x,
+            OverlayBatch::glyph_size()[1],
+        ]
+    }
+
+    fn hovered(&self, position: [f32; 2], size: [f32; 2]) -> bool {
+        let cursor = self.ui.cursor;
+        cursor[0] >= position[0]
+            && cursor[0] < position[0] + size[0]
+            && cursor[1] >= position[1]
+            && cursor[1] < position[1] + size[1]
+    }
+}
@@ -1,11 +1,35 @@
 layout (location = 0) in vec3 pos;
 layout (location = 1) in vec4 color;
+layout (location = 2) in vec3 normal;
+layout (location = 3) in vec2 uv;
+layout (location = 13) in vec4 tangent;
 
 uniform mat4 transform;
+uniform mat4 model;
 
 out vec4 v_color;
+out vec3 v_world_pos;
+out vec3 v_normal;
+out vec2 v_uv;
+out vec4 v_tangent;
 
 void main() {
-  gl_Position = transform * vec4(pos, 1.);
-  v_color = color;
+  vec3 local_pos = pos;
+  vec3 local_normal = normal;
+  vec3 local_tangent = tangent.xyz;
+  vec4 tint = vec4(1.0);
+  deform(local_pos, local_normal, local_tangent);
+  if (instanced) {
+    local_pos = place_instance(local_pos);
+    local_normal = rotate(instance_rotation, local_normal);
+    local_tangent = rotate(instance_rotation, local_tangent);
+    tint = instance_tint;
+  }
+
+  gl_Position = transform * vec4(local_pos, 1.);
+  v_world_pos = (model * vec4(local_pos, 1.)).xyz;
+  v_color = animate_color(color, v_world_pos) * tint;
+  v_normal = mat3(model) * local_normal;
+  v_uv = uv;
+  v_tangent = vec4(mat3(model) * local_tangent, tangent.w);
 }